@@ -0,0 +1,348 @@
+use super::{ClientBindings, NamePattern};
+use crate::graph::PortGraph;
+use crate::model::{PortData, PortFullname};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A rule connecting two ordered sets of ports channel by channel, such as every
+/// output of a synth to a pair of mixer inputs.
+///
+/// ```toml
+/// [["@bundles"]]
+/// from = "synth"
+/// to = "mixer:in_*"
+/// to_channels = "3..4"
+///
+/// [["@bundles"]]
+/// from = "mic:capture_1"
+/// to = ["reverb:in_R", "reverb:in_L"]
+/// pairing = "fan_out"
+/// ```
+///
+/// The connections a bundle makes count as listed connections, so they are forced
+/// under the same locks as connections listed on a port.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bundle {
+    /// The output ports to connect from.
+    pub from: BundleEnd,
+    /// The input ports to connect to.
+    pub to: BundleEnd,
+    /// Which of the `from` ports to use, after putting them in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_channels: Option<ChannelRange>,
+    /// Which of the `to` ports to use, after putting them in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_channels: Option<ChannelRange>,
+    #[serde(default, skip_serializing_if = "BundlePairing::is_default")]
+    pub pairing: BundlePairing,
+}
+
+/// One side of a bundle.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BundleEnd {
+    /// Every port of a client, or every port matching a port name or pattern,
+    /// in natural order: `out_2` comes before `out_10`.
+    Matching(String),
+    /// These ports, in the order listed.
+    Listed(Vec<PortFullname>),
+}
+
+/// How the ports on either side of a bundle are paired up once both are in order.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BundlePairing {
+    /// Each channel goes to the channel at the same position, leaving any extra ports
+    /// on the longer side unconnected.
+    #[default]
+    Zip,
+    /// The shorter side starts over from its first channel until every port on the
+    /// longer side is connected.
+    Wrap,
+    /// A side with a single port is connected to every port on the other side, as when
+    /// sending a mono output to both channels of a stereo input.
+    /// Otherwise, channels are paired as with `Zip`.
+    FanOut,
+}
+
+impl BundlePairing {
+    fn is_default(&self) -> bool {
+        *self == BundlePairing::default()
+    }
+}
+
+/// A run of channels in a bundle, counting from 1, written as `"3..4"` for the third
+/// and fourth channels, `"3.."` for the third onwards, or `"3"` for the third alone.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChannelRange {
+    pub first: usize,
+    /// The last channel in the run, or `None` to run to the last port.
+    pub last: Option<usize>,
+}
+
+impl ChannelRange {
+    fn select<T>(self, items: &[T]) -> &[T] {
+        let start = (self.first - 1).min(items.len());
+        let end = self.last.unwrap_or(items.len()).clamp(start, items.len());
+        &items[start..end]
+    }
+}
+
+impl TryFrom<String> for ChannelRange {
+    type Error = String;
+    fn try_from(raw: String) -> Result<Self, String> {
+        let parse_channel = |raw: &str| match raw.trim().parse::<usize>() {
+            Ok(channel) if channel > 0 => Ok(channel),
+            _ => Err(format!("invalid channel range {}", raw)),
+        };
+        let (first, last) = match raw.split_once("..") {
+            Some((first, "")) => (parse_channel(first)?, None),
+            Some((first, last)) => (parse_channel(first)?, Some(parse_channel(last)?)),
+            None => {
+                let channel = parse_channel(&raw)?;
+                (channel, Some(channel))
+            }
+        };
+        if matches!(last, Some(last) if last < first) {
+            return Err(format!("channel range {} runs backwards", raw));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+impl From<ChannelRange> for String {
+    fn from(range: ChannelRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for ChannelRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.last {
+            Some(last) if last == self.first => write!(f, "{}", self.first),
+            Some(last) => write!(f, "{}..{}", self.first, last),
+            None => write!(f, "{}..", self.first),
+        }
+    }
+}
+
+impl Bundle {
+    /// Gets the connections the bundle makes in `graph`, as `(output, input)` pairs of
+    /// port names, with client entries that have an instance mode resolved through
+    /// `bindings`.
+    pub fn connections(
+        &self,
+        graph: &PortGraph,
+        bindings: &ClientBindings,
+    ) -> Vec<(PortFullname, PortFullname)> {
+        let sources = self.from.ports(graph, bindings, true);
+        let sinks = self.to.ports(graph, bindings, false);
+        let sources = match self.from_channels {
+            Some(range) => range.select(&sources),
+            None => &sources,
+        };
+        let sinks = match self.to_channels {
+            Some(range) => range.select(&sinks),
+            None => &sinks,
+        };
+        if sources.is_empty() || sinks.is_empty() {
+            return Vec::new();
+        }
+        let count = match self.pairing {
+            BundlePairing::Wrap => sources.len().max(sinks.len()),
+            BundlePairing::FanOut if sources.len() == 1 || sinks.len() == 1 => {
+                sources.len().max(sinks.len())
+            }
+            _ => sources.len().min(sinks.len()),
+        };
+        (0..count)
+            .map(|idx| (sources[idx % sources.len()], sinks[idx % sinks.len()]))
+            .filter(|(src, dst)| src.is_compatible(dst))
+            .map(|(src, dst)| (src.name.clone(), dst.name.clone()))
+            .collect()
+    }
+}
+
+impl BundleEnd {
+    /// Gets the ports on this side of a bundle that are in `graph`, in channel order,
+    /// keeping only outputs if `outputs` is set and only inputs otherwise.
+    fn ports<'a>(
+        &self,
+        graph: &'a PortGraph,
+        bindings: &ClientBindings,
+        outputs: bool,
+    ) -> Vec<&'a PortData> {
+        let mut retvl: Vec<&PortData> = match self {
+            BundleEnd::Matching(raw) if NamePattern::is_pattern(raw) => {
+                // Patterns are checked while parsing, so they always compile here.
+                let pattern = NamePattern::new(raw).unwrap();
+                let is_port_pattern = raw.contains(':');
+                graph
+                    .all_ports()
+                    .filter(|port| {
+                        port.all_names().any(|name| {
+                            if is_port_pattern {
+                                pattern.matches(name.as_ref())
+                            } else {
+                                pattern.matches(name.client_name())
+                            }
+                        })
+                    })
+                    .collect()
+            }
+            BundleEnd::Matching(raw) => match PortFullname::new(raw.clone()) {
+                Ok(name) => bindings
+                    .bound_ports(&name)
+                    .iter()
+                    .filter_map(|name| graph.find_port(name))
+                    .collect(),
+                Err(_) => match bindings.instances(raw) {
+                    Some(clients) => clients
+                        .iter()
+                        .flat_map(|client| graph.client_ports(client))
+                        .collect(),
+                    None => graph.client_ports(raw).collect(),
+                },
+            },
+            BundleEnd::Listed(names) => names
+                .iter()
+                .flat_map(|name| bindings.bound_ports(name))
+                .filter_map(|name| graph.find_port(&name))
+                .collect(),
+        };
+        retvl.retain(|port| port.direction.is_output() == outputs);
+        if let BundleEnd::Matching(_) = self {
+            retvl.sort_by(|a, b| natural_cmp(a.name.as_ref(), b.name.as_ref()));
+        }
+        retvl
+    }
+}
+
+/// Compares two names the way a person would order them, treating each run of digits
+/// as a single number so that `in_2` comes before `in_10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        let (a_next, b_next) = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_next), Some(b_next)) => (*a_next, *b_next),
+        };
+        if a_next.is_ascii_digit() && b_next.is_ascii_digit() {
+            let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                let mut digits = String::new();
+                while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(*c);
+                    chars.next();
+                }
+                digits
+            };
+            let a_digits = take_number(&mut a_chars);
+            let b_digits = take_number(&mut b_chars);
+            let a_trimmed = a_digits.trim_start_matches('0');
+            let b_trimmed = b_digits.trim_start_matches('0');
+            let ordering = a_trimmed
+                .len()
+                .cmp(&b_trimmed.len())
+                .then_with(|| a_trimmed.cmp(b_trimmed))
+                .then_with(|| a_digits.len().cmp(&b_digits.len()));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        } else {
+            if a_next != b_next {
+                return a_next.cmp(&b_next);
+            }
+            a_chars.next();
+            b_chars.next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{PortCategory, PortDirection};
+
+    fn names(raw: &[&str]) -> Vec<PortFullname> {
+        raw.iter()
+            .map(|raw| PortFullname::new((*raw).to_owned()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_bundle_connections() {
+        assert_eq!(natural_cmp("in_2", "in_10"), Ordering::Less);
+        assert_eq!(natural_cmp("in_10", "in_9"), Ordering::Greater);
+        assert_eq!(natural_cmp("out_L", "out_R"), Ordering::Less);
+        assert_eq!(natural_cmp("in_02", "in_2"), Ordering::Greater);
+
+        let mut graph = PortGraph::new();
+        let outputs = names(&["synth:out_10", "synth:out_2", "mic:capture_1"]);
+        for name in outputs.iter() {
+            graph.add_port(PortData::new(
+                name.clone(),
+                PortCategory::Audio,
+                PortDirection::Out,
+            ));
+        }
+        let mut inputs = names(&["synth:in"]);
+        inputs.extend((1..=4).map(|idx| PortFullname::new(format!("mixer:in_{}", idx)).unwrap()));
+        for name in inputs.iter() {
+            graph.add_port(PortData::new(
+                name.clone(),
+                PortCategory::Audio,
+                PortDirection::In,
+            ));
+        }
+        let bindings = ClientBindings::default();
+
+        let mut bundle = Bundle {
+            from: BundleEnd::Matching("synth".to_owned()),
+            to: BundleEnd::Matching("mixer:in_*".to_owned()),
+            from_channels: None,
+            to_channels: Some(ChannelRange::try_from("3..4".to_owned()).unwrap()),
+            pairing: BundlePairing::Zip,
+        };
+        assert_eq!(
+            bundle.connections(&graph, &bindings),
+            vec![
+                (outputs[1].clone(), inputs[3].clone()),
+                (outputs[0].clone(), inputs[4].clone()),
+            ]
+        );
+
+        bundle.to_channels = None;
+        bundle.pairing = BundlePairing::Wrap;
+        let wrapped = bundle.connections(&graph, &bindings);
+        assert_eq!(wrapped.len(), 4);
+        assert_eq!(wrapped[2], (outputs[1].clone(), inputs[3].clone()));
+
+        let fan_out = Bundle {
+            from: BundleEnd::Matching("mic:capture_1".to_owned()),
+            to: BundleEnd::Listed(names(&["mixer:in_2", "mixer:in_1"])),
+            from_channels: None,
+            to_channels: None,
+            pairing: BundlePairing::FanOut,
+        };
+        assert_eq!(
+            fan_out.connections(&graph, &bindings),
+            vec![
+                (outputs[2].clone(), inputs[2].clone()),
+                (outputs[2].clone(), inputs[1].clone()),
+            ]
+        );
+
+        assert!(ChannelRange::try_from("4..3".to_owned()).is_err());
+        assert!(ChannelRange::try_from("0".to_owned()).is_err());
+        let open = ChannelRange::try_from("2..".to_owned()).unwrap();
+        assert_eq!(open.select(&[1, 2, 3]), &[2, 3]);
+        assert_eq!(open.to_string(), "2..");
+    }
+}
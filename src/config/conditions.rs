@@ -0,0 +1,100 @@
+use super::{LockRules, NamePattern};
+use crate::graph::PortGraph;
+
+/// A test on which clients and ports are in the graph.
+///
+/// Names containing `:` are checked against the names of ports, and the rest against the
+/// names of clients; both can be globs or regexes, as with config keys.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Condition {
+    /// Names, each of which must match at least one client or port.
+    when_present: Vec<NamePattern>,
+    /// Names, none of which may match any client or port.
+    when_absent: Vec<NamePattern>,
+}
+
+impl Condition {
+    /// Makes a condition from the names listed under `when_present` and `when_absent`.
+    pub fn new(when_present: &[String], when_absent: &[String]) -> Result<Self, regex::Error> {
+        let to_patterns = |names: &[String]| -> Result<Vec<_>, regex::Error> {
+            names.iter().map(|name| NamePattern::new(name)).collect()
+        };
+        Ok(Self {
+            when_present: to_patterns(when_present)?,
+            when_absent: to_patterns(when_absent)?,
+        })
+    }
+    /// Gets the names listed under `when_present`.
+    pub fn when_present(&self) -> impl Iterator<Item = &str> {
+        self.when_present.iter().map(|pattern| pattern.raw())
+    }
+    /// Gets the names listed under `when_absent`.
+    pub fn when_absent(&self) -> impl Iterator<Item = &str> {
+        self.when_absent.iter().map(|pattern| pattern.raw())
+    }
+    /// Checks whether the condition holds in `graph`.
+    pub fn holds(&self, graph: &PortGraph) -> bool {
+        self.when_present
+            .iter()
+            .all(|pattern| is_present(pattern, graph))
+            && !self
+                .when_absent
+                .iter()
+                .any(|pattern| is_present(pattern, graph))
+    }
+}
+
+/// Checks whether any client or port in `graph` matches `pattern`.
+fn is_present(pattern: &NamePattern, graph: &PortGraph) -> bool {
+    if pattern.raw().contains(':') {
+        graph
+            .all_ports()
+            .any(|port| port.all_names().any(|name| pattern.matches(name.as_ref())))
+    } else {
+        graph.all_clients().any(|client| pattern.matches(client))
+    }
+}
+
+/// A set of rules that only applies while its condition holds.
+///
+/// ```toml
+/// ["@conditional".reverb_send]
+/// when_absent = ["looper"]
+/// ["@conditional".reverb_send."system:capture_1"]
+/// lock = "force"
+/// connections = ["reverb:in"]
+/// ```
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ConditionalRules {
+    pub condition: Condition,
+    pub rules: LockRules,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PortDirection;
+    use crate::testing::port;
+
+    #[test]
+    fn test_conditions() {
+        let mut graph = PortGraph::new();
+        for raw in ["looper:in", "usb_audio_2:capture_1"].iter() {
+            graph.add_port(port(raw, PortDirection::In));
+        }
+        let names = |raw: &[&str]| -> Vec<String> { raw.iter().map(|s| (*s).to_owned()).collect() };
+
+        let no_looper = Condition::new(&[], &names(&["looper"])).unwrap();
+        assert!(!no_looper.holds(&graph));
+        let usb = Condition::new(&names(&["usb_audio*"]), &names(&["reverb"])).unwrap();
+        assert!(usb.holds(&graph));
+        let usb_port = Condition::new(&names(&["usb_audio_?:capture_1"]), &[]).unwrap();
+        assert!(usb_port.holds(&graph));
+        let both = Condition::new(&names(&["looper", "reverb"]), &[]).unwrap();
+        assert!(!both.holds(&graph));
+        assert!(Condition::default().holds(&graph));
+
+        assert_eq!(usb.when_present().collect::<Vec<_>>(), vec!["usb_audio*"]);
+        assert!(Condition::new(&names(&["/looper(/"]), &[]).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use super::{parsing, LockConfig};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// The name of the directories, within the system and user config directories, whose
+/// `*.toml` snippets are merged into every config.
+const CONF_DIR_NAME: &str = "conf.d";
+
+#[derive(Debug, Error)]
+pub enum ConfigLoadError {
+    #[error("Failed to read config file \"{}\": {}", .path.display(), .source)]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Failed to parse config file \"{}\": {}", .path.display(), .source)]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Config file \"{}\" includes itself.", .0.display())]
+    IncludeCycle(PathBuf),
+}
+
+/// A config put together from several files, along with every file and directory it
+/// was read from.
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: LockConfig,
+    /// The conf.d directories that exist and the files read, in the order they were merged.
+    pub sources: Vec<PathBuf>,
+}
+
+/// Gets the directories whose snippets are merged into every config: the system-wide
+/// `/etc/trej/conf.d`, then the user's `$XDG_CONFIG_HOME/trej/conf.d`, which defaults to
+/// `~/.config/trej/conf.d`.
+pub fn default_conf_dirs() -> Vec<PathBuf> {
+    let mut retvl = vec![Path::new("/etc/trej").join(CONF_DIR_NAME)];
+    let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")));
+    if let Some(dir) = user_dir {
+        retvl.push(dir.join("trej").join(CONF_DIR_NAME));
+    }
+    retvl
+}
+
+/// Loads the config file at `path` along with everything merged into it.
+///
+/// The `*.toml` files in each of `conf_dirs` are merged first, a directory at a time and
+/// in file name order within each, followed by `path` itself.
+/// The files listed under a file's `include` key, relative to that file, are merged in
+/// just before it, so a file overrides everything it includes; see
+/// `ConfigFile::merge` for how rules from later files override earlier ones.
+/// A file included more than once is only merged in the first time.
+///
+/// ```toml
+/// include = ["gear/looper.toml"]
+///
+/// [system]
+/// lock = "full"
+/// ```
+pub fn load_config(path: &Path, conf_dirs: &[PathBuf]) -> Result<LoadedConfig, ConfigLoadError> {
+    let loader = Loader::load_all(path, conf_dirs)?;
+    Ok(LoadedConfig {
+        config: LockConfig::from(loader.file),
+        sources: loader.sources,
+    })
+}
+
+/// Loads the config file at `path` along with everything merged into it, the same way
+/// as `load_config`, but returns the merged file rather than the rules read from it.
+pub(super) fn load_config_file(
+    path: &Path,
+    conf_dirs: &[PathBuf],
+) -> Result<parsing::ConfigFile, ConfigLoadError> {
+    Ok(Loader::load_all(path, conf_dirs)?.file)
+}
+
+#[derive(Default)]
+struct Loader {
+    /// The contents of every file merged so far.
+    file: parsing::ConfigFile,
+    sources: Vec<PathBuf>,
+    /// The files currently being loaded, each included by the one before it.
+    including: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// Merges the snippets in `conf_dirs`, then the file at `path`.
+    fn load_all(path: &Path, conf_dirs: &[PathBuf]) -> Result<Self, ConfigLoadError> {
+        let mut loader = Self::default();
+        for dir in conf_dirs {
+            let read_err = |source| ConfigLoadError::Read {
+                path: dir.clone(),
+                source,
+            };
+            let mut snippets: Vec<PathBuf> = match fs::read_dir(dir) {
+                Ok(ents) => ents
+                    .map(|ent| Ok(ent?.path()))
+                    .collect::<Result<Vec<_>, io::Error>>()
+                    .map_err(read_err)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(read_err(e)),
+            };
+            snippets.retain(|snippet| {
+                snippet.is_file()
+                    && snippet.extension().and_then(|ext| ext.to_str()) == Some("toml")
+            });
+            snippets.sort();
+            loader.sources.push(dir.clone());
+            for snippet in snippets {
+                loader.load(&snippet)?;
+            }
+        }
+        loader.load(path)?;
+        Ok(loader)
+    }
+    fn load(&mut self, path: &Path) -> Result<(), ConfigLoadError> {
+        let read_err = |source| ConfigLoadError::Read {
+            path: path.to_owned(),
+            source,
+        };
+        let canonical = path.canonicalize().map_err(read_err)?;
+        if self.including.contains(&canonical) {
+            return Err(ConfigLoadError::IncludeCycle(path.to_owned()));
+        }
+        if self.sources.contains(&canonical) {
+            return Ok(());
+        }
+        let raw = fs::read_to_string(path).map_err(read_err)?;
+        let mut file: parsing::ConfigFile =
+            toml::from_str(&raw).map_err(|source| ConfigLoadError::Parse {
+                path: path.to_owned(),
+                source,
+            })?;
+        file.set_file(path);
+        self.sources.push(canonical.clone());
+        self.including.push(canonical);
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for include in std::mem::take(&mut file.includes) {
+            self.load(&base.join(include))?;
+        }
+        self.including.pop();
+        self.file.merge(file);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LockStatus;
+    use crate::model::PortDirection;
+    use crate::testing::{port, TempDir};
+
+    #[test]
+    fn test_load_config() {
+        let tmp = TempDir::new("load_config");
+        let root = tmp.path();
+        let conf_dir = root.join(CONF_DIR_NAME);
+        fs::create_dir_all(conf_dir.join("ignored")).unwrap();
+        fs::create_dir_all(root.join("gear")).unwrap();
+        let files = [
+            (
+                "conf.d/10-looper.toml",
+                "[looper]\nlock = \"block\"\n[looper.in]\nconnections = [\"system:capture_1\"]\n",
+            ),
+            ("conf.d/20-looper.toml", "[looper]\nlock = \"force\"\n"),
+            ("conf.d/notes.txt", "not a config"),
+            (
+                "gear/reverb.toml",
+                "\"@active_profile\" = \"live\"\n[reverb]\nlock = \"full\"\n",
+            ),
+            (
+                "main.toml",
+                "include = [\"gear/reverb.toml\", \"gear/reverb.toml\"]\n\
+                 [looper.in]\nconnections = [\"system:capture_2\"]\n\
+                 [\"@profiles\".live.reverb]\nlock = \"none\"\n",
+            ),
+            ("loop_a.toml", "include = [\"loop_b.toml\"]\n"),
+            ("loop_b.toml", "include = [\"loop_a.toml\"]\n"),
+        ];
+        for (name, contents) in files.iter() {
+            fs::write(root.join(name), contents).unwrap();
+        }
+
+        let missing_dir = root.join("missing");
+        let conf_dirs = vec![conf_dir.clone(), missing_dir];
+        let loaded = load_config(&root.join("main.toml"), &conf_dirs).unwrap();
+        let canonical = |name: &str| root.join(name).canonicalize().unwrap();
+        assert_eq!(
+            loaded.sources,
+            vec![
+                conf_dir.clone(),
+                canonical("conf.d/10-looper.toml"),
+                canonical("conf.d/20-looper.toml"),
+                canonical("main.toml"),
+                canonical("gear/reverb.toml"),
+            ]
+        );
+        let config = loaded.config;
+        // The later snippet replaces the lock, but the connections from every file add up.
+        assert_eq!(config.client_status("looper"), LockStatus::Force);
+        assert_eq!(
+            config.client_lock_source("looper").unwrap().to_string(),
+            format!("[looper] in {}", conf_dir.join("20-looper.toml").display())
+        );
+        let looper_in = port("looper:in", PortDirection::In);
+        for capture in ["system:capture_1", "system:capture_2"].iter() {
+            let capture = port(capture, PortDirection::Out);
+            assert_eq!(
+                config.connection_status(&looper_in, &capture),
+                LockStatus::Force
+            );
+        }
+        assert_eq!(config.active_profile(), Some("live"));
+        assert_eq!(config.client_status("reverb"), LockStatus::None);
+
+        let cycle = load_config(&root.join("loop_a.toml"), &[]);
+        assert!(matches!(cycle, Err(ConfigLoadError::IncludeCycle(_))));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::graph::PortGraph;
+use crate::model::PortFullname;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Which running instances of a client a config entry applies to, when the client has
+/// been started more than once.
+///
+/// JACK renames every instance after the first by adding a numbered suffix, so a second
+/// `synth` shows up as `synth-01`.
+/// Entries without an instance mode only ever apply to the client with the exact name.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstanceMode {
+    /// The lowest-numbered instance that is running, counting the unsuffixed name first.
+    First,
+    /// A single instance, which stays bound for as long as it runs even if a lower-numbered
+    /// instance starts.
+    Any,
+    /// Every running instance.
+    All,
+}
+
+impl fmt::Display for InstanceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InstanceMode::First => "first",
+            InstanceMode::Any => "any",
+            InstanceMode::All => "all",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Gets which instance of the client `entry` the client called `client` is, where the
+/// unsuffixed name is instance 0 and `entry-01` is instance 1.
+pub fn instance_number(entry: &str, client: &str) -> Option<u32> {
+    if client == entry {
+        return Some(0);
+    }
+    let suffix = client.strip_prefix(entry)?.strip_prefix('-')?;
+    if suffix.is_empty() || !suffix.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    suffix.parse().ok()
+}
+
+/// The running clients bound to each config entry that has an instance mode.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ClientBindings {
+    /// The config entry each bound client stands in for.
+    entries: HashMap<String, String>,
+    /// The clients bound to each config entry with an instance mode, lowest-numbered first.
+    instances: BTreeMap<String, Vec<String>>,
+}
+
+impl ClientBindings {
+    /// Binds each config entry in `modes` to instances of its client running in `graph`.
+    /// Clients in `named`, the clients the config names directly, are never bound to
+    /// another entry, since their own entries apply to them instead.
+    /// Entries in `Any` mode keep whichever client they were bound to in `previous` if it
+    /// is still running.
+    pub fn bind(
+        modes: &HashMap<String, InstanceMode>,
+        named: &HashSet<&str>,
+        graph: &PortGraph,
+        previous: &ClientBindings,
+    ) -> Self {
+        let mut retvl = ClientBindings::default();
+        for (entry, mode) in modes.iter() {
+            let mut running: Vec<_> = graph
+                .all_clients()
+                // A client with its own entry is never bound to another one.
+                .filter(|client| *client == entry || !named.contains(client))
+                .filter_map(|client| Some((instance_number(entry, client)?, client)))
+                .collect();
+            running.sort_unstable();
+            let kept = previous
+                .instances
+                .get(entry)
+                .and_then(|old| old.first())
+                .filter(|old| running.iter().any(|(_, client)| client == old));
+            let bound: Vec<String> = match (mode, kept) {
+                (InstanceMode::All, _) => running
+                    .iter()
+                    .map(|(_, client)| (*client).to_owned())
+                    .collect(),
+                (InstanceMode::Any, Some(old)) => vec![old.clone()],
+                (InstanceMode::Any, None) | (InstanceMode::First, _) => running
+                    .first()
+                    .map(|(_, client)| (*client).to_owned())
+                    .into_iter()
+                    .collect(),
+            };
+            for client in bound.iter() {
+                retvl.entries.insert(client.clone(), entry.clone());
+            }
+            retvl.instances.insert(entry.clone(), bound);
+        }
+        retvl
+    }
+    /// Gets the config entry whose rules apply to `client`, or `None` if its own entry has
+    /// been bound to other instances instead.
+    pub fn entry_for<'a>(&'a self, client: &'a str) -> Option<&'a str> {
+        match self.entries.get(client) {
+            Some(entry) => Some(entry),
+            None if self.instances.contains_key(client) => None,
+            None => Some(client),
+        }
+    }
+    /// Gets the name of the port `port` stands in for in the config; see `entry_for`.
+    pub fn entry_port<'a>(&self, port: &'a PortFullname) -> Option<Cow<'a, PortFullname>> {
+        let client = port.client_name();
+        match self.entry_for(client)? {
+            entry if entry == client => Some(Cow::Borrowed(port)),
+            entry => {
+                let raw = format!("{}:{}", entry, port.port_shortname());
+                Some(Cow::Owned(PortFullname::try_from(raw).unwrap()))
+            }
+        }
+    }
+    /// Gets the running ports a port named in the config applies to.
+    pub fn bound_ports(&self, port: &PortFullname) -> Vec<PortFullname> {
+        match self.instances.get(port.client_name()) {
+            Some(clients) => clients
+                .iter()
+                .map(|client| format!("{}:{}", client, port.port_shortname()))
+                .filter_map(|raw| PortFullname::try_from(raw).ok())
+                .collect(),
+            None => vec![port.clone()],
+        }
+    }
+    /// Gets the clients bound to the config entry `entry`, if it has an instance mode.
+    pub fn instances(&self, entry: &str) -> Option<&[String]> {
+        self.instances.get(entry).map(|clients| clients.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PortDirection;
+    use crate::testing::port;
+
+    fn graph_with_clients(clients: &[&str]) -> PortGraph {
+        let mut graph = PortGraph::new();
+        for client in clients.iter() {
+            graph.add_port(port(&format!("{}:out", client), PortDirection::Out));
+        }
+        graph
+    }
+
+    #[test]
+    fn test_bind_instances() {
+        assert_eq!(instance_number("synth", "synth"), Some(0));
+        assert_eq!(instance_number("synth", "synth-02"), Some(2));
+        assert_eq!(instance_number("synth", "synthesizer"), None);
+        assert_eq!(instance_number("synth", "synth-"), None);
+
+        let mut modes = HashMap::new();
+        modes.insert("synth".to_owned(), InstanceMode::Any);
+        modes.insert("drums".to_owned(), InstanceMode::First);
+        modes.insert("looper".to_owned(), InstanceMode::All);
+
+        // The first instance of each crashed and came back with a suffix.
+        let graph = graph_with_clients(&["synth-01", "drums-01", "drums-02", "looper-01"]);
+        let named: HashSet<_> = modes.keys().map(|name| name.as_str()).collect();
+        let bindings = ClientBindings::bind(&modes, &named, &graph, &ClientBindings::default());
+        assert_eq!(bindings.entry_for("synth-01"), Some("synth"));
+        assert_eq!(bindings.entry_for("drums-01"), Some("drums"));
+        assert_eq!(bindings.entry_for("drums-02"), Some("drums-02"));
+        assert_eq!(bindings.entry_for("looper-01"), Some("looper"));
+
+        // Then they were started again under their original names.
+        let graph = graph_with_clients(&[
+            "synth",
+            "synth-01",
+            "drums",
+            "drums-01",
+            "drums-02",
+            "looper",
+            "looper-01",
+        ]);
+        let bindings = ClientBindings::bind(&modes, &named, &graph, &bindings);
+        assert_eq!(bindings.entry_for("synth-01"), Some("synth"));
+        assert_eq!(bindings.entry_for("synth"), None);
+        assert_eq!(bindings.entry_for("drums"), Some("drums"));
+        assert_eq!(bindings.entry_for("drums-01"), Some("drums-01"));
+        assert_eq!(
+            bindings.instances("looper"),
+            Some(&["looper".to_owned(), "looper-01".to_owned()][..])
+        );
+
+        let synth_out = PortFullname::new("synth:out".to_owned()).unwrap();
+        let bound_out = PortFullname::new("synth-01:out".to_owned()).unwrap();
+        assert_eq!(bindings.bound_ports(&synth_out), vec![bound_out.clone()]);
+        assert_eq!(bindings.entry_port(&bound_out).as_deref(), Some(&synth_out));
+        assert_eq!(bindings.entry_port(&synth_out), None);
+
+        // A suffixed client with its own plain entry keeps it.
+        let mut named = named;
+        named.insert("drums-01");
+        let graph = graph_with_clients(&["drums-01", "drums-02"]);
+        let bindings = ClientBindings::bind(&modes, &named, &graph, &ClientBindings::default());
+        assert_eq!(bindings.entry_for("drums-01"), Some("drums-01"));
+        assert_eq!(bindings.entry_for("drums-02"), Some("drums"));
+    }
+}
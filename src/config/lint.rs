@@ -0,0 +1,572 @@
+use super::{
+    includes, instance_number, parsing, ConfigLoadError, LockConfig, NamePattern, RuleConflict,
+    RuleLayer, RuleSource,
+};
+use crate::graph::PortGraph;
+use crate::model::{PortData, PortDirection, PortFullname};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Something in a config file that is most likely a mistake, even though the file loads.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigProblem {
+    /// A name containing `:` that can't be the name of a port.
+    InvalidName {
+        name: String,
+        source: RuleSource,
+        reason: String,
+    },
+    /// Two tables in the same part of the same file both configure one port.
+    DuplicateEntry {
+        name: String,
+        first: RuleSource,
+        second: RuleSource,
+    },
+    /// A port lists a connection to another port, which doesn't list one back.
+    OneSidedConnection {
+        source: RuleSource,
+        other: String,
+    },
+    /// A port lists a connection to itself.
+    SelfConnection {
+        source: RuleSource,
+    },
+    /// The listed connections form a loop through an odd number of ports, so at least one
+    /// of them joins two inputs or two outputs.
+    OddConnectionLoop {
+        ports: (String, String),
+    },
+    /// A listed connection joins two ports in the graph that go the same way.
+    SameDirection {
+        source: RuleSource,
+        other: String,
+        direction: PortDirection,
+    },
+    /// A client, port or pattern named in the config that matches nothing in the graph.
+    Missing {
+        name: String,
+        source: RuleSource,
+    },
+    Conflict(RuleConflict),
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::InvalidName {
+                name,
+                source,
+                reason,
+            } => write!(f, "{} names the invalid port {}: {}", source, name, reason),
+            ConfigProblem::DuplicateEntry {
+                name,
+                first,
+                second,
+            } => write!(f, "{} is configured by both {} and {}", name, first, second),
+            ConfigProblem::OneSidedConnection { source, other } => write!(
+                f,
+                "{} lists a connection to {}, but {} doesn't list one back",
+                source, other, other
+            ),
+            ConfigProblem::SelfConnection { source } => {
+                write!(f, "{} lists a connection to itself", source)
+            }
+            ConfigProblem::OddConnectionLoop { ports: (a, b) } => write!(
+                f,
+                "the connection between {} and {} closes a loop through an odd number of ports, \
+                 so some connection in it joins two inputs or two outputs",
+                a, b
+            ),
+            ConfigProblem::SameDirection {
+                source,
+                other,
+                direction,
+            } => {
+                let kind = if direction.is_input() {
+                    "inputs"
+                } else {
+                    "outputs"
+                };
+                write!(
+                    f,
+                    "{} lists a connection to {}, but both are {}",
+                    source, other, kind
+                )
+            }
+            ConfigProblem::Missing { name, source } => {
+                write!(
+                    f,
+                    "{} names {}, which matches nothing in the graph",
+                    source, name
+                )
+            }
+            ConfigProblem::Conflict(conflict) => conflict.fmt(f),
+        }
+    }
+}
+
+/// A table in a config file configuring a single port or port pattern, either written
+/// out in full or nested within the table of its client.
+struct PortTable<'a> {
+    name: String,
+    source: RuleSource,
+    info: &'a parsing::PortInfo,
+}
+
+/// A config read in to be checked for mistakes, rather than to be enforced.
+pub struct ConfigLint {
+    file: parsing::ConfigFile,
+    config: LockConfig,
+}
+
+impl ConfigLint {
+    /// Parses `raw` as a config file on its own, failing on anything that stops it from
+    /// loading.
+    #[cfg(test)]
+    pub fn parse(raw: &str) -> Result<Self, toml::de::Error> {
+        Ok(Self {
+            file: toml::from_str(raw)?,
+            config: toml::from_str(raw)?,
+        })
+    }
+    /// Loads the config file at `path` along with everything merged into it, as
+    /// `load_config` does, so that the whole config the daemon would enforce is checked.
+    pub fn load(path: &Path, conf_dirs: &[PathBuf]) -> Result<Self, ConfigLoadError> {
+        let file = includes::load_config_file(path, conf_dirs)?;
+        Ok(Self {
+            config: LockConfig::from(file.clone()),
+            file,
+        })
+    }
+    /// Finds the problems that show up in the config on its own.
+    /// A port configured in several files isn't a problem, as later files are meant to
+    /// override earlier ones.
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        let mut retvl = Vec::new();
+        for (layer, entries) in self.layers() {
+            for ent in entries.iter() {
+                if let parsing::LockEntry::Client { name, info } = ent {
+                    // Keys that aren't valid port names are read as client names instead.
+                    if let Some(reason) = name_error(name) {
+                        retvl.push(ConfigProblem::InvalidName {
+                            name: name.clone(),
+                            source: source_at(&layer, &[name], info.file.as_deref()),
+                            reason,
+                        });
+                    }
+                }
+            }
+        }
+        let tables = self.port_tables();
+        let mut first_tables: HashMap<(&RuleLayer, Option<&Path>, &str), &RuleSource> =
+            HashMap::new();
+        for table in tables.iter() {
+            let key = (
+                &table.source.layer,
+                table.source.file.as_deref(),
+                table.name.as_str(),
+            );
+            match first_tables.get(&key) {
+                Some(first) => retvl.push(ConfigProblem::DuplicateEntry {
+                    name: table.name.clone(),
+                    first: (*first).clone(),
+                    second: table.source.clone(),
+                }),
+                None => {
+                    first_tables.insert(key, &table.source);
+                }
+            }
+            if let Some(reason) = name_error(&table.name) {
+                retvl.push(ConfigProblem::InvalidName {
+                    name: table.name.clone(),
+                    source: table.source.clone(),
+                    reason,
+                });
+            }
+        }
+        for (table, other) in connections(&tables) {
+            if other == table.name {
+                retvl.push(ConfigProblem::SelfConnection {
+                    source: table.source.clone(),
+                });
+                continue;
+            }
+            if let Some(reason) = name_error(other) {
+                retvl.push(ConfigProblem::InvalidName {
+                    name: other.to_owned(),
+                    source: table.source.clone(),
+                    reason,
+                });
+            }
+            let lists_back = tables.iter().any(|back| {
+                back.source.layer == table.source.layer
+                    && back.name == other
+                    && back
+                        .info
+                        .connections
+                        .iter()
+                        .any(|c| c.as_ref() == table.name)
+            });
+            if !lists_back && !is_pattern_pair(&table.name, other) {
+                retvl.push(ConfigProblem::OneSidedConnection {
+                    source: table.source.clone(),
+                    other: other.to_owned(),
+                });
+            }
+        }
+        retvl.extend(odd_loops(&tables));
+        retvl.extend(
+            self.config
+                .conflicts()
+                .iter()
+                .cloned()
+                .map(ConfigProblem::Conflict),
+        );
+        retvl
+    }
+    /// Finds the problems that show up when comparing the config file to `graph`:
+    /// names that match nothing in it, and listed connections between two of its inputs
+    /// or two of its outputs.
+    pub fn graph_problems(&self, graph: &PortGraph) -> Vec<ConfigProblem> {
+        let instanced: HashSet<&str> = self
+            .layers()
+            .into_iter()
+            .flat_map(|(_, entries)| entries.iter())
+            .filter_map(|ent| match ent {
+                parsing::LockEntry::Client { name, info } if info.instance.is_some() => {
+                    Some(name.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        let mut retvl = Vec::new();
+        for (layer, entries) in self.layers() {
+            for ent in entries.iter() {
+                if let parsing::LockEntry::Client { name, info } = ent {
+                    if !client_exists(name, &instanced, graph) {
+                        retvl.push(ConfigProblem::Missing {
+                            name: name.clone(),
+                            source: source_at(&layer, &[name], info.file.as_deref()),
+                        });
+                    }
+                }
+            }
+        }
+        let tables = self.port_tables();
+        let mut checked = BTreeSet::new();
+        for table in tables.iter() {
+            let client_found = table.source.path.len() == 1
+                || client_exists(&table.source.path[0], &instanced, graph);
+            if client_found && matching_ports(&table.name, &instanced, graph).is_empty() {
+                retvl.push(ConfigProblem::Missing {
+                    name: table.name.clone(),
+                    source: table.source.clone(),
+                });
+            }
+        }
+        for (table, other) in connections(&tables) {
+            if other == table.name {
+                continue;
+            }
+            let other_ports = matching_ports(other, &instanced, graph);
+            if other_ports.is_empty() {
+                retvl.push(ConfigProblem::Missing {
+                    name: other.to_owned(),
+                    source: table.source.clone(),
+                });
+                continue;
+            }
+            // Connections listed from both sides are only checked once.
+            let pair = if table.name.as_str() < other {
+                (table.name.as_str(), other)
+            } else {
+                (other, table.name.as_str())
+            };
+            if is_pattern_pair(&table.name, other) || !checked.insert(pair) {
+                continue;
+            }
+            let own_ports = matching_ports(&table.name, &instanced, graph);
+            if let (Some(own), Some(peer)) = (own_ports.first(), other_ports.first()) {
+                if own.direction == peer.direction {
+                    retvl.push(ConfigProblem::SameDirection {
+                        source: table.source.clone(),
+                        other: other.to_owned(),
+                        direction: own.direction,
+                    });
+                }
+            }
+        }
+        retvl
+    }
+    /// Gets the entries of the shared rules, then of each profile and conditional rule set.
+    fn layers(&self) -> Vec<(RuleLayer, &[parsing::LockEntry])> {
+        let profiles =
+            self.file.profiles.iter().map(|(name, profile)| {
+                (RuleLayer::Profile(name.clone()), profile.entries.as_slice())
+            });
+        let conditional = self.file.conditional.iter().map(|(name, conditional)| {
+            (
+                RuleLayer::Conditional(name.clone()),
+                conditional.entries.as_slice(),
+            )
+        });
+        std::iter::once((RuleLayer::Shared, self.file.entries.as_slice()))
+            .chain(profiles)
+            .chain(conditional)
+            .collect()
+    }
+    fn port_tables(&self) -> Vec<PortTable<'_>> {
+        let mut retvl = Vec::new();
+        for (layer, entries) in self.layers() {
+            for ent in entries.iter() {
+                match ent {
+                    parsing::LockEntry::Port { name, info } => retvl.push(PortTable {
+                        name: name.as_ref().to_owned(),
+                        source: source_at(&layer, &[name.as_ref()], info.file.as_deref()),
+                        info,
+                    }),
+                    parsing::LockEntry::Client { name, info } => {
+                        for (shortname, info) in info.ports.iter() {
+                            retvl.push(PortTable {
+                                name: NamePattern::join(name, shortname),
+                                source: source_at(&layer, &[name, shortname], info.file.as_deref()),
+                                info,
+                            });
+                        }
+                    }
+                    parsing::LockEntry::Class { .. } | parsing::LockEntry::Bundle(_) => {}
+                }
+            }
+        }
+        retvl
+    }
+}
+
+fn source_at(layer: &RuleLayer, path: &[&str], file: Option<&Path>) -> RuleSource {
+    RuleSource {
+        layer: layer.clone(),
+        path: path.iter().map(|key| (*key).to_owned()).collect(),
+        file: file.map(Path::to_owned),
+    }
+}
+
+/// Gets every connection listed in `tables`, along with the table listing it.
+fn connections<'a, 'b>(tables: &'b [PortTable<'a>]) -> Vec<(&'b PortTable<'a>, &'b str)> {
+    tables
+        .iter()
+        .flat_map(|table| {
+            table
+                .info
+                .connections
+                .iter()
+                .map(move |other| (table, other.as_ref()))
+        })
+        .collect()
+}
+
+fn is_pattern_pair(a: &str, b: &str) -> bool {
+    NamePattern::is_pattern(a) || NamePattern::is_pattern(b)
+}
+
+/// Explains why `raw`, if it is a plain name containing `:`, isn't a valid port name.
+fn name_error(raw: &str) -> Option<String> {
+    if !raw.contains(':') || NamePattern::is_pattern(raw) {
+        return None;
+    }
+    match PortFullname::new(raw.to_owned()) {
+        Err(e) => Some(e.to_string()),
+        Ok(name) if name.client_name().is_empty() => Some("the client name is empty".to_owned()),
+        Ok(name) if name.port_shortname().is_empty() => Some("the port name is empty".to_owned()),
+        Ok(_) => None,
+    }
+}
+
+/// Finds the loops through an odd number of ports in the connections listed between
+/// plainly named ports, reporting one connection closing such a loop for each group of
+/// connected ports that has one.
+fn odd_loops(tables: &[PortTable<'_>]) -> Vec<ConfigProblem> {
+    let mut peers: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (table, other) in connections(tables) {
+        if other == table.name || is_pattern_pair(&table.name, other) {
+            continue;
+        }
+        peers.entry(&table.name).or_default().insert(other);
+        peers.entry(other).or_default().insert(&table.name);
+    }
+    // Every connection joins an output to an input, so the ports can only be split into
+    // the two sides if no loop goes through an odd number of them.
+    let mut sides: HashMap<&str, bool> = HashMap::new();
+    let mut retvl = Vec::new();
+    for start in peers.keys() {
+        if sides.contains_key(start) {
+            continue;
+        }
+        sides.insert(start, false);
+        let mut found = None;
+        let mut queue: VecDeque<&str> = VecDeque::new();
+        queue.push_back(start);
+        while let Some(port) = queue.pop_front() {
+            let side = sides[port];
+            for peer in peers[port].iter() {
+                match sides.get(peer) {
+                    Some(peer_side) if *peer_side == side && found.is_none() => {
+                        found = Some((port.to_owned(), (*peer).to_owned()));
+                    }
+                    Some(_) => {}
+                    None => {
+                        sides.insert(peer, !side);
+                        queue.push_back(peer);
+                    }
+                }
+            }
+        }
+        if let Some(ports) = found {
+            retvl.push(ConfigProblem::OddConnectionLoop { ports });
+        }
+    }
+    retvl
+}
+
+/// Checks whether a client entry named `raw` applies to any client in `graph`.
+fn client_exists(raw: &str, instanced: &HashSet<&str>, graph: &PortGraph) -> bool {
+    if NamePattern::is_pattern(raw) {
+        // Patterns are checked while parsing, so they always compile here.
+        let pattern = NamePattern::new(raw).unwrap();
+        return graph.all_clients().any(|client| pattern.matches(client));
+    }
+    graph.all_clients().any(|client| {
+        client == raw || (instanced.contains(raw) && instance_number(raw, client).is_some())
+    })
+}
+
+/// Gets the ports in `graph` that a port named `raw` in the config applies to.
+fn matching_ports<'a>(
+    raw: &str,
+    instanced: &HashSet<&str>,
+    graph: &'a PortGraph,
+) -> Vec<&'a PortData> {
+    if NamePattern::is_pattern(raw) {
+        let pattern = NamePattern::new(raw).unwrap();
+        return graph
+            .all_ports()
+            .filter(|port| port.all_names().any(|name| pattern.matches(name.as_ref())))
+            .collect();
+    }
+    let name = match PortFullname::new(raw.to_owned()) {
+        Ok(name) => name,
+        Err(_) => return Vec::new(),
+    };
+    let entry = name.client_name();
+    graph
+        .all_ports()
+        .filter(|port| {
+            let is_instance = instanced.contains(entry)
+                && instance_number(entry, port.name.client_name()).is_some()
+                && port.name.port_shortname() == name.port_shortname();
+            is_instance || port.all_names().any(|other| *other == name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{port, TempDir};
+    use std::fs;
+
+    #[test]
+    fn test_config_lint() {
+        let raw_conf = r#"
+            ["synth:out"]
+            connections = ["system:playback_1", "synth:out"]
+            [synth.out]
+            lock = "full"
+
+            ["system:playback_1"]
+            connections = ["synth:out", "looper:in"]
+            ["looper:in"]
+            connections = ["system:playback_1", "synth:out"]
+
+            [":in"]
+            lock = "block"
+
+            ["@profiles".live.reverb.in]
+            connections = ["system:capture_1", "/drum.*:out/"]
+        "#;
+        let lint = ConfigLint::parse(raw_conf).unwrap();
+        let problems: Vec<_> = lint.problems().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                r#"synth:out is configured by both ["synth:out"] and [synth.out]"#,
+                r#"[":in"] names the invalid port :in: the client name is empty"#,
+                r#"["synth:out"] lists a connection to itself"#,
+                r#"["looper:in"] lists a connection to synth:out, but synth:out doesn't list one back"#,
+                r#"["@profiles".live.reverb.in] lists a connection to system:capture_1, but system:capture_1 doesn't list one back"#,
+                "the connection between synth:out and system:playback_1 closes a loop through an odd number of ports, \
+                 so some connection in it joins two inputs or two outputs",
+            ]
+        );
+
+        let mut graph = PortGraph::new();
+        let ports = [
+            ("synth:out", PortDirection::Out),
+            ("system:playback_1", PortDirection::In),
+            ("looper:in", PortDirection::In),
+        ];
+        for (raw, direction) in ports.iter() {
+            graph.add_port(port(raw, *direction));
+        }
+        let problems: Vec<_> = lint
+            .graph_problems(&graph)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                r#"["@profiles".live.reverb] names reverb, which matches nothing in the graph"#,
+                r#"[":in"] names :in, which matches nothing in the graph"#,
+                r#"["system:playback_1"] lists a connection to looper:in, but both are inputs"#,
+                r#"["@profiles".live.reverb.in] names system:capture_1, which matches nothing in the graph"#,
+                r#"["@profiles".live.reverb.in] names /drum.*:out/, which matches nothing in the graph"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_config_lint() {
+        let tmp = TempDir::new("merged_config_lint");
+        let conf_dir = tmp.path().join("conf.d");
+        fs::create_dir_all(&conf_dir).unwrap();
+        let snippet = conf_dir.join("10-synth.toml");
+        fs::write(
+            &snippet,
+            "[\"synth:out\"]\nconnections = [\"system:playback_1\"]\n",
+        )
+        .unwrap();
+        let main = tmp.path().join("main.toml");
+        fs::write(
+            &main,
+            "[\"system:playback_1\"]\nconnections = [\"synth:out\"]\n\
+             [synth.out]\nlock = \"full\"\n\
+             [\"synth:out\"]\nlock = \"full\"\n",
+        )
+        .unwrap();
+
+        let lint = ConfigLint::load(&main, &[conf_dir]).unwrap();
+        let problems: Vec<_> = lint.problems().iter().map(|p| p.to_string()).collect();
+        // The snippet's connection is listed back from the main file, and only the two
+        // tables within the main file count as duplicates.
+        assert_eq!(
+            problems,
+            vec![format!(
+                r#"synth:out is configured by both [synth.out] in {0} and ["synth:out"] in {0}"#,
+                main.display()
+            )]
+        );
+        let missing = ConfigLint::load(&tmp.path().join("missing.toml"), &[]);
+        assert!(matches!(missing, Err(ConfigLoadError::Read { .. })));
+    }
+}
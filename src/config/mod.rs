@@ -1,112 +1,176 @@
-use crate::model::PortFullname;
+use crate::graph::{GraphBackend, GraphError, PortGraph};
+use crate::model::{PortData, PortFullname};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::convert::TryFrom;
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::Hash;
 
 mod parsing;
 
+mod pattern;
+pub use pattern::*;
+
+mod rules;
+pub use rules::*;
+
+mod instances;
+pub use instances::*;
+
+mod bundles;
+pub use bundles::*;
+
+mod conditions;
+pub use conditions::*;
+
+mod precedence;
+pub use precedence::*;
+
+mod lint;
+pub use lint::*;
+
+mod includes;
+pub use includes::*;
+
+/// The full set of rules trej enforces, made up of rules shared by every profile,
+/// any number of named profiles adding their own rules on top, and any number of
+/// named rule sets that only apply while some clients are present or absent.
+///
+/// ```toml
+/// "@active_profile" = "tracking"
+///
+/// [system]
+/// lock = "full"
+///
+/// ["@profiles".tracking.looper.in]
+/// connections = ["system:capture_1"]
+///
+/// ["@profiles".overdub]
+/// inherits = ["tracking"]
+///
+/// ["@conditional".reverb_send]
+/// when_absent = ["looper"]
+/// ["@conditional".reverb_send."system:capture_1"]
+/// connections = ["reverb:in"]
+/// ```
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Eq, PartialEq)]
 #[serde(from = "parsing::ConfigFile", into = "parsing::ConfigFile")]
 pub struct LockConfig {
-    client_locks: HashMap<String, LockStatus>,
-    port_locks: HashMap<PortFullname, LockStatus>,
-    connections_list: Vec<(PortFullname, PortFullname)>,
+    /// The rules shared by every profile.
+    base: LockRules,
+    profiles: BTreeMap<String, Profile>,
+    /// The profile the config file selects, if any.
+    default_profile: Option<String>,
+    /// The profile picked while running in place of the default one, if any.
+    selected_profile: Option<String>,
+    /// The rule sets that only apply while their condition holds, applied in name order
+    /// on top of the active profile.
+    conditional: BTreeMap<String, ConditionalRules>,
+    /// The names of the conditional rule sets whose condition held when the graph was
+    /// last checked.
+    holding: BTreeSet<String>,
+    /// The shared rules merged with those of the active profile and every profile it
+    /// inherits from, and with the conditional rule sets that hold.
+    active: LockRules,
+    /// The running clients bound to each client entry with an instance mode.
+    bindings: ClientBindings,
+    /// The connections the active bundles make in the graph, as `(output, input)` pairs.
+    bundled: Vec<(PortFullname, PortFullname)>,
+    /// The peer each exclusive port was left connected to when the graph was last checked.
+    exclusive_peers: BTreeMap<PortFullname, PortFullname>,
+    /// Connections to exclusive ports that a newer connection has replaced, and which
+    /// are therefore blocked.
+    superseded: Vec<(PortFullname, PortFullname)>,
+    /// The clashes between rules found when the config was loaded.
+    conflicts: Vec<RuleConflict>,
+}
+
+/// A named set of rules, applied on top of the rules of the profiles it inherits from.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Profile {
+    /// The profiles this one builds on, applied in order.
+    inherits: Vec<String>,
+    rules: LockRules,
 }
 
 impl From<LockConfig> for parsing::ConfigFile {
     fn from(conf: LockConfig) -> Self {
-        let mut client_map: HashMap<String, parsing::ClientInfo> = HashMap::new();
-        let mut port_map: HashMap<PortFullname, parsing::PortInfo> = HashMap::new();
-        for (client_name, lock) in conf.client_locks {
-            client_map.insert(client_name, parsing::ClientInfo::new().with_lock(lock));
-        }
-        for (port_name, lock) in conf.port_locks {
-            let ent = client_map
-                .get_mut(port_name.client_name())
-                .map(|client_info| &mut client_info.ports)
-                .map(|port_map| {
-                    port_map
-                        .entry(port_name.port_shortname().to_owned())
-                        .or_default()
-                })
-                .unwrap_or_else(|| port_map.entry(port_name).or_default());
-            ent.set_lock(lock);
-        }
-        for (first, second) in conf.connections_list {
-            let first_ent = client_map
-                .get_mut(first.client_name())
-                .map(|client_info| &mut client_info.ports)
-                .and_then(|port_map| port_map.get_mut(first.port_shortname()))
-                .or_else(|| port_map.get_mut(&first));
-            if let Some(first_ent) = first_ent {
-                first_ent.connections.push(second.clone());
-            }
-
-            let second_ent = client_map
-                .get_mut(second.client_name())
-                .map(|client_info| &mut client_info.ports)
-                .and_then(|port_map| port_map.get_mut(second.port_shortname()))
-                .or_else(|| port_map.get_mut(&second));
-            if let Some(second_ent) = second_ent {
-                second_ent.connections.push(first.clone());
-            }
-        }
-        let client_ents = client_map
+        let profiles = conf
+            .profiles
             .into_iter()
-            .map(|(name, info)| parsing::LockEntry::Client { name, info });
-        let port_ents = port_map
+            .map(|(name, profile)| {
+                let file = parsing::ProfileFile {
+                    inherits: profile.inherits,
+                    entries: profile.rules.into(),
+                };
+                (name, file)
+            })
+            .collect();
+        let conditional = conf
+            .conditional
             .into_iter()
-            .map(|(name, info)| parsing::LockEntry::Port { name, info });
-        let entries = client_ents.chain(port_ents).collect();
-        parsing::ConfigFile { entries }
+            .map(|(name, conditional)| {
+                let condition = &conditional.condition;
+                let file = parsing::ConditionalFile {
+                    when_present: condition.when_present().map(str::to_owned).collect(),
+                    when_absent: condition.when_absent().map(str::to_owned).collect(),
+                    entries: conditional.rules.into(),
+                };
+                (name, file)
+            })
+            .collect();
+        parsing::ConfigFile {
+            includes: Vec::new(),
+            active_profile: conf.default_profile,
+            entries: conf.base.into(),
+            profiles,
+            conditional,
+        }
     }
 }
 
 impl From<parsing::ConfigFile> for LockConfig {
     fn from(fl: parsing::ConfigFile) -> Self {
-        let mut retvl = LockConfig::new();
-        for ent in fl.entries {
-            match ent {
-                parsing::LockEntry::Client { name, info } => {
-                    if let Some(lock) = info.lock {
-                        retvl.client_locks.insert(name.clone(), lock);
-                    }
-                    for (shortname, port_info) in info.ports.into_iter() {
-                        let raw_fullname = format!("{}:{}", name, shortname);
-                        let fullname = PortFullname::try_from(raw_fullname).unwrap();
-                        if let Some(lock) = port_info.lock {
-                            retvl.port_locks.insert(fullname.clone(), lock);
-                        }
-                        for other in port_info.connections {
-                            let connection = if fullname > other {
-                                (other, fullname.clone())
-                            } else {
-                                (fullname.clone(), other)
-                            };
-                            if let Err(idx) = retvl.connections_list.binary_search(&connection) {
-                                retvl.connections_list.insert(idx, connection);
-                            }
-                        }
-                    }
-                }
-                parsing::LockEntry::Port { name, info } => {
-                    if let Some(lock) = info.lock {
-                        retvl.port_locks.insert(name.clone(), lock);
-                    }
-                    for other in info.connections {
-                        let connection = if name > other {
-                            (other, name.clone())
-                        } else {
-                            (name.clone(), other)
-                        };
-                        if let Err(idx) = retvl.connections_list.binary_search(&connection) {
-                            retvl.connections_list.insert(idx, connection);
-                        }
-                    }
-                }
-            }
-        }
+        let profiles = fl
+            .profiles
+            .into_iter()
+            .map(|(name, file)| {
+                let rules = LockRules::from(file.entries);
+                let profile = Profile {
+                    inherits: file.inherits,
+                    rules: rules.in_layer(RuleLayer::Profile(name.clone())),
+                };
+                (name, profile)
+            })
+            .collect();
+        let conditional = fl
+            .conditional
+            .into_iter()
+            .map(|(name, file)| {
+                // Names are checked while parsing, so they always compile here.
+                let condition = Condition::new(&file.when_present, &file.when_absent).unwrap();
+                let rules = LockRules::from(file.entries);
+                let conditional = ConditionalRules {
+                    condition,
+                    rules: rules.in_layer(RuleLayer::Conditional(name.clone())),
+                };
+                (name, conditional)
+            })
+            .collect();
+        let mut retvl = LockConfig {
+            base: fl.entries.into(),
+            profiles,
+            default_profile: fl.active_profile,
+            selected_profile: None,
+            conditional,
+            holding: BTreeSet::new(),
+            active: LockRules::new(),
+            bindings: ClientBindings::default(),
+            bundled: Vec::new(),
+            exclusive_peers: BTreeMap::new(),
+            superseded: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        retvl.conflicts = retvl.find_conflicts();
+        retvl.refresh_active();
         retvl
     }
 }
@@ -115,37 +179,352 @@ impl LockConfig {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn client_status(&self, client: &str) -> LockStatus {
-        self.client_locks.get(client).copied().unwrap_or_default()
+    /// Builds a config that recreates every connection currently in `graph`,
+    /// giving each client in the graph the lock picked for it by `client_lock`.
+    pub fn snapshot<F: FnMut(&str) -> LockStatus>(graph: &PortGraph, client_lock: F) -> Self {
+        let base = LockRules::snapshot(graph, client_lock);
+        let mut retvl = LockConfig {
+            base,
+            ..Self::default()
+        };
+        retvl.refresh_active();
+        retvl
+    }
+    /// Gets the name of the profile whose rules are being enforced, if any.
+    pub fn active_profile(&self) -> Option<&str> {
+        self.selected_profile
+            .as_deref()
+            .or(self.default_profile.as_deref())
+            .filter(|name| self.profiles.contains_key(*name))
     }
-    pub fn port_status(&self, port: &PortFullname) -> LockStatus {
-        self.port_locks
-            .get(port)
-            .copied()
-            .unwrap_or_else(|| self.client_status(port.client_name()))
+    /// Gets the names of every profile in the config, in alphabetical order.
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(|name| name.as_str())
+    }
+    /// Switches to enforcing the profile called `name` instead of the one the config
+    /// file selects.
+    /// Returns `false`, leaving the active profile as it was, if there is no such profile.
+    pub fn select_profile(&mut self, name: &str) -> bool {
+        if !self.profiles.contains_key(name) {
+            return false;
+        }
+        self.selected_profile = Some(name.to_owned());
+        self.refresh_active();
+        true
+    }
+    /// Carries over the profile picked in `old`, a previous version of this config,
+    /// as long as that profile still exists, along with what it worked out from the graph
+    /// in `bind_graph`.
+    pub fn keep_runtime_state(&mut self, old: &LockConfig) {
+        self.holding = old
+            .holding
+            .iter()
+            .filter(|name| self.conditional.contains_key(*name))
+            .cloned()
+            .collect();
+        if let Some(name) = old.selected_profile.as_deref() {
+            self.select_profile(name);
+        }
+        self.refresh_active();
+        self.bindings = old.bindings.clone();
+        self.bundled = old.bundled.clone();
+        self.exclusive_peers = old.exclusive_peers.clone();
+        self.superseded = old.superseded.clone();
+    }
+    /// Gets every conditional rule set in the config, in name order, along with whether
+    /// its condition held when the graph was last checked.
+    pub fn conditions(&self) -> impl Iterator<Item = (&str, &Condition, bool)> {
+        self.conditional.iter().map(move |(name, conditional)| {
+            let holds = self.holding.contains(name);
+            (name.as_str(), &conditional.condition, holds)
+        })
+    }
+    /// Checks which conditional rule sets apply to `graph`, binds the client entries with
+    /// an instance mode to the clients running in it, works out the connections the
+    /// bundles make between its ports, and finds the connections to exclusive ports that
+    /// newer ones have replaced.
+    /// This needs to be done whenever the graph changes, before enforcing the config.
+    pub fn bind_graph(&mut self, graph: &PortGraph) {
+        let holding: BTreeSet<String> = self
+            .conditional
+            .iter()
+            .filter(|(_, conditional)| conditional.condition.holds(graph))
+            .map(|(name, _)| name.clone())
+            .collect();
+        if holding != self.holding {
+            self.holding = holding;
+            self.refresh_active();
+        }
+        let modes = self.active.client_instances();
+        let named = self.active.named_clients();
+        self.bindings = ClientBindings::bind(modes, &named, graph, &self.bindings);
+        self.bundled = self.active.bundle_connections(graph, &self.bindings);
+        self.check_exclusive(graph);
     }
-    pub fn forced_connections<'a>(
+    /// Binds the config to the current state of `graph`, then breaks the connections it
+    /// blocks and makes the ones it forces.
+    pub fn enforce<G: GraphBackend>(&mut self, graph: &mut G) -> Result<(), GraphError> {
+        self.bind_graph(graph.graph());
+        let should_disconnect = graph
+            .graph()
+            .all_connections()
+            .filter(|(a, b)| self.connection_status(a, b).should_block())
+            .map(|(a, b)| (a.clone(), b.clone()))
+            .collect::<Vec<_>>();
+        for (a, b) in should_disconnect {
+            let (src, dst) = if a.direction.is_output() {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            graph.disconnect(&src.name, &dst.name)?;
+        }
+        for (src, dst) in self.forced_connections(graph.graph()) {
+            if !graph.is_connected(&src, &dst) {
+                graph.connect(&src, &dst)?;
+            }
+        }
+        Ok(())
+    }
+    /// Picks the one connection each exclusive port in `graph` keeps, marking the rest as
+    /// superseded.
+    /// A forced connection is always kept; otherwise a connection made since the graph
+    /// was last checked replaces the one the port had before.
+    fn check_exclusive(&mut self, graph: &PortGraph) {
+        let mut peers = BTreeMap::new();
+        let mut superseded = Vec::new();
+        for port in graph.all_ports() {
+            if self.port_status(port) != LockStatus::Exclusive {
+                continue;
+            }
+            let connected: Vec<_> = graph
+                .port_connections(&port.name)
+                .filter(|other| !is_listed(&superseded, &port.name, &other.name))
+                .collect();
+            let previous = self.exclusive_peers.get(&port.name);
+            let keep = connected
+                .iter()
+                .find(|other| {
+                    self.active
+                        .connection_status(port, other, &self.bindings, &self.bundled)
+                        .should_force()
+                })
+                .or_else(|| connected.iter().find(|other| Some(&other.name) != previous))
+                .or_else(|| connected.first());
+            let keep = match keep {
+                Some(keep) => keep,
+                None => {
+                    continue;
+                }
+            };
+            for other in connected.iter().filter(|other| other.name != keep.name) {
+                superseded.push((port.name.clone(), other.name.clone()));
+            }
+            peers.insert(port.name.clone(), keep.name.clone());
+        }
+        self.exclusive_peers = peers;
+        self.superseded = superseded;
+    }
+    /// Gets the client entry whose rules apply to `client`, along with its instance mode,
+    /// if that entry has one.
+    /// The entry is `None` if `client` has its own entry but another instance is bound
+    /// to it instead.
+    pub fn client_binding<'a>(
         &'a self,
-    ) -> impl Iterator<Item = (&'a PortFullname, &'a PortFullname)> + 'a {
-        self.connections_list
+        client: &'a str,
+    ) -> Option<(Option<&'a str>, InstanceMode)> {
+        let entry = self.bindings.entry_for(client);
+        let mode = self
+            .active
+            .client_instances()
+            .get(entry.unwrap_or(client))?;
+        Some((entry, *mode))
+    }
+    /// Gets the clients bound to the client entry `entry`, if it has an instance mode.
+    pub fn bound_instances(&self, entry: &str) -> Option<&[String]> {
+        self.bindings.instances(entry)
+    }
+    fn refresh_active(&mut self) {
+        let holding = self.holding.iter().map(|name| name.as_str());
+        self.active = self.merged_rules(self.active_profile(), holding);
+    }
+    /// Merges the shared rules with those of the profile called `profile`, if any, and
+    /// with the conditional rule sets named in `conditional`.
+    fn merged_rules<'a, I: Iterator<Item = &'a str>>(
+        &self,
+        profile: Option<&str>,
+        conditional: I,
+    ) -> LockRules {
+        let mut retvl = self.base.clone();
+        if let Some(name) = profile {
+            let mut visited = Vec::new();
+            self.apply_profile(name, &mut visited, &mut retvl);
+        }
+        for name in conditional {
+            if let Some(conditional) = self.conditional.get(name) {
+                retvl.extend(&conditional.rules);
+            }
+        }
+        retvl
+    }
+    /// Finds the clashes between rules that could apply at the same time: the shared
+    /// rules on their own or with any one profile, with or without any one conditional
+    /// rule set on top.
+    fn find_conflicts(&self) -> Vec<RuleConflict> {
+        let profiles = std::iter::once(None).chain(self.profile_names().map(Some));
+        let mut retvl = Vec::new();
+        for profile in profiles {
+            let conditional = std::iter::once(None).chain(self.conditional.keys().map(Some));
+            for name in conditional {
+                let rules = self.merged_rules(profile, name.map(|name| name.as_str()).into_iter());
+                for conflict in rules.conflicts() {
+                    if !retvl.contains(&conflict) {
+                        retvl.push(conflict);
+                    }
+                }
+            }
+        }
+        retvl
+    }
+    /// Gets the clashes between rules found when the config was loaded.
+    pub fn conflicts(&self) -> &[RuleConflict] {
+        &self.conflicts
+    }
+    /// Adds the rules of the profile called `name` to `rules`, after first adding those
+    /// of the profiles it inherits from.
+    /// Each profile is only applied once, so inheritance cycles are harmless.
+    fn apply_profile<'a>(
+        &'a self,
+        name: &'a str,
+        visited: &mut Vec<&'a str>,
+        rules: &mut LockRules,
+    ) {
+        if visited.contains(&name) {
+            return;
+        }
+        visited.push(name);
+        let profile = match self.profiles.get(name) {
+            Some(profile) => profile,
+            None => {
+                return;
+            }
+        };
+        for parent in profile.inherits.iter() {
+            self.apply_profile(parent, visited, rules);
+        }
+        rules.extend(&profile.rules);
+    }
+    pub fn client_status(&self, client: &str) -> LockStatus {
+        self.active.client_status(client, &self.bindings)
+    }
+    /// Gets where the lock deciding the lock status of `client` was declared, if any
+    /// applies.
+    pub fn client_lock_source(&self, client: &str) -> Option<&RuleSource> {
+        let matched = self.active.client_rule(client, &self.bindings)?;
+        Some(&matched.rule.source)
+    }
+    /// Gets where the lock deciding the lock status of `port` was declared, if any
+    /// applies.
+    pub fn port_lock_source(&self, port: &PortData) -> Option<&RuleSource> {
+        let matched = self.active.port_rule(port, &self.bindings)?;
+        Some(&matched.rule.source)
+    }
+    /// Gets the lock status of `port` under the active profile; see
+    /// `LockRules::port_status`.
+    pub fn port_status(&self, port: &PortData) -> LockStatus {
+        self.active.port_status(port, &self.bindings)
+    }
+    /// Gets every connection the active profile forces in `graph`, as `(output, input)`
+    /// pairs; see `LockRules::forced_connections`.
+    /// An exclusive port only keeps one connection, so only one of the connections forced
+    /// on it is included: the one it already has, if that is forced, or else the first.
+    pub fn forced_connections(&self, graph: &PortGraph) -> Vec<(PortFullname, PortFullname)> {
+        let forced = self.active.forced_connections(graph, &self.bindings);
+        let is_exclusive = |name: &PortFullname| match graph.port_by_name(name) {
+            Some(port) => self.port_status(port) == LockStatus::Exclusive,
+            None => false,
+        };
+        let mut chosen: BTreeMap<&PortFullname, &PortFullname> = BTreeMap::new();
+        for (a, b) in forced.iter() {
+            for (port, other) in [(a, b), (b, a)].iter().copied() {
+                if !is_exclusive(port) {
+                    continue;
+                }
+                if self.exclusive_peers.get(port) == Some(other) {
+                    chosen.insert(port, other);
+                } else {
+                    chosen.entry(port).or_insert(other);
+                }
+            }
+        }
+        let allowed = |port: &PortFullname, other: &PortFullname| match chosen.get(port) {
+            Some(peer) => *peer == other,
+            None => true,
+        };
+        forced
             .iter()
-            .filter(move |(a, b)| self.connection_status(a, b).should_force())
-            .map(|(a, b)| (a, b))
-    }
-    pub fn connection_status(&self, a: &PortFullname, b: &PortFullname) -> LockStatus {
-        let con_key = (a.min(b), a.max(b));
-        let con_preexists = self
-            .connections_list
-            .binary_search_by_key(&con_key, |(a, b)| (&a, &b))
-            .is_ok();
-        let a_lock = self.port_status(a);
-        let b_lock = self.port_status(b);
-        if con_preexists && (a_lock.should_force() || b_lock.should_force()) {
-            LockStatus::Force
-        } else if !con_preexists && (a_lock.should_block() || b_lock.should_block()) {
-            LockStatus::Block
-        } else {
-            LockStatus::None
+            .filter(|(a, b)| allowed(a, b) && allowed(b, a))
+            .cloned()
+            .collect()
+    }
+    /// Gets the lock status of the connection between `a` and `b` under the active
+    /// profile; connections an exclusive port has replaced are always blocked.
+    pub fn connection_status(&self, a: &PortData, b: &PortData) -> LockStatus {
+        if self.is_superseded(a, b) {
+            return LockStatus::Block;
+        }
+        self.active
+            .connection_status(a, b, &self.bindings, &self.bundled)
+    }
+    /// Checks whether the connection between `a` and `b` is to an exclusive port that a
+    /// newer connection has replaced.
+    pub fn is_superseded(&self, a: &PortData, b: &PortData) -> bool {
+        is_listed(&self.superseded, &a.name, &b.name)
+    }
+}
+
+/// Checks whether `connections` holds the connection between `a` and `b`, in either order.
+fn is_listed(
+    connections: &[(PortFullname, PortFullname)],
+    a: &PortFullname,
+    b: &PortFullname,
+) -> bool {
+    connections
+        .iter()
+        .any(|(x, y)| (x == a && y == b) || (x == b && y == a))
+}
+
+/// A group of ports picked out by their flags rather than by name.
+/// In a config file these are written as keys starting with `@`:
+///
+/// ```toml
+/// ["@physical_outputs"]
+/// lock = "block"
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PortClass {
+    PhysicalInputs,
+    PhysicalOutputs,
+}
+
+impl PortClass {
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "@physical_inputs" => Some(PortClass::PhysicalInputs),
+            "@physical_outputs" => Some(PortClass::PhysicalOutputs),
+            _ => None,
+        }
+    }
+    pub const fn key(self) -> &'static str {
+        match self {
+            PortClass::PhysicalInputs => "@physical_inputs",
+            PortClass::PhysicalOutputs => "@physical_outputs",
+        }
+    }
+    pub fn matches(self, port: &PortData) -> bool {
+        match self {
+            PortClass::PhysicalInputs => port.is_physical && port.direction.is_input(),
+            PortClass::PhysicalOutputs => port.is_physical && port.direction.is_output(),
         }
     }
 }
@@ -158,18 +537,32 @@ pub enum LockStatus {
     Force = 0b01,
     Block = 0b10,
     Full = 0b11,
+    /// The port keeps at most one connection: when it is connected somewhere new,
+    /// its previous connection is removed.
+    Exclusive = 0b100,
 }
 
 impl LockStatus {
+    /// Parses a lock level the way it is written in a config file, such as `"full"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(LockStatus::None),
+            "force" => Some(LockStatus::Force),
+            "block" => Some(LockStatus::Block),
+            "full" => Some(LockStatus::Full),
+            "exclusive" => Some(LockStatus::Exclusive),
+            _ => None,
+        }
+    }
     pub const fn should_force(self) -> bool {
         match self {
-            LockStatus::None | LockStatus::Block => false,
+            LockStatus::None | LockStatus::Block | LockStatus::Exclusive => false,
             LockStatus::Force | LockStatus::Full => true,
         }
     }
     pub const fn should_block(self) -> bool {
         match self {
-            LockStatus::None | LockStatus::Force => false,
+            LockStatus::None | LockStatus::Force | LockStatus::Exclusive => false,
             LockStatus::Block | LockStatus::Full => true,
         }
     }
@@ -180,3 +573,383 @@ impl Default for LockStatus {
         LockStatus::None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PortDirection;
+    use crate::testing::port;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let synth_out = port("synth:out", PortDirection::Out);
+        let playback_1 = port("system:playback_1", PortDirection::In);
+        let playback_2 = port("system:playback_2", PortDirection::In);
+        let mut graph = PortGraph::new();
+        graph.add_port(synth_out.clone());
+        graph.add_port(playback_1.clone());
+        graph.add_port(playback_2.clone());
+        graph.add_connection(&synth_out.name, &playback_1.name);
+        graph.add_connection(&synth_out.name, &playback_2.name);
+
+        let config = LockConfig::snapshot(&graph, |client| match client {
+            "synth" => LockStatus::Full,
+            _ => LockStatus::None,
+        });
+        assert_eq!(config.client_status("synth"), LockStatus::Full);
+        assert_eq!(config.client_status("system"), LockStatus::None);
+        assert_eq!(
+            config.connection_status(&synth_out, &playback_2),
+            LockStatus::Force
+        );
+
+        let raw_conf = toml::to_string_pretty(&config).unwrap();
+        let reparsed: LockConfig = toml::from_str(&raw_conf).unwrap();
+        assert_eq!(reparsed, config);
+    }
+
+    #[test]
+    fn test_profiles() {
+        let synth_out = port("synth:out", PortDirection::Out);
+        let playback_1 = port("system:playback_1", PortDirection::In);
+        let capture_1 = port("system:capture_1", PortDirection::Out);
+        let looper_in = port("looper:in", PortDirection::In);
+        let raw_conf = r#"
+            "@active_profile" = "tracking"
+
+            [system]
+            lock = "full"
+            [looper]
+            lock = "block"
+
+            ["@profiles".common.synth.out]
+            connections = ["system:playback_1"]
+
+            ["@profiles".tracking]
+            inherits = ["common"]
+            ["@profiles".tracking."looper:in"]
+            connections = ["system:capture_1"]
+
+            ["@profiles".mixing]
+            inherits = ["common", "mixing"]
+        "#;
+        let mut config: LockConfig = toml::from_str(raw_conf).unwrap();
+        assert_eq!(config.active_profile(), Some("tracking"));
+        assert_eq!(
+            config.profile_names().collect::<Vec<_>>(),
+            vec!["common", "mixing", "tracking"]
+        );
+        assert_eq!(config.client_status("looper"), LockStatus::Block);
+        assert_eq!(
+            config.connection_status(&synth_out, &playback_1),
+            LockStatus::Force
+        );
+        assert_eq!(
+            config.connection_status(&capture_1, &looper_in),
+            LockStatus::Force
+        );
+
+        // Inheriting from itself doesn't stop the rest of the profile from applying.
+        assert!(config.select_profile("mixing"));
+        assert_eq!(config.active_profile(), Some("mixing"));
+        assert_eq!(
+            config.connection_status(&synth_out, &playback_1),
+            LockStatus::Force
+        );
+        assert_eq!(
+            config.connection_status(&capture_1, &looper_in),
+            LockStatus::Block
+        );
+        assert!(!config.select_profile("live"));
+        assert_eq!(config.active_profile(), Some("mixing"));
+
+        let raw_conf = toml::to_string_pretty(&config).unwrap();
+        let mut reparsed: LockConfig = toml::from_str(&raw_conf).unwrap();
+        assert_eq!(reparsed.active_profile(), Some("tracking"));
+        reparsed.keep_runtime_state(&config);
+        assert_eq!(reparsed, config);
+    }
+
+    #[test]
+    fn test_pattern_keys() {
+        let synth_out = port("synth:out", PortDirection::Out);
+        let playback_1 = port("system:playback_1", PortDirection::In);
+        let playback_2 = port("system:playback_2", PortDirection::In);
+        let playback_12 = port("system:playback_12", PortDirection::In);
+        let mut graph = PortGraph::new();
+        for data in [&synth_out, &playback_1, &playback_2, &playback_12].iter() {
+            graph.add_port((*data).clone());
+        }
+        let raw_conf = r#"
+            ["sys*"]
+            lock = "force"
+            ["system:playback_*"]
+            lock = "block"
+            ["system:playback_?"]
+            lock = "full"
+            ["/system:playback_1[0-9]/"]
+            lock = "none"
+            [synth.out]
+            connections = ["system:playback_?"]
+        "#;
+        let config: LockConfig = toml::from_str(raw_conf).unwrap();
+        assert_eq!(config.client_status("system"), LockStatus::Force);
+        assert_eq!(config.client_status("synth"), LockStatus::None);
+        assert_eq!(config.port_status(&playback_1), LockStatus::Full);
+        assert_eq!(config.port_status(&playback_12), LockStatus::Block);
+        assert_eq!(
+            config.connection_status(&synth_out, &playback_2),
+            LockStatus::Force
+        );
+        assert_eq!(
+            config.forced_connections(&graph),
+            vec![
+                (synth_out.name.clone(), playback_1.name.clone()),
+                (synth_out.name.clone(), playback_2.name.clone()),
+            ]
+        );
+
+        let raw_conf = toml::to_string_pretty(&config).unwrap();
+        let reparsed: LockConfig = toml::from_str(&raw_conf).unwrap();
+        assert_eq!(reparsed, config);
+
+        let bad_conf = r#"
+            ["/system:playback_(/"]
+            lock = "block"
+        "#;
+        assert!(toml::from_str::<LockConfig>(bad_conf).is_err());
+
+        // Nested port keys keep their declaration order, so the later of two equally
+        // specific patterns wins.
+        let out_l = port("synth:out_L", PortDirection::Out);
+        let tied_conf = r#"
+            [synth]
+            "out*" = { lock = "block" }
+            "o*_L" = { lock = "force" }
+        "#;
+        let config: LockConfig = toml::from_str(tied_conf).unwrap();
+        assert_eq!(config.port_status(&out_l), LockStatus::Force);
+    }
+
+    #[test]
+    fn test_escaped_keys() {
+        let mixer_out = port("mix*:out", PortDirection::Out);
+        let playback_1 = port("system:playback_1", PortDirection::In);
+        let raw_conf = r#"
+            ['mix\*']
+            lock = "block"
+        "#;
+        let config: LockConfig = toml::from_str(raw_conf).unwrap();
+        assert_eq!(config.client_status("mix*"), LockStatus::Block);
+        assert_eq!(config.client_status("mixer"), LockStatus::None);
+
+        // Snapshots escape live names, so they don't turn into patterns when read back.
+        let mut graph = PortGraph::new();
+        graph.add_port(mixer_out.clone());
+        graph.add_port(playback_1.clone());
+        graph.add_connection(&mixer_out.name, &playback_1.name);
+        let config = LockConfig::snapshot(&graph, |_| LockStatus::Full);
+        let raw_conf = toml::to_string_pretty(&config).unwrap();
+        let reparsed: LockConfig = toml::from_str(&raw_conf).unwrap();
+        assert_eq!(reparsed.client_status("mix*"), LockStatus::Full);
+        assert_eq!(reparsed.client_status("mixer"), LockStatus::None);
+        assert_eq!(
+            reparsed.connection_status(&mixer_out, &playback_1),
+            LockStatus::Force
+        );
+    }
+
+    #[test]
+    fn test_instance_entries() {
+        let synth_out = port("synth-01:out", PortDirection::Out);
+        let playback_1 = port("system:playback_1", PortDirection::In);
+        let mut graph = PortGraph::new();
+        graph.add_port(synth_out.clone());
+        graph.add_port(playback_1.clone());
+        let raw_conf = r#"
+            [synth]
+            instance = "first"
+            lock = "full"
+            [synth.out]
+            connections = ["system:playback_1"]
+        "#;
+        let mut config: LockConfig = toml::from_str(raw_conf).unwrap();
+        assert_eq!(config.client_status("synth-01"), LockStatus::None);
+        config.bind_graph(&graph);
+        assert_eq!(config.client_status("synth-01"), LockStatus::Full);
+        assert_eq!(
+            config.client_binding("synth-01"),
+            Some((Some("synth"), InstanceMode::First))
+        );
+        assert_eq!(
+            config.forced_connections(&graph),
+            vec![(synth_out.name.clone(), playback_1.name.clone())]
+        );
+
+        let raw_conf = toml::to_string_pretty(&config).unwrap();
+        let mut reparsed: LockConfig = toml::from_str(&raw_conf).unwrap();
+        reparsed.keep_runtime_state(&config);
+        assert_eq!(reparsed, config);
+
+        // An instance with its own entry isn't bound to the instanced one.
+        let explicit_conf = r#"
+            [synth]
+            instance = "all"
+            lock = "full"
+            ["synth-01"]
+            lock = "block"
+        "#;
+        let mut config: LockConfig = toml::from_str(explicit_conf).unwrap();
+        config.bind_graph(&graph);
+        assert_eq!(config.client_status("synth-01"), LockStatus::Block);
+        assert_eq!(config.forced_connections(&graph), vec![]);
+
+        let bad_conf = r#"
+            ["synth*"]
+            instance = "all"
+        "#;
+        assert!(toml::from_str::<LockConfig>(bad_conf).is_err());
+    }
+
+    #[test]
+    fn test_bundles() {
+        let synth_l = port("synth:out_L", PortDirection::Out);
+        let synth_r = port("synth:out_R", PortDirection::Out);
+        let in_3 = port("mixer:in_3", PortDirection::In);
+        let in_4 = port("mixer:in_4", PortDirection::In);
+        let mut graph = PortGraph::new();
+        for data in [&synth_l, &synth_r, &in_3, &in_4].iter() {
+            graph.add_port((*data).clone());
+        }
+        let raw_conf = r#"
+            [synth]
+            lock = "force"
+
+            [["@bundles"]]
+            from = "synth"
+            to = ["mixer:in_4", "mixer:in_3"]
+        "#;
+        let mut config: LockConfig = toml::from_str(raw_conf).unwrap();
+        config.bind_graph(&graph);
+        assert_eq!(
+            config.forced_connections(&graph),
+            vec![
+                (synth_l.name.clone(), in_4.name.clone()),
+                (synth_r.name.clone(), in_3.name.clone()),
+            ]
+        );
+        assert_eq!(config.connection_status(&synth_r, &in_3), LockStatus::Force);
+        assert_eq!(config.connection_status(&synth_r, &in_4), LockStatus::None);
+
+        let raw_conf = toml::to_string_pretty(&config).unwrap();
+        let mut reparsed: LockConfig = toml::from_str(&raw_conf).unwrap();
+        reparsed.keep_runtime_state(&config);
+        assert_eq!(reparsed, config);
+
+        let bad_conf = r#"
+            [["@bundles"]]
+            from = "synth"
+            to = ["mixer:in_*"]
+        "#;
+        assert!(toml::from_str::<LockConfig>(bad_conf).is_err());
+    }
+
+    #[test]
+    fn test_rule_precedence() {
+        let synth_out = port("synth:out", PortDirection::Out);
+        let playback_1 = port("system:playback_1", PortDirection::In);
+        let raw_conf = r#"
+            [synth]
+            lock = "force"
+            [synth.out]
+            connections = ["system:playback_1", "system:playback_2"]
+
+            ["system:playback_*"]
+            lock = "block"
+            ["system:playback_2"]
+            lock = "none"
+            priority = 1
+
+            ["sys*"]
+            lock = "full"
+            ["*tem"]
+            lock = "none"
+
+            ["@profiles".live.synth]
+            lock = "none"
+            priority = 5
+        "#;
+        let mut config: LockConfig = toml::from_str(raw_conf).unwrap();
+        // The port pattern is more specific than the client lock, so it wins.
+        assert_eq!(config.port_status(&synth_out), LockStatus::Force);
+        assert_eq!(config.port_status(&playback_1), LockStatus::Block);
+        assert_eq!(
+            config.connection_status(&synth_out, &playback_1),
+            LockStatus::Block
+        );
+        assert_eq!(
+            config.port_lock_source(&playback_1).unwrap().to_string(),
+            r#"["system:playback_*"]"#
+        );
+        // Between equally specific globs, the one declared later wins.
+        assert_eq!(config.client_status("system"), LockStatus::None);
+
+        let conflicts: Vec<_> = config.conflicts().iter().map(|c| c.to_string()).collect();
+        assert_eq!(conflicts.len(), 2);
+        assert!(conflicts[0].starts_with(r#"system is locked by both ["*tem"] and ["sys*"]"#));
+        assert!(conflicts[1].starts_with(
+            r#"the connection between synth:out and system:playback_1 is forced by [synth] but blocked by ["system:playback_*"]"#
+        ));
+
+        assert!(config.select_profile("live"));
+        assert_eq!(config.client_status("synth"), LockStatus::None);
+
+        let raw_conf = toml::to_string_pretty(&config).unwrap();
+        let mut reparsed: LockConfig = toml::from_str(&raw_conf).unwrap();
+        reparsed.keep_runtime_state(&config);
+        assert_eq!(reparsed, config);
+        assert_eq!(reparsed.client_status("system"), LockStatus::None);
+
+        // Priorities only decide between equally specific rules in the same layer.
+        let capture_1 = port("system:capture_1", PortDirection::Out);
+        let ranked_conf = r#"
+            ["system:capture_*"]
+            lock = "block"
+            priority = 3
+            ["system:capture_1"]
+            lock = "force"
+            ["sys*"]
+            lock = "full"
+            priority = 1
+            ["*tem"]
+            lock = "none"
+        "#;
+        let config: LockConfig = toml::from_str(ranked_conf).unwrap();
+        assert_eq!(config.port_status(&capture_1), LockStatus::Force);
+        assert_eq!(config.client_status("system"), LockStatus::Full);
+        assert!(config.conflicts().is_empty());
+
+        // Swapping two tied rules changes which one wins, so the configs differ.
+        let swapped_conf = r#"
+            ["*tem"]
+            lock = "none"
+            ["sys*"]
+            lock = "full"
+        "#;
+        let tied_conf = r#"
+            ["sys*"]
+            lock = "full"
+            ["*tem"]
+            lock = "none"
+        "#;
+        let swapped: LockConfig = toml::from_str(swapped_conf).unwrap();
+        let tied: LockConfig = toml::from_str(tied_conf).unwrap();
+        assert_ne!(swapped, tied);
+
+        let bad_conf = r#"
+            [synth]
+            priority = 2
+        "#;
+        assert!(toml::from_str::<LockConfig>(bad_conf).is_err());
+    }
+}
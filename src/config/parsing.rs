@@ -1,22 +1,143 @@
-use super::LockStatus;
+use super::{Bundle, BundleEnd, InstanceMode, LockRule, LockStatus, NamePattern, PortClass};
 use crate::model::PortFullname;
+use indexmap::IndexMap;
 use serde::{
-    de::{Deserializer, MapAccess, Visitor},
+    de::{self, Deserializer, MapAccess, Visitor},
     ser::{SerializeMap, Serializer},
     Deserialize, Serialize,
 };
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Default)]
+/// The key listing the other config files to merge in before this one.
+const INCLUDE_KEY: &str = "include";
+/// The key holding the name of the profile to enforce.
+const ACTIVE_PROFILE_KEY: &str = "@active_profile";
+/// The key holding the table of named profiles.
+const PROFILES_KEY: &str = "@profiles";
+/// The key holding the table of conditional rule sets.
+const CONDITIONAL_KEY: &str = "@conditional";
+/// The keys, within a conditional rule set, listing the names that must be present or
+/// absent for it to apply.
+const WHEN_PRESENT_KEY: &str = "when_present";
+const WHEN_ABSENT_KEY: &str = "when_absent";
+/// The key holding the list of bundles, at the top level or within a profile.
+const BUNDLES_KEY: &str = "@bundles";
+/// The key, within a profile, listing the profiles it inherits from.
+const INHERITS_KEY: &str = "inherits";
+
+#[derive(Debug, Default, Clone)]
 pub struct ConfigFile {
+    /// The paths of the other config files to merge in, relative to this one.
+    pub includes: Vec<String>,
+    pub active_profile: Option<String>,
+    pub entries: Vec<LockEntry>,
+    pub profiles: BTreeMap<String, ProfileFile>,
+    pub conditional: BTreeMap<String, ConditionalFile>,
+}
+
+impl ConfigFile {
+    /// Adds the contents of `other` to this file, as if they were written after its own.
+    ///
+    /// Entries from `other` count as declared later, so they replace the locks set on
+    /// the same keys and win ties between equally strong rules, while the connections
+    /// they list are added to those already listed.
+    /// Profiles and conditional rule sets with the same name are combined the same way,
+    /// with the lists of profiles they inherit from and of names in their condition
+    /// appended to, and the active profile is taken from `other` if it sets one.
+    pub fn merge(&mut self, other: ConfigFile) {
+        self.includes.extend(other.includes);
+        if other.active_profile.is_some() {
+            self.active_profile = other.active_profile;
+        }
+        self.entries.extend(other.entries);
+        for (name, profile) in other.profiles {
+            let merged = self.profiles.entry(name).or_default();
+            for parent in profile.inherits {
+                if !merged.inherits.contains(&parent) {
+                    merged.inherits.push(parent);
+                }
+            }
+            merged.entries.extend(profile.entries);
+        }
+        for (name, conditional) in other.conditional {
+            let merged = self.conditional.entry(name).or_default();
+            merged.when_present.extend(conditional.when_present);
+            merged.when_absent.extend(conditional.when_absent);
+            merged.entries.extend(conditional.entries);
+        }
+    }
+    /// Records `path` as the file every entry in this one was read from.
+    pub fn set_file(&mut self, path: &Path) {
+        let profiles = self.profiles.values_mut().map(|p| &mut p.entries);
+        let conditional = self.conditional.values_mut().map(|c| &mut c.entries);
+        let layers = std::iter::once(&mut self.entries)
+            .chain(profiles)
+            .chain(conditional);
+        for ent in layers.flatten() {
+            match ent {
+                LockEntry::Client { info, .. } => {
+                    info.file = Some(path.to_owned());
+                    for port_info in info.ports.values_mut() {
+                        port_info.file = Some(path.to_owned());
+                    }
+                }
+                LockEntry::Port { info, .. } | LockEntry::Class { info, .. } => {
+                    info.file = Some(path.to_owned());
+                }
+                LockEntry::Bundle(_) => {}
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ProfileFile {
+    pub inherits: Vec<String>,
+    pub entries: Vec<LockEntry>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ConditionalFile {
+    pub when_present: Vec<String>,
+    pub when_absent: Vec<String>,
     pub entries: Vec<LockEntry>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LockEntry {
     Client { name: String, info: ClientInfo },
     Port { name: PortFullname, info: PortInfo },
+    Class { class: PortClass, info: PortInfo },
+    Bundle(Bundle),
+}
+
+fn serialize_entries<M: SerializeMap>(
+    map_serializer: &mut M,
+    entries: &[LockEntry],
+) -> Result<(), M::Error> {
+    let mut bundles = Vec::new();
+    for ent in entries.iter() {
+        match ent {
+            LockEntry::Client { name, info } => {
+                map_serializer.serialize_entry(name, info)?;
+            }
+            LockEntry::Port { name, info } => {
+                map_serializer.serialize_entry(name, info)?;
+            }
+            LockEntry::Class { class, info } => {
+                map_serializer.serialize_entry(class.key(), info)?;
+            }
+            LockEntry::Bundle(bundle) => {
+                bundles.push(bundle);
+            }
+        }
+    }
+    if !bundles.is_empty() {
+        map_serializer.serialize_entry(BUNDLES_KEY, &bundles)?;
+    }
+    Ok(())
 }
 
 impl Serialize for ConfigFile {
@@ -24,21 +145,56 @@ impl Serialize for ConfigFile {
     where
         S: Serializer,
     {
-        let mut map_serializer = serializer.serialize_map(Some(self.entries.len()))?;
-        for ent in self.entries.iter() {
-            match ent {
-                LockEntry::Client { name, info } => {
-                    map_serializer.serialize_entry(name, info)?;
-                }
-                LockEntry::Port { name, info } => {
-                    map_serializer.serialize_entry(name, info)?;
-                }
-            }
+        let mut map_serializer = serializer.serialize_map(None)?;
+        // Plain values have to come before any tables in TOML.
+        if !self.includes.is_empty() {
+            map_serializer.serialize_entry(INCLUDE_KEY, &self.includes)?;
+        }
+        if let Some(name) = self.active_profile.as_ref() {
+            map_serializer.serialize_entry(ACTIVE_PROFILE_KEY, name)?;
+        }
+        serialize_entries(&mut map_serializer, &self.entries)?;
+        if !self.profiles.is_empty() {
+            map_serializer.serialize_entry(PROFILES_KEY, &self.profiles)?;
+        }
+        if !self.conditional.is_empty() {
+            map_serializer.serialize_entry(CONDITIONAL_KEY, &self.conditional)?;
         }
         map_serializer.end()
     }
 }
 
+impl Serialize for ProfileFile {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map_serializer = serializer.serialize_map(None)?;
+        if !self.inherits.is_empty() {
+            map_serializer.serialize_entry(INHERITS_KEY, &self.inherits)?;
+        }
+        serialize_entries(&mut map_serializer, &self.entries)?;
+        map_serializer.end()
+    }
+}
+
+impl Serialize for ConditionalFile {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map_serializer = serializer.serialize_map(None)?;
+        if !self.when_present.is_empty() {
+            map_serializer.serialize_entry(WHEN_PRESENT_KEY, &self.when_present)?;
+        }
+        if !self.when_absent.is_empty() {
+            map_serializer.serialize_entry(WHEN_ABSENT_KEY, &self.when_absent)?;
+        }
+        serialize_entries(&mut map_serializer, &self.entries)?;
+        map_serializer.end()
+    }
+}
+
 impl<'de> Deserialize<'de> for ConfigFile {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -48,6 +204,125 @@ impl<'de> Deserialize<'de> for ConfigFile {
     }
 }
 
+impl<'de> Deserialize<'de> for ProfileFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ProfileVisitor {})
+    }
+}
+
+impl<'de> Deserialize<'de> for ConditionalFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ConditionalVisitor {})
+    }
+}
+
+/// Reads the value for `rawkey`, which names either a class of ports, a port,
+/// or a client.
+fn next_entry<'de, A>(map: &mut A, rawkey: String) -> Result<LockEntry, A::Error>
+where
+    A: MapAccess<'de>,
+{
+    if let Some(class) = PortClass::from_key(&rawkey) {
+        let info: PortInfo = map.next_value()?;
+        if !info.connections.is_empty() {
+            return Err(de::Error::custom(format!(
+                "{} can only set a lock, not connections",
+                rawkey
+            )));
+        }
+        check_priority(&rawkey, info.lock, info.priority)?;
+        return Ok(LockEntry::Class { class, info });
+    }
+    check_pattern(&rawkey)?;
+    match PortFullname::new(rawkey.clone()) {
+        Ok(name) => {
+            let info: PortInfo = map.next_value()?;
+            check_priority(&rawkey, info.lock, info.priority)?;
+            check_connections(&info)?;
+            Ok(LockEntry::Port { name, info })
+        }
+        Err(_) => {
+            let name = rawkey;
+            let info: ClientInfo = map.next_value()?;
+            if info.instance.is_some() && NamePattern::is_pattern(&name) {
+                return Err(de::Error::custom(format!(
+                    "{} is a pattern, which already matches every instance",
+                    name
+                )));
+            }
+            check_priority(&name, info.lock, info.priority)?;
+            for (shortname, port_info) in info.ports.iter() {
+                let fullname = NamePattern::join(&name, shortname);
+                check_pattern(&fullname)?;
+                check_priority(&fullname, port_info.lock, port_info.priority)?;
+                check_connections(port_info)?;
+            }
+            Ok(LockEntry::Client { name, info })
+        }
+    }
+}
+
+/// Checks that `raw`, if it is a glob or regex rather than a plain name, is a valid one.
+fn check_pattern<E: de::Error>(raw: &str) -> Result<(), E> {
+    if NamePattern::is_pattern(raw) {
+        NamePattern::new(raw).map_err(|e| E::custom(format!("invalid pattern {}: {}", raw, e)))?;
+    }
+    Ok(())
+}
+
+/// Reads the list of bundles under the `@bundles` key.
+fn next_bundles<'de, A>(map: &mut A) -> Result<Vec<LockEntry>, A::Error>
+where
+    A: MapAccess<'de>,
+{
+    let bundles: Vec<Bundle> = map.next_value()?;
+    for bundle in bundles.iter() {
+        for end in [&bundle.from, &bundle.to].iter() {
+            match end {
+                BundleEnd::Matching(raw) => check_pattern(raw)?,
+                BundleEnd::Listed(names) => {
+                    if let Some(name) = names
+                        .iter()
+                        .find(|name| NamePattern::is_pattern(name.as_ref()))
+                    {
+                        return Err(de::Error::custom(format!(
+                            "{} is a pattern, but bundle ports listed in order must be plain names",
+                            name
+                        )));
+                    }
+                }
+            }
+        }
+    }
+    Ok(bundles.into_iter().map(LockEntry::Bundle).collect())
+}
+
+/// Checks that the entry for `key` only sets a priority alongside a lock, since the
+/// priority only ranks that lock against others.
+fn check_priority<E: de::Error>(
+    key: &str,
+    lock: Option<LockStatus>,
+    priority: Option<i32>,
+) -> Result<(), E> {
+    if priority.is_some() && lock.is_none() {
+        return Err(E::custom(format!("{} sets a priority but no lock", key)));
+    }
+    Ok(())
+}
+
+fn check_connections<E: de::Error>(info: &PortInfo) -> Result<(), E> {
+    for other in info.connections.iter() {
+        check_pattern(other.as_ref())?;
+    }
+    Ok(())
+}
+
 struct LockEntryVisitor {}
 impl<'de> Visitor<'de> for LockEntryVisitor {
     type Value = ConfigFile;
@@ -58,21 +333,101 @@ impl<'de> Visitor<'de> for LockEntryVisitor {
     where
         A: MapAccess<'de>,
     {
-        let mut entries = Vec::new();
+        let mut retvl = ConfigFile::default();
+        while let Some(rawkey) = map.next_key::<String>()? {
+            if rawkey == INCLUDE_KEY {
+                retvl.includes = map.next_value()?;
+            } else if rawkey == ACTIVE_PROFILE_KEY {
+                retvl.active_profile = Some(map.next_value()?);
+            } else if rawkey == PROFILES_KEY {
+                retvl.profiles = map.next_value()?;
+            } else if rawkey == CONDITIONAL_KEY {
+                retvl.conditional = map.next_value()?;
+            } else if rawkey == BUNDLES_KEY {
+                retvl.entries.extend(next_bundles(&mut map)?);
+            } else {
+                retvl.entries.push(next_entry(&mut map, rawkey)?);
+            }
+        }
+        Ok(retvl)
+    }
+}
+
+struct ProfileVisitor {}
+impl<'de> Visitor<'de> for ProfileVisitor {
+    type Value = ProfileFile;
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "a profile with the profiles it inherits from and its own locks and connections"
+        )
+    }
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut retvl = ProfileFile::default();
+        while let Some(rawkey) = map.next_key::<String>()? {
+            if rawkey == INHERITS_KEY {
+                retvl.inherits = map.next_value()?;
+            } else if rawkey == ACTIVE_PROFILE_KEY
+                || rawkey == PROFILES_KEY
+                || rawkey == CONDITIONAL_KEY
+            {
+                return Err(de::Error::custom(format!(
+                    "{} can only be set at the top level, not inside a profile",
+                    rawkey
+                )));
+            } else if rawkey == BUNDLES_KEY {
+                retvl.entries.extend(next_bundles(&mut map)?);
+            } else {
+                retvl.entries.push(next_entry(&mut map, rawkey)?);
+            }
+        }
+        Ok(retvl)
+    }
+}
+
+struct ConditionalVisitor {}
+impl<'de> Visitor<'de> for ConditionalVisitor {
+    type Value = ConditionalFile;
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "a set of locks and connections with the names that must be present or absent for it to apply"
+        )
+    }
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut retvl = ConditionalFile::default();
         while let Some(rawkey) = map.next_key::<String>()? {
-            match PortFullname::new(rawkey.clone()) {
-                Ok(name) => {
-                    let info = map.next_value()?;
-                    entries.push(LockEntry::Port { name, info });
+            if rawkey == WHEN_PRESENT_KEY || rawkey == WHEN_ABSENT_KEY {
+                let names: Vec<String> = map.next_value()?;
+                for name in names.iter() {
+                    check_pattern(name)?;
                 }
-                Err(_) => {
-                    let name = rawkey;
-                    let info = map.next_value()?;
-                    entries.push(LockEntry::Client { name, info });
+                if rawkey == WHEN_PRESENT_KEY {
+                    retvl.when_present = names;
+                } else {
+                    retvl.when_absent = names;
                 }
+            } else if rawkey == ACTIVE_PROFILE_KEY
+                || rawkey == PROFILES_KEY
+                || rawkey == CONDITIONAL_KEY
+            {
+                return Err(de::Error::custom(format!(
+                    "{} can only be set at the top level, not inside a conditional rule set",
+                    rawkey
+                )));
+            } else if rawkey == BUNDLES_KEY {
+                retvl.entries.extend(next_bundles(&mut map)?);
+            } else {
+                retvl.entries.push(next_entry(&mut map, rawkey)?);
             }
         }
-        Ok(ConfigFile { entries })
+        Ok(retvl)
     }
 }
 
@@ -80,17 +435,26 @@ impl<'de> Visitor<'de> for LockEntryVisitor {
 pub struct ClientInfo {
     #[serde(default)]
     pub lock: Option<LockStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<InstanceMode>,
+    /// The ports nested under the client, in the order they were declared.
     #[serde(flatten, default)]
-    pub ports: HashMap<String, PortInfo>,
+    pub ports: IndexMap<String, PortInfo>,
+    /// The config file the entry was read from, if known.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
 }
 
 impl ClientInfo {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_lock(self, lock: LockStatus) -> Self {
+    pub fn with_rule(self, rule: &LockRule) -> Self {
         Self {
-            lock: Some(lock),
+            lock: Some(rule.lock),
+            priority: Some(rule.priority).filter(|priority| *priority != 0),
             ..self
         }
     }
@@ -100,12 +464,18 @@ impl ClientInfo {
 pub struct PortInfo {
     #[serde(default)]
     pub lock: Option<LockStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
     #[serde(default)]
     pub connections: Vec<PortFullname>,
+    /// The config file the entry was read from, if known.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
 }
 
 impl PortInfo {
-    pub fn set_lock(&mut self, lock: LockStatus) {
-        self.lock = Some(lock);
+    pub fn set_rule(&mut self, rule: &LockRule) {
+        self.lock = Some(rule.lock);
+        self.priority = Some(rule.priority).filter(|priority| *priority != 0);
     }
 }
@@ -0,0 +1,237 @@
+use regex::Regex;
+use std::hash::{Hash, Hasher};
+
+/// A config key that can match more than one name.
+///
+/// Keys containing `*` (any run of characters) or `?` (any single character) are globs,
+/// such as `system:playback_*`.
+/// Keys wrapped in slashes are regular expressions, such as `/system:playback_[0-9]+/`,
+/// and must match the whole name rather than just part of it.
+/// Anything else only matches that exact name.
+///
+/// To match a name that itself contains `*` or `?`, escape them with a backslash:
+/// `'Mixer \*Main\*'` (a single-quoted TOML key, so the backslashes are kept) only
+/// matches the client called `Mixer *Main*`.
+///
+/// As with plain names, a key containing `:` matches full port names while any other
+/// key matches client names, so a regex on client names can't use `:`.
+#[derive(Debug, Clone)]
+pub struct NamePattern {
+    raw: String,
+    kind: PatternKind,
+    regex: Option<Regex>,
+}
+
+/// The kinds of key a `NamePattern` can be made from, ordered from least to most specific.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PatternKind {
+    Regex,
+    Glob,
+    Exact,
+}
+
+impl NamePattern {
+    pub fn new(raw: &str) -> Result<Self, regex::Error> {
+        let kind = Self::kind_of(raw);
+        let regex = match kind {
+            PatternKind::Exact => None,
+            _ => Some(Regex::new(&format!("^(?:{})$", Self::regex_source(raw)))?),
+        };
+        Ok(Self {
+            raw: raw.to_owned(),
+            kind,
+            regex,
+        })
+    }
+    /// Checks whether `raw` is a glob or regex rather than an exact name.
+    pub fn is_pattern(raw: &str) -> bool {
+        Self::kind_of(raw) != PatternKind::Exact
+    }
+    /// Escapes any `*` or `?` in `name`, giving a key that only matches `name` itself.
+    pub fn escape(name: &str) -> String {
+        let mut retvl = String::with_capacity(name.len());
+        for c in name.chars() {
+            if c == '*' || c == '?' {
+                retvl.push('\\');
+            }
+            retvl.push(c);
+        }
+        retvl
+    }
+    fn kind_of(raw: &str) -> PatternKind {
+        if raw.len() > 2 && raw.starts_with('/') && raw.ends_with('/') {
+            PatternKind::Regex
+        } else if raw.contains(&['*', '?'][..]) {
+            // Escaped wildcards still make a glob, just one that matches them literally.
+            PatternKind::Glob
+        } else {
+            PatternKind::Exact
+        }
+    }
+    /// Splits a glob into its literal characters and wildcards.
+    fn glob_tokens(raw: &str) -> Vec<GlobToken> {
+        let mut retvl = Vec::with_capacity(raw.len());
+        let mut chars = raw.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '\\' if chars.peek() == Some(&'*') || chars.peek() == Some(&'?') => {
+                    GlobToken::Literal(chars.next().unwrap())
+                }
+                '*' => GlobToken::AnyRun,
+                '?' => GlobToken::AnyOne,
+                c => GlobToken::Literal(c),
+            };
+            retvl.push(token);
+        }
+        retvl
+    }
+    /// Converts `raw` into an unanchored regular expression matching the same names.
+    fn regex_source(raw: &str) -> String {
+        match Self::kind_of(raw) {
+            PatternKind::Regex => raw[1..raw.len() - 1].to_owned(),
+            PatternKind::Glob => {
+                let mut retvl = String::with_capacity(raw.len() * 2);
+                for token in Self::glob_tokens(raw) {
+                    match token {
+                        GlobToken::AnyRun => retvl.push_str(".*"),
+                        GlobToken::AnyOne => retvl.push('.'),
+                        GlobToken::Literal(c) => {
+                            retvl.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])))
+                        }
+                    }
+                }
+                retvl
+            }
+            PatternKind::Exact => regex::escape(raw),
+        }
+    }
+    /// Combines a client key with the key of a port nested under it into a single key
+    /// matching full port names.
+    pub fn join(client: &str, port: &str) -> String {
+        let has_regex = Self::kind_of(client) == PatternKind::Regex
+            || Self::kind_of(port) == PatternKind::Regex;
+        if has_regex {
+            format!(
+                "/(?:{}):(?:{})/",
+                Self::regex_source(client),
+                Self::regex_source(port)
+            )
+        } else {
+            format!("{}:{}", client, port)
+        }
+    }
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+    pub fn matches(&self, name: &str) -> bool {
+        match self.regex.as_ref() {
+            Some(regex) => regex.is_match(name),
+            None => self.raw == name,
+        }
+    }
+    /// Ranks how narrowly the pattern picks out names, so that the most specific of
+    /// several matching patterns can be chosen.
+    /// Exact names beat globs, which beat regexes; between two keys of the same kind,
+    /// the one with more characters that must match literally wins, and then the one
+    /// with fewer `*` wildcards.
+    pub fn specificity(&self) -> (PatternKind, usize, usize) {
+        let (literal_chars, fixed_chars) = match self.kind {
+            PatternKind::Exact => {
+                let len = self.raw.chars().count();
+                (len, len)
+            }
+            PatternKind::Glob => {
+                let tokens = Self::glob_tokens(&self.raw);
+                let is_literal = |token: &&GlobToken| matches!(token, GlobToken::Literal(_));
+                (
+                    tokens.iter().filter(is_literal).count(),
+                    tokens.iter().filter(|t| **t != GlobToken::AnyRun).count(),
+                )
+            }
+            PatternKind::Regex => {
+                let len = self.raw[1..self.raw.len() - 1]
+                    .chars()
+                    .filter(|c| !"\\.+*?()|[]{}^$".contains(*c))
+                    .count();
+                (len, len)
+            }
+        };
+        (self.kind, literal_chars, fixed_chars)
+    }
+}
+
+/// A single piece of a glob.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum GlobToken {
+    Literal(char),
+    /// `*`
+    AnyRun,
+    /// `?`
+    AnyOne,
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl Eq for NamePattern {}
+
+impl Hash for NamePattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.raw.hash(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_patterns() {
+        let glob = NamePattern::new("system:playback_*").unwrap();
+        assert_eq!(glob.specificity().0, PatternKind::Glob);
+        assert!(glob.matches("system:playback_12"));
+        assert!(!glob.matches("system:capture_1"));
+        assert!(!glob.matches("my_system:playback_1"));
+
+        let single = NamePattern::new("system:playback_?").unwrap();
+        assert!(single.matches("system:playback_1"));
+        assert!(!single.matches("system:playback_12"));
+
+        let regex = NamePattern::new("/system:playback_[0-9]+/").unwrap();
+        assert_eq!(regex.specificity().0, PatternKind::Regex);
+        assert!(regex.matches("system:playback_12"));
+        assert!(!regex.matches("system:playback_12_b"));
+
+        let exact = NamePattern::new("system:playback_1").unwrap();
+        assert!(exact.matches("system:playback_1"));
+        assert!(!exact.matches("system:playback_12"));
+
+        assert!(NamePattern::new("/playback_(/").is_err());
+        assert!(exact.specificity() > single.specificity());
+        assert!(single.specificity() > glob.specificity());
+        assert!(glob.specificity() > regex.specificity());
+
+        assert_eq!(NamePattern::join("synth", "out_*"), "synth:out_*");
+        let joined = NamePattern::new(&NamePattern::join("/synth|drums/", "out.L")).unwrap();
+        assert!(joined.matches("drums:out.L"));
+        assert!(!joined.matches("drums:outxL"));
+    }
+
+    #[test]
+    fn test_escaped_wildcards() {
+        let raw = NamePattern::escape("Mixer *Main?");
+        assert_eq!(raw, "Mixer \\*Main\\?");
+        let literal = NamePattern::new(&raw).unwrap();
+        assert!(literal.matches("Mixer *Main?"));
+        assert!(!literal.matches("Mixer Main2"));
+        assert!(!literal.matches("Mixer *Main"));
+
+        let glob = NamePattern::new("Mixer \\*Main*").unwrap();
+        assert!(glob.matches("Mixer *Main 2"));
+        assert!(!glob.matches("Mixer Main 2"));
+        assert!(literal.specificity() > glob.specificity());
+    }
+}
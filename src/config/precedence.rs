@@ -0,0 +1,186 @@
+use super::{LockStatus, PatternKind};
+use crate::model::PortFullname;
+use std::borrow::Cow;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The part of a config a rule was declared in.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum RuleLayer {
+    /// The rules shared by every profile.
+    Shared,
+    Profile(String),
+    Conditional(String),
+}
+
+/// Where a rule was declared, shown as the header of the TOML table holding it followed
+/// by the file it is in, if known.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RuleSource {
+    pub layer: RuleLayer,
+    /// The keys leading to the table within its layer, such as `["synth", "out"]`.
+    pub path: Vec<String>,
+    /// The config file holding the table, if the rule was read from one.
+    pub file: Option<PathBuf>,
+}
+
+impl RuleSource {
+    pub fn new(path: Vec<String>, file: Option<PathBuf>) -> Self {
+        Self {
+            layer: RuleLayer::Shared,
+            path,
+            file,
+        }
+    }
+}
+
+impl fmt::Display for RuleSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match &self.layer {
+            RuleLayer::Shared => vec![],
+            RuleLayer::Profile(name) => vec!["@profiles", name.as_str()],
+            RuleLayer::Conditional(name) => vec!["@conditional", name.as_str()],
+        };
+        let keys: Vec<_> = prefix
+            .into_iter()
+            .chain(self.path.iter().map(|key| key.as_str()))
+            .map(toml_key)
+            .collect();
+        write!(f, "[{}]", keys.join("."))?;
+        if let Some(file) = self.file.as_ref() {
+            write!(f, " in {}", file.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes `raw` if it can't be written as a bare TOML key.
+fn toml_key(raw: &str) -> Cow<'_, str> {
+    let is_bare = !raw.is_empty()
+        && raw
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if is_bare {
+        Cow::Borrowed(raw)
+    } else {
+        Cow::Owned(format!("{:?}", raw))
+    }
+}
+
+/// A lock set by a single entry in a config.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LockRule {
+    pub lock: LockStatus,
+    /// An explicit rank for the rule, which decides between equally specific rules
+    /// declared in the same layer; defaults to 0.
+    ///
+    /// Declaration order only counts for more than priority between layers: within a
+    /// layer, priority is compared before the position a rule was declared at. No two
+    /// rules share a position, so if the whole declaration order came first, priority
+    /// would never get to decide anything.
+    pub priority: i32,
+    /// When the rule was declared: the number of the layer of rules it was merged in
+    /// with, then its position within that layer.
+    pub order: (usize, usize),
+    pub source: RuleSource,
+}
+
+/// The kinds of thing a lock rule can be keyed on, from least to most specific.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum RuleScope {
+    Client,
+    Class,
+    Port,
+}
+
+/// How narrowly a rule picks out the ports it applies to: rules keyed on ports beat rules
+/// on classes of ports, which beat rules on clients, and then keys are compared with
+/// `NamePattern::specificity`.
+pub type Specificity = (RuleScope, (PatternKind, usize, usize));
+
+/// A rule that applies to a client or port, along with how specifically it matched.
+#[derive(Debug, Copy, Clone)]
+pub struct MatchedRule<'a> {
+    pub rule: &'a LockRule,
+    pub specificity: Specificity,
+}
+
+impl<'a> MatchedRule<'a> {
+    /// Ranks the rule against others applying to the same thing by its specificity, then
+    /// by the layer it was declared in, with later layers ranking higher, and then by its
+    /// explicit priority.
+    pub fn strength(&self) -> (Specificity, usize, i32) {
+        (self.specificity, self.rule.order.0, self.rule.priority)
+    }
+    /// Ranks the rule by its strength, and then by its position within its layer, with
+    /// later rules ranking higher.
+    pub fn precedence(&self) -> ((Specificity, usize, i32), usize) {
+        (self.strength(), self.rule.order.1)
+    }
+}
+
+/// A pair of rules whose effects clash, found when a config is loaded.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RuleConflict {
+    /// Two equally strong rules set different locks on one client or port, so only the
+    /// order they were declared in within their layer decides between them.
+    Ambiguous {
+        subject: String,
+        winner: RuleSource,
+        loser: RuleSource,
+    },
+    /// A listed connection is forced by the lock on one end, but a stronger rule blocks
+    /// the other end, so the connection is never made.
+    BlockedConnection {
+        ports: (PortFullname, PortFullname),
+        forced_by: RuleSource,
+        blocked_by: RuleSource,
+    },
+}
+
+impl fmt::Display for RuleConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleConflict::Ambiguous {
+                subject,
+                winner,
+                loser,
+            } => write!(
+                f,
+                "{} is locked by both {} and {}, which are equally strong; {} wins only because \
+                 it was declared later, so give one of them a priority",
+                subject, winner, loser, winner
+            ),
+            RuleConflict::BlockedConnection {
+                ports: (a, b),
+                forced_by,
+                blocked_by,
+            } => write!(
+                f,
+                "the connection between {} and {} is forced by {} but blocked by {}, which \
+                 takes precedence, so it is never made",
+                a, b, forced_by, blocked_by
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_sources() {
+        let shared = RuleSource::new(vec!["synth".to_owned(), "out_1".to_owned()], None);
+        assert_eq!(shared.to_string(), "[synth.out_1]");
+        let profile = RuleSource {
+            layer: RuleLayer::Profile("live".to_owned()),
+            path: vec!["system:playback_*".to_owned()],
+            file: Some(PathBuf::from("conf.d/live.toml")),
+        };
+        assert_eq!(
+            profile.to_string(),
+            r#"["@profiles".live."system:playback_*"] in conf.d/live.toml"#
+        );
+    }
+}
@@ -0,0 +1,690 @@
+use super::{
+    parsing, Bundle, ClientBindings, InstanceMode, LockRule, LockStatus, MatchedRule, NamePattern,
+    PatternKind, PortClass, RuleConflict, RuleLayer, RuleScope, RuleSource,
+};
+use crate::graph::PortGraph;
+use crate::model::{PortCategory, PortData, PortDirection, PortFullname};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+
+/// A set of locks and connections, such as the rules shared by every profile in a
+/// config or the rules a single profile adds on top of them.
+///
+/// Client and port keys, as well as either end of a connection, can be globs or regexes;
+/// see `NamePattern`.
+/// Connections can also be listed in bulk as bundles; see `Bundle`.
+///
+/// When several locks apply to the same client or port, the one that wins is picked by:
+///
+/// 1. its specificity: locks on a port beat locks on a class of ports, which beat locks
+///    on a client, and exact names beat patterns; see `NamePattern::specificity`;
+/// 2. then the layer it was declared in, with later layers winning: the rules of a
+///    profile come after the shared rules and those of the profiles it inherits from,
+///    and conditional rule sets come after the active profile;
+/// 3. then its `priority`, if set on the entry; higher priorities win, and the default
+///    is 0;
+/// 4. then its position within its layer, with later locks winning, which is reported
+///    as a conflict when the locks differ; see `RuleConflict::Ambiguous`.
+///
+/// Priority comes before the position within a layer so that it can override the order
+/// entries are written in; see `LockRule::priority`.
+///
+/// A listed connection is forced if either end is forced, unless a lock blocking the
+/// other end is stronger by the first three measures.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct LockRules {
+    client_locks: HashMap<String, LockRule>,
+    port_locks: HashMap<PortFullname, LockRule>,
+    class_locks: HashMap<PortClass, LockRule>,
+    /// Locks on clients picked out by a pattern, most specific first.
+    client_pattern_locks: Vec<(NamePattern, LockRule)>,
+    /// Locks on ports picked out by a pattern, most specific first.
+    port_pattern_locks: Vec<(NamePattern, LockRule)>,
+    /// Every listed connection, by the names or patterns used in the config.
+    connections_list: Vec<(PortFullname, PortFullname)>,
+    /// The listed connections with a pattern at either end.
+    pattern_connections: Vec<(NamePattern, NamePattern)>,
+    /// The instance mode of each client entry that has one.
+    client_instances: HashMap<String, InstanceMode>,
+    bundles: Vec<Bundle>,
+    /// The number of other sets of rules merged into these ones by `extend`.
+    layers: usize,
+    /// The number of locks declared directly in these rules, used to number the next one.
+    declared: usize,
+}
+
+impl LockRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Builds rules that recreate every connection currently in `graph`,
+    /// giving each client in the graph the lock picked for it by `client_lock`.
+    pub fn snapshot<F: FnMut(&str) -> LockStatus>(graph: &PortGraph, mut client_lock: F) -> Self {
+        let mut retvl = LockRules::new();
+        // Live names can contain wildcards, which have to be escaped to stay literal.
+        for client in graph.all_clients() {
+            let key = NamePattern::escape(client);
+            let rule = retvl.declare(client_lock(client), None, vec![key.clone()], None);
+            retvl.set_client_lock(key, rule);
+        }
+        let escape_port = |name: &PortFullname| {
+            PortFullname::new(NamePattern::escape(name.as_ref())).unwrap_or_else(|_| name.clone())
+        };
+        for (a, b) in graph.all_connections() {
+            retvl.add_connection(escape_port(&a.name), escape_port(&b.name));
+        }
+        retvl
+    }
+    /// Marks every lock in these rules as declared in `layer`.
+    pub fn in_layer(mut self, layer: RuleLayer) -> Self {
+        let rules = self
+            .client_locks
+            .values_mut()
+            .chain(self.port_locks.values_mut())
+            .chain(self.class_locks.values_mut())
+            .chain(self.client_pattern_locks.iter_mut().map(|(_, rule)| rule))
+            .chain(self.port_pattern_locks.iter_mut().map(|(_, rule)| rule));
+        for rule in rules {
+            rule.source.layer = layer.clone();
+        }
+        self
+    }
+    /// Adds the rules in `other` on top of these ones.
+    /// Locks in `other` replace any set on the same key here and count as declared after
+    /// every lock already here, while the connections listed in both are kept.
+    pub fn extend(&mut self, other: &LockRules) {
+        self.layers += 1;
+        let layer = self.layers;
+        let restamp = |rule: &LockRule| LockRule {
+            order: (layer, rule.order.1),
+            ..rule.clone()
+        };
+        for (name, rule) in other.client_locks.iter() {
+            self.set_client_lock(name.clone(), restamp(rule));
+        }
+        for (name, rule) in other.port_locks.iter() {
+            self.set_port_lock(name.clone(), restamp(rule));
+        }
+        for (pattern, rule) in other.client_pattern_locks.iter() {
+            self.set_client_lock(pattern.raw().to_owned(), restamp(rule));
+        }
+        for (pattern, rule) in other.port_pattern_locks.iter() {
+            let name = PortFullname::new(pattern.raw().to_owned()).unwrap();
+            self.set_port_lock(name, restamp(rule));
+        }
+        for (class, rule) in other.class_locks.iter() {
+            self.class_locks.insert(*class, restamp(rule));
+        }
+        self.client_instances.extend(
+            other
+                .client_instances
+                .iter()
+                .map(|(name, mode)| (name.clone(), *mode)),
+        );
+        for (a, b) in other.connections_list.iter() {
+            self.add_connection(a.clone(), b.clone());
+        }
+        for bundle in other.bundles.iter() {
+            self.add_bundle(bundle.clone());
+        }
+    }
+    /// Makes a lock rule declared after every other one in these rules, in the table at
+    /// `path` in `file`.
+    fn declare(
+        &mut self,
+        lock: LockStatus,
+        priority: Option<i32>,
+        path: Vec<String>,
+        file: Option<PathBuf>,
+    ) -> LockRule {
+        self.declared += 1;
+        LockRule {
+            lock,
+            priority: priority.unwrap_or_default(),
+            order: (self.layers, self.declared),
+            source: RuleSource::new(path, file),
+        }
+    }
+    fn set_client_lock(&mut self, name: String, rule: LockRule) {
+        if NamePattern::is_pattern(&name) {
+            set_pattern_lock(&mut self.client_pattern_locks, &name, rule);
+        } else {
+            self.client_locks.insert(name, rule);
+        }
+    }
+    fn set_port_lock(&mut self, name: PortFullname, rule: LockRule) {
+        if NamePattern::is_pattern(name.as_ref()) {
+            set_pattern_lock(&mut self.port_pattern_locks, name.as_ref(), rule);
+        } else {
+            self.port_locks.insert(name, rule);
+        }
+    }
+    /// Lists a connection between `a` and `b`, keeping the list sorted and free of
+    /// duplicates.
+    fn add_connection(&mut self, a: PortFullname, b: PortFullname) {
+        let connection = if a > b { (b, a) } else { (a, b) };
+        let idx = match self.connections_list.binary_search(&connection) {
+            Ok(_) => {
+                return;
+            }
+            Err(idx) => idx,
+        };
+        let (a, b) = &connection;
+        if NamePattern::is_pattern(a.as_ref()) || NamePattern::is_pattern(b.as_ref()) {
+            let a = NamePattern::new(a.as_ref()).unwrap();
+            let b = NamePattern::new(b.as_ref()).unwrap();
+            self.pattern_connections.push((a, b));
+        }
+        self.connections_list.insert(idx, connection);
+    }
+    fn add_bundle(&mut self, bundle: Bundle) {
+        if !self.bundles.contains(&bundle) {
+            self.bundles.push(bundle);
+        }
+    }
+    /// Gets the instance mode of each client entry that has one.
+    pub fn client_instances(&self) -> &HashMap<String, InstanceMode> {
+        &self.client_instances
+    }
+    /// Gets every client named exactly, rather than through a pattern, by a lock,
+    /// instance mode, or listed connection in these rules.
+    pub fn named_clients(&self) -> HashSet<&str> {
+        let ports = self
+            .port_locks
+            .keys()
+            .chain(self.connections_list.iter().flat_map(|(a, b)| vec![a, b]))
+            .filter(|name| !NamePattern::is_pattern(name.as_ref()))
+            .map(|name| name.client_name());
+        self.client_locks
+            .keys()
+            .chain(self.client_instances.keys())
+            .map(|name| name.as_str())
+            .chain(ports)
+            .collect()
+    }
+    /// Gets every lock that applies to `client`.
+    /// Entries with an instance mode are looked up through `bindings`, while patterns are
+    /// always matched against the client's actual name.
+    fn client_rules(&self, client: &str, bindings: &ClientBindings) -> Vec<MatchedRule<'_>> {
+        let mut retvl = Vec::new();
+        if let Some(entry) = bindings.entry_for(client) {
+            if let Some(rule) = self.client_locks.get(entry) {
+                let specificity = (RuleScope::Client, exact_specificity(entry));
+                retvl.push(MatchedRule { rule, specificity });
+            }
+        }
+        for (pattern, rule) in self.client_pattern_locks.iter() {
+            if pattern.matches(client) {
+                let specificity = (RuleScope::Client, pattern.specificity());
+                retvl.push(MatchedRule { rule, specificity });
+            }
+        }
+        retvl
+    }
+    /// Gets every lock that applies to `port`, matching config keys against both its name
+    /// and its aliases.
+    fn port_rules(&self, port: &PortData, bindings: &ClientBindings) -> Vec<MatchedRule<'_>> {
+        let mut retvl = Vec::new();
+        for name in port.all_names() {
+            let entry = match bindings.entry_port(name) {
+                Some(entry) => entry,
+                None => {
+                    continue;
+                }
+            };
+            if let Some(rule) = self.port_locks.get(&*entry) {
+                let specificity = (RuleScope::Port, exact_specificity((*entry).as_ref()));
+                retvl.push(MatchedRule { rule, specificity });
+            }
+        }
+        for (pattern, rule) in self.port_pattern_locks.iter() {
+            if matches_port(pattern, port) {
+                let specificity = (RuleScope::Port, pattern.specificity());
+                retvl.push(MatchedRule { rule, specificity });
+            }
+        }
+        for (class, rule) in self.class_locks.iter() {
+            if class.matches(port) {
+                let specificity = (RuleScope::Class, (PatternKind::Exact, 0, 0));
+                retvl.push(MatchedRule { rule, specificity });
+            }
+        }
+        let clients: BTreeSet<_> = port.all_names().map(|name| name.client_name()).collect();
+        for client in clients {
+            retvl.extend(self.client_rules(client, bindings));
+        }
+        retvl
+    }
+    /// Gets the lock that decides the lock status of `client`, if any applies.
+    pub fn client_rule(&self, client: &str, bindings: &ClientBindings) -> Option<MatchedRule<'_>> {
+        strongest(self.client_rules(client, bindings))
+    }
+    /// Gets the lock that decides the lock status of `port`, if any applies.
+    pub fn port_rule(&self, port: &PortData, bindings: &ClientBindings) -> Option<MatchedRule<'_>> {
+        strongest(self.port_rules(port, bindings))
+    }
+    pub fn client_status(&self, client: &str, bindings: &ClientBindings) -> LockStatus {
+        self.client_rule(client, bindings)
+            .map(|matched| matched.rule.lock)
+            .unwrap_or_default()
+    }
+    pub fn port_status(&self, port: &PortData, bindings: &ClientBindings) -> LockStatus {
+        self.port_rule(port, bindings)
+            .map(|matched| matched.rule.lock)
+            .unwrap_or_default()
+    }
+    /// Gets the connections every bundle makes in `graph`, as `(output, input)` pairs of
+    /// port names.
+    pub fn bundle_connections(
+        &self,
+        graph: &PortGraph,
+        bindings: &ClientBindings,
+    ) -> Vec<(PortFullname, PortFullname)> {
+        let mut retvl = Vec::new();
+        for connection in self
+            .bundles
+            .iter()
+            .flat_map(|bundle| bundle.connections(graph, bindings))
+        {
+            if !retvl.contains(&connection) {
+                retvl.push(connection);
+            }
+        }
+        retvl
+    }
+    /// Gets every connection that should be forced in `graph`, as `(output, input)`
+    /// pairs of port names.
+    /// Connections listed with a pattern at either end are expanded into every pair of
+    /// compatible ports the patterns match, those naming a client entry with an
+    /// instance mode into every pair of bound ports, and bundles into the pairs of
+    /// channels they connect.
+    pub fn forced_connections(
+        &self,
+        graph: &PortGraph,
+        bindings: &ClientBindings,
+    ) -> Vec<(PortFullname, PortFullname)> {
+        let mut candidates = Vec::new();
+        for (a, b) in self.connections_list.iter() {
+            if NamePattern::is_pattern(a.as_ref()) || NamePattern::is_pattern(b.as_ref()) {
+                continue;
+            }
+            let b_bound = bindings.bound_ports(b);
+            for a in bindings.bound_ports(a).iter() {
+                for b in b_bound.iter() {
+                    if let Some(ports) = graph.find_port(a).zip(graph.find_port(b)) {
+                        candidates.push(ports);
+                    }
+                }
+            }
+        }
+        for (a, b) in self.pattern_connections.iter() {
+            let a_ports = graph.all_ports().filter(|port| matches_port(a, port));
+            for a_port in a_ports {
+                let b_ports = graph.all_ports().filter(|port| matches_port(b, port));
+                candidates.extend(b_ports.map(|b_port| (a_port, b_port)));
+            }
+        }
+        let bundled = self.bundle_connections(graph, bindings);
+        for (a, b) in bundled.iter() {
+            if let Some(ports) = graph.find_port(a).zip(graph.find_port(b)) {
+                candidates.push(ports);
+            }
+        }
+        let mut retvl = Vec::new();
+        for (a, b) in candidates {
+            let (src, dst) = match (a.direction.is_output(), b.direction.is_output()) {
+                (true, false) => (a, b),
+                (false, true) => (b, a),
+                _ => {
+                    continue;
+                }
+            };
+            if !src.is_compatible(dst)
+                || !self
+                    .connection_status(src, dst, bindings, &bundled)
+                    .should_force()
+            {
+                continue;
+            }
+            let connection = (src.name.clone(), dst.name.clone());
+            if !retvl.contains(&connection) {
+                retvl.push(connection);
+            }
+        }
+        retvl
+    }
+    /// Checks whether the rules list a connection between `a` and `b`,
+    /// under either their names or their aliases, or whether `bundled` pairs them up.
+    fn has_connection(
+        &self,
+        a: &PortData,
+        b: &PortData,
+        bindings: &ClientBindings,
+        bundled: &[(PortFullname, PortFullname)],
+    ) -> bool {
+        let in_bundle = bundled
+            .iter()
+            .any(|(x, y)| (*x == a.name && *y == b.name) || (*x == b.name && *y == a.name));
+        if in_bundle {
+            return true;
+        }
+        let entry_names = |port: &PortData| {
+            port.all_names()
+                .filter_map(|name| bindings.entry_port(name))
+                .map(|name| name.into_owned())
+                .collect::<Vec<_>>()
+        };
+        let b_names = entry_names(b);
+        let listed = entry_names(a).iter().any(|a| {
+            b_names.iter().any(|b| {
+                let con_key = (a.min(b), a.max(b));
+                self.connections_list
+                    .binary_search_by_key(&con_key, |(a, b)| (a, b))
+                    .is_ok()
+            })
+        });
+        listed
+            || self.pattern_connections.iter().any(|(x, y)| {
+                (matches_port(x, a) && matches_port(y, b))
+                    || (matches_port(x, b) && matches_port(y, a))
+            })
+    }
+    /// Gets the lock status of the connection between `a` and `b`, where `bundled` holds
+    /// the connections bundles make in the current graph; see `bundle_connections`.
+    pub fn connection_status(
+        &self,
+        a: &PortData,
+        b: &PortData,
+        bindings: &ClientBindings,
+        bundled: &[(PortFullname, PortFullname)],
+    ) -> LockStatus {
+        let a_rule = self.port_rule(a, bindings);
+        let b_rule = self.port_rule(b, bindings);
+        if self.has_connection(a, b, bindings, bundled) {
+            match listed_connection_rules(a_rule, b_rule) {
+                (Some(forcing), Some(blocking)) if blocking.strength() > forcing.strength() => {
+                    LockStatus::Block
+                }
+                (Some(_), _) => LockStatus::Force,
+                (None, _) => LockStatus::None,
+            }
+        } else if a_rule
+            .iter()
+            .chain(b_rule.iter())
+            .any(|matched| matched.rule.lock.should_block())
+        {
+            LockStatus::Block
+        } else {
+            LockStatus::None
+        }
+    }
+    /// Finds the clashes between these rules that can be seen from the names in the
+    /// config alone, without knowing which clients and ports are running.
+    pub fn conflicts(&self) -> Vec<RuleConflict> {
+        let bindings = ClientBindings::default();
+        let named_port = |name: &PortFullname| {
+            PortData::new(name.clone(), PortCategory::Unknown, PortDirection::In)
+        };
+        let listed: Vec<_> = self
+            .connections_list
+            .iter()
+            .filter(|(a, b)| {
+                !NamePattern::is_pattern(a.as_ref()) && !NamePattern::is_pattern(b.as_ref())
+            })
+            .collect();
+        let ports: BTreeSet<&PortFullname> = self
+            .port_locks
+            .keys()
+            .chain(listed.iter().flat_map(|(a, b)| vec![a, b]))
+            .collect();
+        let clients: BTreeSet<&str> = self
+            .client_locks
+            .keys()
+            .map(|name| name.as_str())
+            .chain(ports.iter().map(|name| name.client_name()))
+            .collect();
+
+        let mut retvl = Vec::new();
+        for client in clients {
+            let rules = self.client_rules(client, &bindings);
+            retvl.extend(ambiguity(client, rules));
+        }
+        for port in ports.iter() {
+            let rules = self.port_rules(&named_port(port), &bindings);
+            retvl.extend(ambiguity(port.as_ref(), rules));
+        }
+        for (a, b) in listed {
+            let a_rule = self.port_rule(&named_port(a), &bindings);
+            let b_rule = self.port_rule(&named_port(b), &bindings);
+            if let (Some(forcing), Some(blocking)) = listed_connection_rules(a_rule, b_rule) {
+                if blocking.strength() > forcing.strength() {
+                    retvl.push(RuleConflict::BlockedConnection {
+                        ports: (a.clone(), b.clone()),
+                        forced_by: forcing.rule.source.clone(),
+                        blocked_by: blocking.rule.source.clone(),
+                    });
+                }
+            }
+        }
+        retvl
+    }
+}
+
+/// Gets the rule that takes precedence among `rules`.
+fn strongest(rules: Vec<MatchedRule<'_>>) -> Option<MatchedRule<'_>> {
+    rules.into_iter().max_by_key(|matched| matched.precedence())
+}
+
+/// Gets the strongest rule forcing a listed connection between ports whose locks come
+/// from `a_rule` and `b_rule`, and the strongest rule that blocks without forcing.
+fn listed_connection_rules<'a>(
+    a_rule: Option<MatchedRule<'a>>,
+    b_rule: Option<MatchedRule<'a>>,
+) -> (Option<MatchedRule<'a>>, Option<MatchedRule<'a>>) {
+    let rules = a_rule.into_iter().chain(b_rule);
+    let forcing = rules
+        .clone()
+        .filter(|matched| matched.rule.lock.should_force())
+        .max_by_key(|matched| matched.strength());
+    let blocking = rules
+        .filter(|matched| matched.rule.lock.should_block() && !matched.rule.lock.should_force())
+        .max_by_key(|matched| matched.strength());
+    (forcing, blocking)
+}
+
+/// Checks whether the two strongest of `rules`, which all apply to `subject`, set
+/// different locks and only their positions within their layer decide between them.
+fn ambiguity(subject: &str, mut rules: Vec<MatchedRule<'_>>) -> Option<RuleConflict> {
+    rules.sort_by_key(|matched| std::cmp::Reverse(matched.precedence()));
+    let (winner, loser) = match rules.as_slice() {
+        [winner, loser, ..] => (winner, loser),
+        _ => {
+            return None;
+        }
+    };
+    let is_ambiguous = winner.strength() == loser.strength() && winner.rule.lock != loser.rule.lock;
+    if !is_ambiguous {
+        return None;
+    }
+    Some(RuleConflict::Ambiguous {
+        subject: subject.to_owned(),
+        winner: winner.rule.source.clone(),
+        loser: loser.rule.source.clone(),
+    })
+}
+
+/// Gets the specificity of a key that matches only the exact name `name`.
+fn exact_specificity(name: &str) -> (PatternKind, usize, usize) {
+    let len = name.chars().count();
+    (PatternKind::Exact, len, len)
+}
+
+impl From<LockRules> for Vec<parsing::LockEntry> {
+    fn from(rules: LockRules) -> Self {
+        let mut client_map: HashMap<String, parsing::ClientInfo> = HashMap::new();
+        let mut port_map: HashMap<PortFullname, parsing::PortInfo> = HashMap::new();
+        // Entries are written in the order their locks were declared, so that rules which
+        // tie keep winning the same way when the config is read back in.
+        let mut client_orders: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut port_orders: HashMap<PortFullname, (usize, usize)> = HashMap::new();
+        let client_pattern_locks = rules
+            .client_pattern_locks
+            .into_iter()
+            .map(|(pattern, rule)| (pattern.raw().to_owned(), rule));
+        for (client_name, rule) in rules.client_locks.into_iter().chain(client_pattern_locks) {
+            client_orders.insert(client_name.clone(), rule.order);
+            client_map.insert(client_name, parsing::ClientInfo::new().with_rule(&rule));
+        }
+        for (client_name, mode) in rules.client_instances {
+            client_map.entry(client_name).or_default().instance = Some(mode);
+        }
+        let port_pattern_locks = rules.port_pattern_locks.into_iter().map(|(pattern, rule)| {
+            let name = PortFullname::new(pattern.raw().to_owned()).unwrap();
+            (name, rule)
+        });
+        let mut port_locks: Vec<_> = rules
+            .port_locks
+            .into_iter()
+            .chain(port_pattern_locks)
+            .collect();
+        port_locks.sort_by_key(|(name, rule)| (rule.order, name.clone()));
+        for (port_name, rule) in port_locks {
+            port_orders.insert(port_name.clone(), rule.order);
+            port_entry(&mut client_map, &mut port_map, port_name).set_rule(&rule);
+        }
+        for (first, second) in rules.connections_list {
+            port_entry(&mut client_map, &mut port_map, first.clone())
+                .connections
+                .push(second.clone());
+            port_entry(&mut client_map, &mut port_map, second)
+                .connections
+                .push(first);
+        }
+        let client_ents = client_map.into_iter().map(|(name, info)| {
+            let order = client_orders.get(&name).copied();
+            (
+                order,
+                name.clone(),
+                parsing::LockEntry::Client { name, info },
+            )
+        });
+        let port_ents = port_map.into_iter().map(|(name, info)| {
+            let order = port_orders.get(&name).copied();
+            let key = name.as_ref().to_owned();
+            (order, key, parsing::LockEntry::Port { name, info })
+        });
+        let class_ents = rules.class_locks.into_iter().map(|(class, rule)| {
+            let mut info = parsing::PortInfo::default();
+            info.set_rule(&rule);
+            let key = class.key().to_owned();
+            (
+                Some(rule.order),
+                key,
+                parsing::LockEntry::Class { class, info },
+            )
+        });
+        // Entries without a lock of their own only list connections, so they go last.
+        let mut ents: Vec<_> = client_ents.chain(port_ents).chain(class_ents).collect();
+        ents.sort_by(|(a_order, a_key, _), (b_order, b_key, _)| {
+            (a_order.is_none(), a_order, a_key).cmp(&(b_order.is_none(), b_order, b_key))
+        });
+        let bundle_ents = rules.bundles.into_iter().map(parsing::LockEntry::Bundle);
+        ents.into_iter()
+            .map(|(_, _, ent)| ent)
+            .chain(bundle_ents)
+            .collect()
+    }
+}
+
+/// Sets the lock on the pattern `raw` in `locks`, keeping the most specific patterns first.
+fn set_pattern_lock(locks: &mut Vec<(NamePattern, LockRule)>, raw: &str, rule: LockRule) {
+    if let Some(ent) = locks.iter_mut().find(|(pattern, _)| pattern.raw() == raw) {
+        ent.1 = rule;
+        return;
+    }
+    // Patterns are checked while parsing, so they always compile here.
+    let pattern = NamePattern::new(raw).unwrap();
+    locks.push((pattern, rule));
+    locks.sort_by(|(a, _), (b, _)| {
+        b.specificity()
+            .cmp(&a.specificity())
+            .then_with(|| a.raw().cmp(b.raw()))
+    });
+}
+
+/// Checks whether `pattern` matches the name or any alias of `port`.
+fn matches_port(pattern: &NamePattern, port: &PortData) -> bool {
+    port.all_names().any(|name| pattern.matches(name.as_ref()))
+}
+
+/// Gets the entry for `port_name` in a config file being built, creating it if needed.
+/// Ports whose client has its own entry are nested under it; the rest get their own
+/// top-level entry.
+fn port_entry<'a>(
+    client_map: &'a mut HashMap<String, parsing::ClientInfo>,
+    port_map: &'a mut HashMap<PortFullname, parsing::PortInfo>,
+    port_name: PortFullname,
+) -> &'a mut parsing::PortInfo {
+    match client_map.get_mut(port_name.client_name()) {
+        Some(client_info) => client_info
+            .ports
+            .entry(port_name.port_shortname().to_owned())
+            .or_default(),
+        None => port_map.entry(port_name).or_default(),
+    }
+}
+
+impl From<Vec<parsing::LockEntry>> for LockRules {
+    fn from(entries: Vec<parsing::LockEntry>) -> Self {
+        let mut retvl = LockRules::new();
+        for ent in entries {
+            match ent {
+                parsing::LockEntry::Client { name, info } => {
+                    if let Some(lock) = info.lock {
+                        let path = vec![name.clone()];
+                        let rule = retvl.declare(lock, info.priority, path, info.file.clone());
+                        retvl.set_client_lock(name.clone(), rule);
+                    }
+                    if let Some(mode) = info.instance {
+                        retvl.client_instances.insert(name.clone(), mode);
+                    }
+                    for (shortname, port_info) in info.ports.into_iter() {
+                        let raw_fullname = NamePattern::join(&name, &shortname);
+                        let fullname = PortFullname::try_from(raw_fullname).unwrap();
+                        if let Some(lock) = port_info.lock {
+                            let path = vec![name.clone(), shortname.clone()];
+                            let file = port_info.file.clone();
+                            let rule = retvl.declare(lock, port_info.priority, path, file);
+                            retvl.set_port_lock(fullname.clone(), rule);
+                        }
+                        for other in port_info.connections {
+                            retvl.add_connection(fullname.clone(), other);
+                        }
+                    }
+                }
+                parsing::LockEntry::Class { class, info } => {
+                    if let Some(lock) = info.lock {
+                        let path = vec![class.key().to_owned()];
+                        let rule = retvl.declare(lock, info.priority, path, info.file.clone());
+                        retvl.class_locks.insert(class, rule);
+                    }
+                }
+                parsing::LockEntry::Port { name, info } => {
+                    if let Some(lock) = info.lock {
+                        let path = vec![name.as_ref().to_owned()];
+                        let rule = retvl.declare(lock, info.priority, path, info.file.clone());
+                        retvl.set_port_lock(name.clone(), rule);
+                    }
+                    for other in info.connections {
+                        retvl.add_connection(name.clone(), other);
+                    }
+                }
+                parsing::LockEntry::Bundle(bundle) => {
+                    retvl.add_bundle(bundle);
+                }
+            }
+        }
+        retvl
+    }
+}
@@ -10,6 +10,10 @@ pub enum ArgError {
     InvalidFlag(String),
     #[error("Invalid config file passed: \"{0}\"")]
     InvalidPath(String),
+    #[error("Invalid lock level passed: \"{0}\"")]
+    InvalidLock(String),
+    #[error("Invalid profile passed: \"{0}\"")]
+    InvalidProfile(String),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -29,14 +33,18 @@ pub struct DaemonArgs {
     config_path: PathBuf,
     server_flag: Option<StartServerFlag>,
     client_name: Option<String>,
+    profile: Option<String>,
+    use_pipewire: bool,
 }
 
 impl DaemonArgs {
     pub fn from_args<I: Iterator<Item = S>, S: AsRef<str>>(iter: I) -> Result<Self, ArgError> {
         let mut iter = iter.skip(1).peekable();
         let mut client_name = None;
+        let mut profile = None;
         let mut server_flag = None;
         let mut config_path = None;
+        let mut use_pipewire = false;
         while let Some(cur_key) = iter.next() {
             if cur_key.as_ref() == "-c" {
                 let cur_val = iter
@@ -47,12 +55,23 @@ impl DaemonArgs {
                     return Err(ArgError::InvalidClientName);
                 }
                 client_name = Some(cur_val);
+            } else if cur_key.as_ref() == "-P" {
+                let cur_val = iter
+                    .next()
+                    .map(|s| s.as_ref().to_owned())
+                    .unwrap_or_default();
+                if cur_val.is_empty() {
+                    return Err(ArgError::InvalidProfile(cur_val));
+                }
+                profile = Some(cur_val);
             } else if cur_key.as_ref() == "-n" {
                 server_flag = Some(StartServerFlag::NoStart);
             } else if cur_key.as_ref() == "-s" {
                 server_flag = Some(StartServerFlag::StartServer);
             } else if cur_key.as_ref() == "-r" {
                 server_flag = Some(StartServerFlag::StartIfStopped);
+            } else if cur_key.as_ref() == "-p" {
+                use_pipewire = true;
             } else {
                 let raw_path = cur_key.as_ref();
                 let path = PathBuf::from(raw_path);
@@ -67,6 +86,8 @@ impl DaemonArgs {
             config_path,
             server_flag,
             client_name,
+            profile,
+            use_pipewire,
         })
     }
     pub fn config_path(&self) -> &Path {
@@ -76,7 +97,17 @@ impl DaemonArgs {
         const DEFAULT_NAME: &str = "trejdaemon";
         self.client_name.as_deref().unwrap_or(DEFAULT_NAME)
     }
+    /// Gets the profile to enforce in place of the one the config file selects, if any.
+    /// The daemon sticks to this profile even if the config file selects another one
+    /// while it runs.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
     pub fn server_flag(&self) -> StartServerFlag {
         self.server_flag.unwrap_or_default()
     }
+    /// Whether to talk to PipeWire through its command line tools instead of going through JACK.
+    pub fn use_pipewire(&self) -> bool {
+        self.use_pipewire
+    }
 }
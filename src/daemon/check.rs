@@ -0,0 +1,118 @@
+use super::{report_warnings, ArgError};
+use crate::config::{self, ConfigLint, ConfigProblem};
+use crate::graph::{GraphBackend, JackGraph, PipewireGraph};
+
+use std::path::{Path, PathBuf};
+
+pub struct CheckArgs {
+    config_path: PathBuf,
+    check_live: bool,
+    use_pipewire: bool,
+}
+
+impl CheckArgs {
+    /// Parses the arguments following the `check` subcommand:
+    /// `-l` to also compare the config against the live JACK graph, `-p` to compare it
+    /// against the PipeWire graph instead, and the config file to check.
+    pub fn from_args<I: Iterator<Item = S>, S: AsRef<str>>(iter: I) -> Result<Self, ArgError> {
+        let mut config_path = None;
+        let mut check_live = false;
+        let mut use_pipewire = false;
+        for cur_key in iter.skip(1) {
+            if cur_key.as_ref() == "-l" {
+                check_live = true;
+            } else if cur_key.as_ref() == "-p" {
+                check_live = true;
+                use_pipewire = true;
+            } else if cur_key.as_ref().starts_with('-') {
+                return Err(ArgError::InvalidFlag(cur_key.as_ref().to_owned()));
+            } else {
+                let raw_path = cur_key.as_ref();
+                let path = PathBuf::from(raw_path);
+                if config_path.is_some() || !path.is_file() {
+                    return Err(ArgError::InvalidPath(raw_path.to_owned()));
+                }
+                config_path = Some(path);
+            }
+        }
+        let config_path = config_path.ok_or_else(|| ArgError::InvalidPath(String::new()))?;
+        Ok(Self {
+            config_path,
+            check_live,
+            use_pipewire,
+        })
+    }
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+    /// Whether to compare the config against the running graph as well.
+    pub fn check_live(&self) -> bool {
+        self.check_live
+    }
+    /// Whether to read the PipeWire graph through its command line tools instead of JACK.
+    pub fn use_pipewire(&self) -> bool {
+        self.use_pipewire
+    }
+}
+
+/// Checks the config file named in `args`, merged with the files it includes and the
+/// conf.d snippets, for mistakes, printing each one it finds, and returns how many were
+/// found.
+/// A config that fails to load counts as a single mistake.
+pub fn run_check(args: &CheckArgs) -> Result<usize, crate::Error> {
+    let path = args.config_path().display();
+    let lint = match ConfigLint::load(args.config_path(), &config::default_conf_dirs()) {
+        Ok(lint) => lint,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(1);
+        }
+    };
+    let mut problems = lint.problems();
+    if args.check_live() {
+        problems.extend(live_problems(args, &lint)?);
+    }
+    for problem in problems.iter() {
+        println!("{}: {}", path, problem);
+    }
+    Ok(problems.len())
+}
+
+fn live_problems(args: &CheckArgs, lint: &ConfigLint) -> Result<Vec<ConfigProblem>, crate::Error> {
+    if args.use_pipewire() {
+        let mut graph = PipewireGraph::new()?;
+        graph.update()?;
+        report_warnings(&mut graph);
+        Ok(lint.graph_problems(graph.graph()))
+    } else {
+        let (rawclient, _) = jack::Client::new("trejcheck", jack::ClientOptions::NO_START_SERVER)?;
+        let mut graph = JackGraph::new(rawclient)?;
+        graph.update()?;
+        Ok(lint.graph_problems(graph.graph()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_check_args() {
+        let tmp = TempDir::new("check_args");
+        let config_path = tmp.path().join("trej.toml");
+        std::fs::write(&config_path, "").unwrap();
+        let raw_path = config_path.to_str().unwrap();
+
+        let args = CheckArgs::from_args(["check", "-p", raw_path].iter()).unwrap();
+        assert_eq!(args.config_path(), config_path.as_path());
+        assert!(args.check_live());
+        assert!(args.use_pipewire());
+        let offline = CheckArgs::from_args(["check", raw_path].iter()).unwrap();
+        assert!(!offline.check_live());
+
+        assert!(CheckArgs::from_args(["check"].iter()).is_err());
+        assert!(CheckArgs::from_args(["check", "-x", raw_path].iter()).is_err());
+        assert!(CheckArgs::from_args(["check", raw_path, raw_path].iter()).is_err());
+    }
+}
@@ -1,45 +1,115 @@
-use crate::config::LockConfig;
-use crate::model::PortFullname;
+use crate::config::{self, LoadedConfig, LockConfig};
+use crate::graph::{GraphBackend, JackGraph, PipewireGraph, UpdateListener};
 
-use jack::Client as JackClient;
-use jack::PortId;
 use notify::{self, RecommendedWatcher, RecursiveMode, Watcher};
 
-use std::convert::TryFrom;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
 
 mod args;
 pub use args::{ArgError, DaemonArgs, StartServerFlag};
 
-pub struct TrejDaemon {
-    args: DaemonArgs,
+mod check;
+pub use check::{run_check, CheckArgs};
+
+mod simulate;
+pub use simulate::{run_simulation, SimulateArgs};
+
+mod snapshot;
+pub use snapshot::{run_snapshot, SnapshotArgs};
+
+pub struct TrejDaemon<G: GraphBackend = JackGraph> {
+    config_path: PathBuf,
     event_stream: mpsc::Receiver<DaemonMessage>,
     config: LockConfig,
-    client: jack::AsyncClient<GraphNotifier, ()>,
-    _watcher: notify::RecommendedWatcher,
+    graph: G,
+    watcher: Option<notify::RecommendedWatcher>,
+    /// The files and directories the watcher watches.
+    watched: Vec<PathBuf>,
+    /// The paths the watcher reports changes to: the files and directories the config
+    /// was last read from, and the conf.d directories that don't exist yet.
+    relevant: Arc<Mutex<Vec<PathBuf>>>,
 }
 
 impl TrejDaemon {
     pub fn new(args: DaemonArgs) -> Result<(Self, mpsc::SyncSender<DaemonMessage>), crate::Error> {
-        let config = read_config(args.config_path())?;
+        Self::start(args, make_graph)
+    }
+}
+
+impl TrejDaemon<PipewireGraph> {
+    /// Constructs a daemon that manages the PipeWire graph directly instead of via JACK.
+    pub fn new_pipewire(
+        args: DaemonArgs,
+    ) -> Result<(Self, mpsc::SyncSender<DaemonMessage>), crate::Error> {
+        Self::start(args, |_, sender| {
+            let graph = PipewireGraph::with_listener(make_listener(sender))?;
+            Ok(graph)
+        })
+    }
+}
+
+impl<G: GraphBackend> TrejDaemon<G> {
+    fn start<F>(
+        args: DaemonArgs,
+        graph_builder: F,
+    ) -> Result<(Self, mpsc::SyncSender<DaemonMessage>), crate::Error>
+    where
+        F: FnOnce(&DaemonArgs, mpsc::SyncSender<DaemonMessage>) -> Result<G, crate::Error>,
+    {
+        let LoadedConfig {
+            mut config,
+            sources,
+        } = read_config(args.config_path())?;
+        report_conflicts(&config);
+        if let Some(name) = args.profile() {
+            if !config.select_profile(name) {
+                return Err(ArgError::InvalidProfile(name.to_owned()).into());
+            }
+        }
 
         let (snd, event_stream) = mpsc::sync_channel(32);
-        let _watcher = make_watcher(&args, snd.clone())?;
-        let client = make_client(&args, snd.clone())?;
-
-        Ok((
-            Self {
-                args,
-                event_stream,
-                config,
-                client,
-                _watcher,
-            },
-            snd,
-        ))
+        let relevant = Arc::new(Mutex::new(Vec::new()));
+        let watcher = make_watcher(snd.clone(), Arc::clone(&relevant))?;
+        let mut graph = graph_builder(&args, snd.clone())?;
+        report_warnings(&mut graph);
+
+        let mut retvl = Self {
+            config_path: args.config_path().to_owned(),
+            event_stream,
+            config,
+            graph,
+            watcher: Some(watcher),
+            watched: Vec::new(),
+            relevant,
+        };
+        retvl.watch_sources(sources)?;
+        Ok((retvl, snd))
+    }
+    /// Constructs a daemon enforcing `config` on an already-initialized graph backend.
+    /// No file watcher or graph listener is installed; instead, events must be
+    /// sent through the returned channel.
+    #[cfg(test)]
+    pub fn with_graph(
+        config_path: PathBuf,
+        config: LockConfig,
+        graph: G,
+    ) -> (Self, mpsc::SyncSender<DaemonMessage>) {
+        let (snd, event_stream) = mpsc::sync_channel(32);
+        let retvl = Self {
+            config_path,
+            event_stream,
+            config,
+            graph,
+            watcher: None,
+            watched: Vec::new(),
+            relevant: Arc::new(Mutex::new(Vec::new())),
+        };
+        (retvl, snd)
+    }
+    #[cfg(test)]
+    pub fn graph(&self) -> &G {
+        &self.graph
     }
     pub fn run(mut self) -> Result<(), crate::Error> {
         loop {
@@ -48,51 +118,144 @@ impl TrejDaemon {
                     eprintln!("Channel closed. Breaking.");
                     break;
                 }
-                Ok(DaemonMessage::ConfigUpdated) => {
-                    eprintln!("Got config update evt.");
-                    let new_config = read_config(self.args.config_path())?;
-                    if new_config != self.config {
-                        eprintln!("Applying new config.");
-                        self.config = new_config;
-                        apply_config(&self.config, &self.client.as_client())?;
-                    }
-                    else {
-                        eprintln!("Config is unchanged.");
-                    }
+                Ok(msg) => {
+                    self.handle_message(msg)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn handle_message(&mut self, msg: DaemonMessage) -> Result<(), crate::Error> {
+        match msg {
+            DaemonMessage::ConfigUpdated => {
+                eprintln!("Got config update evt.");
+                let loaded = read_config(&self.config_path)?;
+                self.watch_sources(loaded.sources)?;
+                let mut new_config = loaded.config;
+                new_config.keep_runtime_state(&self.config);
+                if new_config != self.config {
+                    eprintln!("Applying new config.");
+                    report_conflicts(&new_config);
+                    self.config = new_config;
+                    self.config.enforce(&mut self.graph)?;
+                } else {
+                    eprintln!("Config is unchanged.");
+                }
+            }
+            DaemonMessage::GraphUpdated => {
+                eprintln!("Got graph update evt.");
+                self.graph.update()?;
+                report_warnings(&mut self.graph);
+                let held = holding_conditions(&self.config);
+                self.config.enforce(&mut self.graph)?;
+                let holding = holding_conditions(&self.config);
+                if holding != held {
+                    eprintln!("Conditions now holding: [{}]", holding.join(", "));
                 }
-                Ok(DaemonMessage::GraphUpdated) => {
-                    eprintln!("Got graph update evt.");
-                    apply_config(&self.config, &self.client.as_client())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<G: GraphBackend> TrejDaemon<G> {
+    /// Watches every file and directory in `sources`, and stops watching those the
+    /// config is no longer read from.
+    /// A conf.d directory that doesn't exist can't be watched itself, so the nearest
+    /// existing directory above it is watched instead, and only changes on the way to
+    /// the missing directory are reported from there.
+    fn watch_sources(&mut self, sources: Vec<PathBuf>) -> Result<(), crate::Error> {
+        let watcher = match self.watcher.as_mut() {
+            Some(watcher) => watcher,
+            None => {
+                return Ok(());
+            }
+        };
+        let missing: Vec<PathBuf> = config::default_conf_dirs()
+            .into_iter()
+            .filter(|dir| !sources.contains(dir))
+            .collect();
+        let mut to_watch = sources.clone();
+        for dir in missing.iter() {
+            if let Some(parent) = dir.ancestors().skip(1).find(|parent| parent.is_dir()) {
+                if !to_watch.iter().any(|path| path == parent) {
+                    to_watch.push(parent.to_owned());
                 }
             }
         }
+        let watched = &self.watched;
+        for old in watched.iter().filter(|old| !to_watch.contains(old)) {
+            // A file that has been deleted is no longer watched anyway.
+            watcher.unwatch(old).ok();
+        }
+        for new in to_watch.iter().filter(|new| !watched.contains(new)) {
+            watcher.watch(new, RecursiveMode::NonRecursive)?;
+        }
+        self.watched = to_watch;
+        let mut relevant = self.relevant.lock().unwrap_or_else(|e| e.into_inner());
+        *relevant = sources.into_iter().chain(missing).collect();
         Ok(())
     }
 }
 
-fn read_config(path: &Path) -> Result<LockConfig, crate::Error> {
-    let mut fh = File::open(path)?;
-    let mut buffer = String::new();
-    fh.read_to_string(&mut buffer)?;
-    let retvl = toml::from_str(&buffer)?;
+/// Whether a change to `path` could change the config read from `relevant`: whether it
+/// is one of them, directly within one of them, or a directory on the way to one.
+fn is_relevant(relevant: &[PathBuf], path: &Path) -> bool {
+    relevant
+        .iter()
+        .any(|source| source.starts_with(path) || path.parent() == Some(source))
+}
+
+/// Warns about every clash between rules in `conf`.
+fn report_conflicts(conf: &LockConfig) {
+    for conflict in conf.conflicts() {
+        eprintln!("Rule conflict: {}", conflict);
+    }
+}
+
+/// Prints the warnings `graph` has raised since they were last printed.
+fn report_warnings<G: GraphBackend>(graph: &mut G) {
+    for warning in graph.take_warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Gets the names of the conditional rule sets in `conf` whose condition holds.
+fn holding_conditions(conf: &LockConfig) -> Vec<String> {
+    conf.conditions()
+        .filter(|(_, _, holds)| *holds)
+        .map(|(name, _, _)| name.to_owned())
+        .collect()
+}
+
+/// Loads the config file at `path`, along with the files it includes and the snippets in
+/// the conf.d directories.
+fn read_config(path: &Path) -> Result<LoadedConfig, crate::Error> {
+    let retvl = config::load_config(path, &config::default_conf_dirs())?;
     Ok(retvl)
 }
 
 fn make_watcher(
-    args: &DaemonArgs,
     sender: mpsc::SyncSender<DaemonMessage>,
+    relevant: Arc<Mutex<Vec<PathBuf>>>,
 ) -> Result<notify::RecommendedWatcher, crate::Error> {
-    let mut watcher = RecommendedWatcher::new_immediate(move |_| {
-        if sender.try_send(DaemonMessage::ConfigUpdated).is_ok() {}
+    let watcher = RecommendedWatcher::new_immediate(move |res: notify::Result<notify::Event>| {
+        let should_reload = match res {
+            Ok(evt) => {
+                let relevant = relevant.lock().unwrap_or_else(|e| e.into_inner());
+                evt.paths.is_empty() || evt.paths.iter().any(|path| is_relevant(&relevant, path))
+            }
+            Err(_) => true,
+        };
+        if should_reload && sender.try_send(DaemonMessage::ConfigUpdated).is_ok() {}
     })?;
-    watcher.watch(args.config_path(), RecursiveMode::NonRecursive)?;
     Ok(watcher)
 }
 
-fn make_client(
+fn make_graph(
     args: &DaemonArgs,
     sender: mpsc::SyncSender<DaemonMessage>,
-) -> Result<jack::AsyncClient<GraphNotifier, ()>, crate::Error> {
+) -> Result<JackGraph, crate::Error> {
     let force_start = args.server_flag() == StartServerFlag::StartServer;
     let try_start = args.server_flag() == StartServerFlag::StartIfStopped;
 
@@ -115,9 +278,16 @@ fn make_client(
     };
 
     let raw_client = second_res?;
-    let notifier = GraphNotifier { channel: sender };
-    let client = raw_client.activate_async(notifier, ())?;
-    Ok(client)
+    let graph = JackGraph::with_listener(raw_client, make_listener(sender))?;
+    Ok(graph)
+}
+
+/// Makes a graph listener that forwards change notifications to the daemon's event loop.
+fn make_listener(sender: mpsc::SyncSender<DaemonMessage>) -> UpdateListener {
+    Box::new(move || match sender.try_send(DaemonMessage::GraphUpdated) {
+        Ok(()) | Err(mpsc::TrySendError::Full(_)) => jack::Control::Continue,
+        Err(mpsc::TrySendError::Disconnected(_)) => jack::Control::Quit,
+    })
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -126,106 +296,201 @@ pub enum DaemonMessage {
     ConfigUpdated,
 }
 
-fn apply_config(conf: &LockConfig, client: &JackClient) -> Result<(), crate::Error> {
-    let port_names = client
-        .ports(None, None, jack::PortFlags::empty())
-        .into_iter()
-        .map(PortFullname::try_from)
-        .collect::<Result<Vec<_>, _>>()?;
-    let port_data: Vec<_> = port_names
-        .iter()
-        .map(|name| client.port_by_name(name.as_ref()))
-        .collect();
-    for (port_a_idx, port_a) in port_names.iter().enumerate() {
-        let a_data_opt = port_data.get(port_a_idx).and_then(|d| d.as_ref());
-        let a_data = match a_data_opt {
-            Some(d) => d,
-            None => {
-                continue;
-            }
-        };
-        let port_b_iter = port_names.iter().skip(1 + port_a_idx);
-        for port_b in port_b_iter {
-            if !a_data.is_connected_to(port_b.as_ref())? {
-                continue;
-            }
-            let lock_status = conf.connection_status(port_a, port_b);
-            if lock_status.should_block() {
-                let a_is_src = a_data.flags().contains(jack::PortFlags::IS_OUTPUT);
-                let (src, dst) = if a_is_src {
-                    (port_a, port_b)
-                } else {
-                    (port_b, port_a)
-                };
-                client.disconnect_ports_by_name(src.as_ref(), dst.as_ref())?;
-            }
-        }
-    }
-    for (port_a, port_b) in conf.forced_connections() {
-        let mut a_data = None;
-        let mut b_data = None;
-        for (cur_idx, cur_name) in port_names.iter().enumerate() {
-            if port_a == cur_name {
-                a_data = port_data.get(cur_idx).and_then(|d| d.as_ref());
-                if b_data.is_some() {
-                    break;
-                }
-            } else if port_b == cur_name {
-                b_data = port_data.get(cur_idx).and_then(|d| d.as_ref());
-                if a_data.is_some() {
-                    break;
-                }
-            }
-        }
-        let (a_data, b_data) = if let Some(dt) = a_data.zip(b_data) {
-            dt
-        } else {
-            continue;
-        };
-        if a_data.port_type()? != b_data.port_type()? || a_data.is_connected_to(port_b.as_ref())? {
-            continue;
-        }
-        let a_is_input = a_data.flags().contains(jack::PortFlags::IS_INPUT);
-        let b_is_input = b_data.flags().contains(jack::PortFlags::IS_INPUT);
-        let (src, dst) = match (a_is_input, b_is_input) {
-            (false, true) => (port_a, port_b),
-            (true, false) => (port_b, port_a),
-            _ => {
-                continue;
-            }
-        };
-        client.connect_ports_by_name(src.as_ref(), dst.as_ref())?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::MemoryGraph;
+    use crate::model::PortDirection;
+    use crate::testing::port;
+
+    #[test]
+    fn test_relevant_changes() {
+        let relevant = vec![
+            PathBuf::from("/home/user/trej.toml"),
+            PathBuf::from("/etc/trej/conf.d"),
+            PathBuf::from("/home/user/.config/trej/conf.d"),
+        ];
+        let path = |raw: &str| PathBuf::from(raw);
+        assert!(is_relevant(&relevant, &path("/home/user/trej.toml")));
+        assert!(is_relevant(
+            &relevant,
+            &path("/etc/trej/conf.d/10-synth.toml")
+        ));
+        // The missing user conf.d is watched for from ~/.config.
+        assert!(is_relevant(&relevant, &path("/home/user/.config/trej")));
+        assert!(is_relevant(
+            &relevant,
+            &path("/home/user/.config/trej/conf.d")
+        ));
+        assert!(!is_relevant(&relevant, &path("/home/user/.config/other")));
+        assert!(!is_relevant(
+            &relevant,
+            &path("/home/user/.config/trej/notes.txt")
+        ));
     }
-    Ok(())
-}
 
-struct GraphNotifier {
-    channel: mpsc::SyncSender<DaemonMessage>,
-}
+    #[test]
+    fn test_graph_update_enforces_locks() {
+        let capture = port("system:capture_1", PortDirection::Out);
+        let reverb_in = port("reverb:in", PortDirection::In);
+        let looper_in = port("looper:in", PortDirection::In);
+        let mut graph = MemoryGraph::new();
+        graph.add_port(capture.clone());
+        graph.add_port(reverb_in.clone());
+        graph.add_port(looper_in.clone());
 
-impl GraphNotifier {
-    pub fn notify(&self) -> jack::Control {
-        match self.channel.try_send(DaemonMessage::GraphUpdated) {
-            Ok(()) | Err(mpsc::TrySendError::Full(_)) => jack::Control::Continue,
-            Err(mpsc::TrySendError::Disconnected(_)) => jack::Control::Quit,
-        }
-    }
-}
+        let raw_conf = r#"
+            [reverb]
+            lock = "full"
+            [reverb.in]
+            connections = ["system:capture_1"]
+        "#;
+        let config = toml::from_str(raw_conf).unwrap();
+        let (mut daemon, _) = TrejDaemon::with_graph(PathBuf::new(), config, graph);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert!(daemon.graph().is_connected(&capture.name, &reverb_in.name));
 
-impl jack::NotificationHandler for GraphNotifier {
-    fn port_registration(&mut self, _: &JackClient, _: PortId, _: bool) {
-        self.notify();
-    }
-    fn ports_connected(&mut self, _: &JackClient, _: PortId, _: PortId, _: bool) {
-        self.notify();
+        // Another client snatching the port is fine, but the forced connection is restored.
+        daemon
+            .graph
+            .remove_connection(&capture.name, &reverb_in.name);
+        daemon.graph.add_connection(&capture.name, &looper_in.name);
+        assert!(daemon.graph().needs_update());
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert!(daemon.graph().is_connected(&capture.name, &reverb_in.name));
+        assert!(daemon.graph().is_connected(&capture.name, &looper_in.name));
     }
-    fn port_rename(&mut self, _: &JackClient, _: PortId, _: &str, _: &str) -> jack::Control {
-        self.notify()
+
+    #[test]
+    fn test_graph_update_checks_conditions() {
+        let capture = port("system:capture_1", PortDirection::Out);
+        let reverb_in = port("reverb:in", PortDirection::In);
+        let looper_in = port("looper:in", PortDirection::In);
+        let mut graph = MemoryGraph::new();
+        graph.add_port(capture.clone());
+        graph.add_port(reverb_in.clone());
+
+        let raw_conf = r#"
+            [reverb]
+            lock = "force"
+
+            ["@conditional".reverb_send]
+            when_absent = ["looper"]
+            ["@conditional".reverb_send."system:capture_1"]
+            connections = ["reverb:in"]
+        "#;
+        let config = toml::from_str(raw_conf).unwrap();
+        let (mut daemon, _) = TrejDaemon::with_graph(PathBuf::new(), config, graph);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert!(daemon.graph().is_connected(&capture.name, &reverb_in.name));
+        assert_eq!(holding_conditions(&daemon.config), vec!["reverb_send"]);
+
+        // Once the looper starts, the send is no longer forced.
+        daemon
+            .graph
+            .remove_connection(&capture.name, &reverb_in.name);
+        daemon.graph.add_port(looper_in);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert!(!daemon.graph().is_connected(&capture.name, &reverb_in.name));
+        assert!(holding_conditions(&daemon.config).is_empty());
     }
-    fn client_registration(&mut self, _: &JackClient, _: &str, _: bool) {
-        self.notify();
+
+    #[test]
+    fn test_exclusive_ports_keep_newest_peer() {
+        let synth_out = port("synth:out", PortDirection::Out);
+        let drums_out = port("drums:out", PortDirection::Out);
+        let cue_in = port("cue:in", PortDirection::In);
+        let mut graph = MemoryGraph::new();
+        graph.add_port(synth_out.clone());
+        graph.add_port(drums_out.clone());
+        graph.add_port(cue_in.clone());
+        graph.add_connection(&synth_out.name, &cue_in.name);
+
+        let raw_conf = r#"
+            ["cue:in"]
+            lock = "exclusive"
+        "#;
+        let config = toml::from_str(raw_conf).unwrap();
+        let (mut daemon, _) = TrejDaemon::with_graph(PathBuf::new(), config, graph);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert!(daemon.graph().is_connected(&synth_out.name, &cue_in.name));
+
+        daemon.graph.add_connection(&drums_out.name, &cue_in.name);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert!(daemon.graph().is_connected(&drums_out.name, &cue_in.name));
+        assert!(!daemon.graph().is_connected(&synth_out.name, &cue_in.name));
+
+        daemon.graph.add_connection(&synth_out.name, &cue_in.name);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert!(daemon.graph().is_connected(&synth_out.name, &cue_in.name));
+        assert!(!daemon.graph().is_connected(&drums_out.name, &cue_in.name));
     }
-    fn graph_reorder(&mut self, _: &JackClient) -> jack::Control {
-        self.notify()
+
+    #[test]
+    fn test_exclusive_ports_settle_with_several_forced_peers() {
+        let synth_out = port("synth:out", PortDirection::Out);
+        let drums_out = port("drums:out", PortDirection::Out);
+        let cue_in = port("cue:in", PortDirection::In);
+        let mut graph = MemoryGraph::new();
+        graph.add_port(synth_out.clone());
+        graph.add_port(drums_out.clone());
+        graph.add_port(cue_in.clone());
+
+        let raw_conf = r#"
+            ["cue:in"]
+            lock = "exclusive"
+            [synth]
+            lock = "force"
+            [synth.out]
+            connections = ["cue:in"]
+            [drums]
+            lock = "force"
+            [drums.out]
+            connections = ["cue:in"]
+        "#;
+        let config = toml::from_str(raw_conf).unwrap();
+        let (mut daemon, _) = TrejDaemon::with_graph(PathBuf::new(), config, graph);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert_eq!(
+            daemon
+                .graph()
+                .graph()
+                .port_connections(&cue_in.name)
+                .count(),
+            1
+        );
+
+        // Once one of the forced peers has been connected, nothing else changes.
+        daemon.graph.take_history();
+        for _ in 0..3 {
+            daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        }
+        assert_eq!(daemon.graph.take_history(), vec![]);
+        assert_eq!(
+            daemon
+                .graph()
+                .graph()
+                .port_connections(&cue_in.name)
+                .count(),
+            1
+        );
+
+        // Both being connected at once is also settled in favour of one of them.
+        daemon.graph.add_connection(&synth_out.name, &cue_in.name);
+        daemon.graph.add_connection(&drums_out.name, &cue_in.name);
+        daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        assert_eq!(
+            daemon
+                .graph()
+                .graph()
+                .port_connections(&cue_in.name)
+                .count(),
+            1
+        );
+        daemon.graph.take_history();
+        for _ in 0..3 {
+            daemon.handle_message(DaemonMessage::GraphUpdated).unwrap();
+        }
+        assert_eq!(daemon.graph.take_history(), vec![]);
     }
 }
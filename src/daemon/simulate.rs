@@ -0,0 +1,213 @@
+use super::{read_config, ArgError};
+use crate::config::LockConfig;
+use crate::graph::{GraphBackend, GraphChange, GraphFile, MemoryGraph, PortGraph};
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The maximum number of times the config is reapplied while waiting for the
+/// graph to settle, in case two rules keep undoing each other.
+const MAX_PASSES: usize = 16;
+
+pub struct SimulateArgs {
+    config_path: PathBuf,
+    graph_path: PathBuf,
+}
+
+impl SimulateArgs {
+    /// Parses the arguments following the `simulate` subcommand:
+    /// the config file followed by the graph description file.
+    pub fn from_args<I: Iterator<Item = S>, S: AsRef<str>>(iter: I) -> Result<Self, ArgError> {
+        let mut paths = Vec::with_capacity(2);
+        for cur in iter.skip(1) {
+            let raw_path = cur.as_ref();
+            if raw_path.starts_with('-') {
+                return Err(ArgError::InvalidFlag(raw_path.to_owned()));
+            }
+            let path = PathBuf::from(raw_path);
+            if !path.is_file() {
+                return Err(ArgError::InvalidPath(raw_path.to_owned()));
+            }
+            paths.push(path);
+        }
+        let graph_path = paths
+            .pop()
+            .ok_or_else(|| ArgError::InvalidPath(String::new()))?;
+        let config_path = paths
+            .pop()
+            .ok_or_else(|| ArgError::InvalidPath(String::new()))?;
+        if !paths.is_empty() {
+            return Err(ArgError::InvalidPath(config_path.display().to_string()));
+        }
+        Ok(Self {
+            config_path,
+            graph_path,
+        })
+    }
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+    pub fn graph_path(&self) -> &Path {
+        &self.graph_path
+    }
+}
+
+/// Loads the config and graph description named in `args`, and prints the changes
+/// the daemon would make to the graph along with the graph it would end up with.
+pub fn run_simulation(args: &SimulateArgs) -> Result<(), crate::Error> {
+    let mut config = read_config(args.config_path())?.config;
+    let graph = read_graph(args.graph_path())?;
+    let (changes, graph) = simulate(&mut config, graph)?;
+    if changes.is_empty() {
+        println!("# No changes.");
+    }
+    for change in changes {
+        println!("# {}", change);
+    }
+    println!();
+    let output = toml::to_string_pretty(&GraphFile::from(graph.graph()))?;
+    print!("{}", output);
+    Ok(())
+}
+
+fn read_graph(path: &Path) -> Result<MemoryGraph, crate::Error> {
+    let mut fh = File::open(path)?;
+    let mut buffer = String::new();
+    fh.read_to_string(&mut buffer)?;
+    let parsed: GraphFile = toml::from_str(&buffer)?;
+    let graph = PortGraph::try_from(parsed)?;
+    Ok(MemoryGraph::from_graph(graph))
+}
+
+/// Repeatedly applies `config` to `graph` the same way the daemon would,
+/// stopping once the graph stops changing.
+fn simulate(
+    config: &mut LockConfig,
+    mut graph: MemoryGraph,
+) -> Result<(Vec<GraphChange>, MemoryGraph), crate::Error> {
+    let mut changes = Vec::new();
+    for _ in 0..MAX_PASSES {
+        config.enforce(&mut graph)?;
+        let pass = graph.take_history();
+        if pass.is_empty() {
+            break;
+        }
+        changes.extend(pass);
+    }
+    Ok((changes, graph))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PortFullname;
+
+    #[test]
+    fn test_simulate() {
+        let raw_graph = r#"
+            [system]
+            capture_1 = { direction = "out", category = "audio", connections = ["looper:in"] }
+            capture_2 = { direction = "out", category = "audio" }
+            [looper]
+            in = { direction = "in", category = "audio", connections = ["system:capture_2"] }
+        "#;
+        let raw_conf = r#"
+            [looper]
+            lock = "full"
+            [looper.in]
+            connections = ["system:capture_1"]
+        "#;
+        let graph: GraphFile = toml::from_str(raw_graph).unwrap();
+        let graph = MemoryGraph::from_graph(PortGraph::try_from(graph).unwrap());
+        let mut config = toml::from_str(raw_conf).unwrap();
+        let (changes, graph) = simulate(&mut config, graph).unwrap();
+
+        let capture_2 = PortFullname::new("system:capture_2".to_owned()).unwrap();
+        let looper_in = PortFullname::new("looper:in".to_owned()).unwrap();
+        assert_eq!(changes, vec![GraphChange::Disconnect(capture_2, looper_in)]);
+        let output = GraphFile::from(graph.graph());
+        let expected: GraphFile = toml::from_str(
+            r#"
+            [looper]
+            in = { direction = "in", category = "audio" }
+            [system]
+            capture_1 = { direction = "out", category = "audio", connections = ["looper:in"] }
+            capture_2 = { direction = "out", category = "audio" }
+        "#,
+        )
+        .unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_simulate_aliases() {
+        let raw_graph = r#"
+            [system]
+            playback_1 = { direction = "in", category = "audio", aliases = ["alsa_pcm:hw:USB:out1"] }
+            playback_2 = { direction = "in", category = "audio", aliases = ["alsa_pcm:hw:PCH:out1"] }
+            [synth]
+            out = { direction = "out", category = "audio", connections = ["system:playback_2"] }
+        "#;
+        let raw_conf = r#"
+            ["alsa_pcm:hw:USB:out1"]
+            lock = "full"
+            connections = ["synth:out"]
+            ["alsa_pcm:hw:PCH:out1"]
+            lock = "block"
+        "#;
+        let graph: GraphFile = toml::from_str(raw_graph).unwrap();
+        let graph = MemoryGraph::from_graph(PortGraph::try_from(graph).unwrap());
+        let mut config = toml::from_str(raw_conf).unwrap();
+        let (_, graph) = simulate(&mut config, graph).unwrap();
+
+        let synth_out = PortFullname::new("synth:out".to_owned()).unwrap();
+        let playback_1 = PortFullname::new("system:playback_1".to_owned()).unwrap();
+        let playback_2 = PortFullname::new("system:playback_2".to_owned()).unwrap();
+        assert!(graph.is_connected(&synth_out, &playback_1));
+        assert!(!graph.is_connected(&synth_out, &playback_2));
+    }
+
+    #[test]
+    fn test_simulate_physical_class() {
+        let raw_graph = r#"
+            [system]
+            capture_1 = { direction = "out", category = "audio", physical = true, connections = ["looper:in"] }
+            [synth]
+            out = { direction = "out", category = "audio", connections = ["looper:in"] }
+            [looper]
+            in = { direction = "in", category = "audio" }
+        "#;
+        let raw_conf = r#"
+            ["@physical_outputs"]
+            lock = "block"
+        "#;
+        let graph: GraphFile = toml::from_str(raw_graph).unwrap();
+        let graph = MemoryGraph::from_graph(PortGraph::try_from(graph).unwrap());
+        let mut config = toml::from_str(raw_conf).unwrap();
+        let (changes, _) = simulate(&mut config, graph).unwrap();
+
+        let capture_1 = PortFullname::new("system:capture_1".to_owned()).unwrap();
+        let looper_in = PortFullname::new("looper:in".to_owned()).unwrap();
+        assert_eq!(changes, vec![GraphChange::Disconnect(capture_1, looper_in)]);
+    }
+
+    #[test]
+    fn test_undeclared_connection() {
+        let raw_graph = r#"
+            [system]
+            capture_1 = { direction = "out", category = "audio", connections = ["looper:inn"] }
+            [looper]
+            in = { direction = "in", category = "audio" }
+        "#;
+        let graph: GraphFile = toml::from_str(raw_graph).unwrap();
+        match PortGraph::try_from(graph) {
+            Err(crate::graph::GraphError::UndeclaredPort(missing, listed_by)) => {
+                assert_eq!(missing.as_ref(), "looper:inn");
+                assert_eq!(listed_by.as_ref(), "system:capture_1");
+            }
+            other => panic!("Expected an undeclared port error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use super::{report_warnings, ArgError};
+use crate::config::{LockConfig, LockStatus};
+use crate::graph::{GraphBackend, JackGraph, PipewireGraph};
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub struct SnapshotArgs {
+    output_path: Option<PathBuf>,
+    default_lock: LockStatus,
+    client_locks: HashMap<String, LockStatus>,
+    use_pipewire: bool,
+}
+
+impl SnapshotArgs {
+    /// Parses the arguments following the `snapshot` subcommand:
+    /// any number of `-l <lock>` or `-l <client>=<lock>` flags setting the lock given to
+    /// every client or to one client, `-p` to read the PipeWire graph, and optionally
+    /// the file to write the config to.
+    pub fn from_args<I: Iterator<Item = S>, S: AsRef<str>>(iter: I) -> Result<Self, ArgError> {
+        let mut iter = iter.skip(1);
+        let mut output_path = None;
+        let mut default_lock = LockStatus::Full;
+        let mut client_locks = HashMap::new();
+        let mut use_pipewire = false;
+        while let Some(cur_key) = iter.next() {
+            if cur_key.as_ref() == "-l" {
+                let cur_val = iter
+                    .next()
+                    .map(|s| s.as_ref().to_owned())
+                    .unwrap_or_default();
+                match cur_val.rsplit_once('=') {
+                    Some((client, raw_lock)) if !client.is_empty() => {
+                        let lock = parse_lock(raw_lock)?;
+                        client_locks.insert(client.to_owned(), lock);
+                    }
+                    Some(_) => {
+                        return Err(ArgError::InvalidClientName);
+                    }
+                    None => {
+                        default_lock = parse_lock(&cur_val)?;
+                    }
+                }
+            } else if cur_key.as_ref() == "-p" {
+                use_pipewire = true;
+            } else if cur_key.as_ref().starts_with('-') || output_path.is_some() {
+                return Err(ArgError::InvalidFlag(cur_key.as_ref().to_owned()));
+            } else {
+                output_path = Some(PathBuf::from(cur_key.as_ref()));
+            }
+        }
+        Ok(Self {
+            output_path,
+            default_lock,
+            client_locks,
+            use_pipewire,
+        })
+    }
+    /// Gets the file to write the config to, if not standard output.
+    pub fn output_path(&self) -> Option<&Path> {
+        self.output_path.as_deref()
+    }
+    /// Gets the lock to give `client` in the snapshot.
+    pub fn client_lock(&self, client: &str) -> LockStatus {
+        self.client_locks
+            .get(client)
+            .copied()
+            .unwrap_or(self.default_lock)
+    }
+    /// Whether to read the PipeWire graph through its command line tools instead of JACK.
+    pub fn use_pipewire(&self) -> bool {
+        self.use_pipewire
+    }
+}
+
+fn parse_lock(raw: &str) -> Result<LockStatus, ArgError> {
+    LockStatus::from_name(raw).ok_or_else(|| ArgError::InvalidLock(raw.to_owned()))
+}
+
+/// Captures every connection in the live graph into a config and writes it out,
+/// either to the file named in `args` or to standard output.
+/// An existing file is never overwritten.
+pub fn run_snapshot(args: &SnapshotArgs) -> Result<(), crate::Error> {
+    let config = if args.use_pipewire() {
+        let mut graph = PipewireGraph::new()?;
+        graph.update()?;
+        report_warnings(&mut graph);
+        snapshot(args, &graph)
+    } else {
+        let (rawclient, _) =
+            jack::Client::new("trejsnapshot", jack::ClientOptions::NO_START_SERVER)?;
+        let mut graph = JackGraph::new(rawclient)?;
+        graph.update()?;
+        snapshot(args, &graph)
+    };
+    let output = toml::to_string_pretty(&config)?;
+    match args.output_path() {
+        Some(path) => {
+            let mut fh = OpenOptions::new().write(true).create_new(true).open(path)?;
+            fh.write_all(output.as_bytes())?;
+        }
+        None => {
+            print!("{}", output);
+        }
+    }
+    Ok(())
+}
+
+fn snapshot<G: GraphBackend>(args: &SnapshotArgs, graph: &G) -> LockConfig {
+    LockConfig::snapshot(graph.graph(), |client| args.client_lock(client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_args() {
+        let raw_args = ["snapshot", "-l", "force", "-l", "system=none", "out.toml"];
+        let args = SnapshotArgs::from_args(raw_args.iter()).unwrap();
+        assert_eq!(args.output_path(), Some(Path::new("out.toml")));
+        assert_eq!(args.client_lock("system"), LockStatus::None);
+        assert_eq!(args.client_lock("synth"), LockStatus::Force);
+        assert!(!args.use_pipewire());
+
+        let defaults = SnapshotArgs::from_args(["snapshot"].iter()).unwrap();
+        assert_eq!(defaults.output_path(), None);
+        assert_eq!(defaults.client_lock("synth"), LockStatus::Full);
+
+        let bad_lock = ["snapshot", "-l", "synth=locked"];
+        assert!(SnapshotArgs::from_args(bad_lock.iter()).is_err());
+    }
+}
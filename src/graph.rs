@@ -1,13 +1,27 @@
-use crate::model::{NameError, PortCategory, PortData, PortDirection, PortFullname};
-use std::convert::TryFrom;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Condvar, Mutex, TryLockError};
-use std::time::Duration;
+use crate::model::{MidiEvent, NameError, PortData, PortFullname};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
 use thiserror::*;
 
-use jack::Client as JackClient;
 use jack::Error as JackError;
 
+mod jackgraph;
+pub use jackgraph::*;
+
+mod memory;
+pub use memory::*;
+
+pub mod metadata;
+
+mod parsing;
+pub use parsing::*;
+
+mod pipewire;
+pub use pipewire::*;
+
+mod transport;
+pub use transport::*;
+
 /// Errors that can occur when interacting with the JACK port graph.
 #[derive(Debug, Error)]
 pub enum GraphError {
@@ -15,203 +29,433 @@ pub enum GraphError {
     Jack(#[from] JackError),
     #[error(transparent)]
     ItemName(#[from] NameError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("PipeWire error: {0}")]
+    Pipewire(String),
+    #[error("Could not find `{0}`, which the PipeWire backend needs.")]
+    MissingTool(&'static str),
+    #[error("Port \"{0}\" does not exist.")]
+    NoSuchPort(PortFullname),
+    #[error("Cannot connect \"{0}\" to \"{1}\".")]
+    InvalidConnection(PortFullname, PortFullname),
+    #[error("Port \"{0}\" is connected to \"{1}\" but is never declared.")]
+    UndeclaredPort(PortFullname, PortFullname),
+    #[error("Ports \"{0}\" and \"{1}\" are not connected.")]
+    NotConnected(PortFullname, PortFullname),
+    #[error("This backend does not support {0}.")]
+    Unsupported(&'static str),
+    #[error("The server refused to {0}.")]
+    Refused(String),
 }
 
-/// A wrapper around the graph of JACK clients and ports.
-/// Note that this struct also caches information, and can therefore get stale.
-/// It is therefore wise to periodically poll for graph changes via the `needs_update()`
-/// method and reloading the graph via `update()`.
-#[derive(Debug)]
-pub struct JackGraph {
-    /// The underlying `jack::Client` that will be used for synchronizing state.
-    client: jack::AsyncClient<Notifier, ()>,
+/// A source of port graph data that can also modify the graph it is reading from.
+/// This lets the UI, config enforcement, and the daemon work against either a live
+/// JACK server (via `JackGraph`) or a canned topology (via `MemoryGraph`).
+pub trait GraphBackend {
+    /// Gets the cached view of the port graph.
+    fn graph(&self) -> &PortGraph;
 
-    /// All ports currently in the JACK graph.
-    ports: Vec<PortData>,
+    /// Removes a connection between two ports in the graph.
+    /// Note that `source` must be an output port, `dest` must be an input port,
+    /// and there must be an existing connection between them; otherwise, this
+    /// function will return an `Err`.
+    fn disconnect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError>;
 
-    /// Connections between ports, stored as indices into `self.ports`.
-    /// Currently stored as sorted.
-    connections: Vec<(usize, usize)>,
+    /// Connects two ports in the graph.
+    /// Note that both `source` and `dest` must transfer the same data type,
+    /// `source` must be an output port, `dest` must be an input port,
+    /// and there must not be an existing connection between them; otherwise, this
+    /// function will return an `Err`.
+    fn connect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError>;
+
+    /// Checks to see if the backend has unsynced updates that should be pulled in.
+    fn needs_update(&self) -> bool;
+
+    /// Refreshes the cached graph with data from the backend.
+    fn update(&mut self) -> Result<(), GraphError>;
+
+    /// Takes the warnings about the graph raised since the last call, such as ports that
+    /// had to be left out of it.
+    /// Each warning is only raised once, however many times the graph is updated.
+    fn take_warnings(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Gets the current state of the audio server behind the backend, if there is one.
+    fn server_info(&self) -> Option<ServerInfo> {
+        None
+    }
+
+    /// Changes the number of frames the server processes per cycle.
+    fn set_buffer_size(&mut self, _frames: u32) -> Result<(), GraphError> {
+        Err(GraphError::Unsupported("changing the buffer size"))
+    }
 
-    /// Set by the backing `jack::Client` whenever the graph changes.
-    update_flag: Notifier,
+    /// Turns the server's freewheel mode, which runs processing as fast as possible
+    /// rather than in realtime, on or off.
+    fn set_freewheel(&mut self, _enabled: bool) -> Result<(), GraphError> {
+        Err(GraphError::Unsupported("freewheel mode"))
+    }
+
+    /// Starts measuring the signal leaving each of the output ports in `ports`,
+    /// replacing whatever was being measured before.
+    /// Passing an empty slice stops metering altogether.
+    fn set_meters(&mut self, _ports: &[PortFullname]) -> Result<(), GraphError> {
+        Err(GraphError::Unsupported("metering"))
+    }
+
+    /// Gets the levels measured on each metered port since the last call.
+    fn meter_levels(&self) -> Vec<(PortFullname, MeterLevel)> {
+        Vec::new()
+    }
+
+    /// Starts listening to the MIDI sent by each of the output ports in `ports`,
+    /// replacing whatever was being listened to before.
+    /// Passing an empty slice stops listening altogether.
+    fn set_midi_taps(&mut self, _ports: &[PortFullname]) -> Result<(), GraphError> {
+        Err(GraphError::Unsupported("MIDI monitoring"))
+    }
+
+    /// Takes the MIDI events received from each tapped port since the last call.
+    fn take_midi_events(&mut self) -> Vec<(PortFullname, MidiEvent)> {
+        Vec::new()
+    }
+
+    /// Gets the current state of the server's transport, if it has one.
+    fn transport(&self) -> Option<TransportInfo> {
+        None
+    }
+
+    /// Starts the transport rolling, or stops it.
+    fn set_transport_rolling(&mut self, _rolling: bool) -> Result<(), GraphError> {
+        Err(GraphError::Unsupported("transport control"))
+    }
+
+    /// Moves the transport to `frame` frames since the start of the song.
+    fn locate_transport(&mut self, _frame: u32) -> Result<(), GraphError> {
+        Err(GraphError::Unsupported("transport control"))
+    }
+
+    /// Makes trej the timebase master, counting bars and beats at `bpm` beats per
+    /// minute, or gives up the role if `bpm` is `None`.
+    fn set_timebase(&mut self, _bpm: Option<f64>) -> Result<(), GraphError> {
+        Err(GraphError::Unsupported("acting as timebase master"))
+    }
+
+    /// Checks whether or not ports `a` and `b` are connected.
+    fn is_connected(&self, a: &PortFullname, b: &PortFullname) -> bool {
+        self.graph().is_connected(a, b)
+    }
 }
 
-impl JackGraph {
-    /// Constructs a new `JackGraph` wrapping the given `jack::Client`.
-    pub fn new(client: JackClient) -> Result<Self, GraphError> {
-        let notifier = Notifier::new();
-        let update_flag = notifier.handle();
-        let client = client.activate_async(notifier, ())?;
-        let mut retvl = JackGraph {
-            client,
-            update_flag,
-            ports: Vec::new(),
-            connections: Vec::new(),
-        };
-        retvl.update()?;
-        Ok(retvl)
+/// A snapshot of the state of an audio server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    /// The name of the server the backend asked to connect to.
+    /// JACK has no way to ask a connected server for its name, so this is what
+    /// `$JACK_DEFAULT_SERVER` names, or `default` if it is unset.
+    pub requested_name: String,
+    /// Frames per second.
+    pub sample_rate: usize,
+    /// Frames per processing cycle.
+    pub buffer_size: u32,
+    /// The percentage of each cycle spent processing, from 0 to 100.
+    pub dsp_load: f32,
+    /// Whether the server is running with realtime scheduling.
+    pub realtime: bool,
+    /// The number of xruns seen since the backend was started.
+    pub xruns: u64,
+    /// Whether the server is running in freewheel mode.
+    pub freewheel: bool,
+}
+
+impl ServerInfo {
+    /// Gets the latency introduced by a single processing cycle, in milliseconds.
+    pub fn cycle_latency_ms(&self) -> f64 {
+        if self.sample_rate == 0 {
+            return 0.0;
+        }
+        f64::from(self.buffer_size) * 1000.0 / self.sample_rate as f64
     }
+}
 
-    /// Removes a connection between two ports in the graph.
-    /// Note that `source` must be an input port, `dest` must be an output port,
-    /// and there must be an existing connection between them; otherwise, this
-    /// function will return an `Err`.
-    pub fn disconnect(
-        &mut self,
-        source: &PortFullname,
-        dest: &PortFullname,
-    ) -> Result<(), GraphError> {
-        self.client
-            .as_client()
-            .disconnect_ports_by_name(source.as_ref(), dest.as_ref())?;
-        let mut source_idx = None;
-        let mut dest_idx = None;
-        for (cur_idx, cur_data) in self.ports.iter().enumerate() {
-            if source == &cur_data.name {
-                source_idx = Some(cur_idx);
-            } else if dest == &cur_data.name {
-                dest_idx = Some(cur_idx);
-            }
-            if source_idx.is_some() && dest_idx.is_some() {
-                break;
-            }
+/// The level of an audio signal over some stretch of time, as linear amplitudes
+/// where `1.0` is full scale.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MeterLevel {
+    /// The largest absolute sample value seen.
+    pub peak: f32,
+    /// The root mean square of the samples seen.
+    pub rms: f32,
+}
+
+impl MeterLevel {
+    /// Measures a single buffer of samples.
+    pub fn measure(samples: &[f32]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
         }
-        let key = source_idx.zip(dest_idx).map(|(a, b)| (a.min(b), a.max(b)));
-        let con_idx = key.and_then(|k| self.connections.binary_search(&k).ok());
-        if let Some(con_idx) = con_idx {
-            self.connections.remove(con_idx);
-        } else {
-            self.update()?;
+        let mut peak = 0.0f32;
+        let mut sum_sq = 0.0f32;
+        for &sample in samples {
+            peak = peak.max(sample.abs());
+            sum_sq += sample * sample;
         }
-        Ok(())
+        let rms = (sum_sq / samples.len() as f32).sqrt();
+        Self { peak, rms }
     }
 
-    /// Connects two ports in the graph.
-    /// Note that both `source` and `dest` must transfer the same data type,
-    /// `source` must be an input port, `dest` must be an output port,
-    /// and there must not be an existing connection between them; otherwise, this
-    /// function will return an `Err`.
-    pub fn connect(
-        &mut self,
-        source: &PortFullname,
-        dest: &PortFullname,
-    ) -> Result<(), GraphError> {
-        self.client
-            .as_client()
-            .connect_ports_by_name(source.as_ref(), dest.as_ref())?;
-        let mut source_idx = None;
-        let mut dest_idx = None;
-        for (cur_idx, cur_data) in self.ports.iter().enumerate() {
-            if source == &cur_data.name {
-                source_idx = Some(cur_idx);
-            } else if dest == &cur_data.name {
-                dest_idx = Some(cur_idx);
+    /// Gets the peak level in decibels relative to full scale.
+    pub fn peak_db(&self) -> f32 {
+        amplitude_to_db(self.peak)
+    }
+
+    /// Gets the RMS level in decibels relative to full scale.
+    pub fn rms_db(&self) -> f32 {
+        amplitude_to_db(self.rms)
+    }
+}
+
+fn amplitude_to_db(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        f32::NEG_INFINITY
+    } else {
+        20.0 * amplitude.log10()
+    }
+}
+
+/// A cached snapshot of the ports in a graph and the connections between them.
+///
+/// Ports are indexed both by name and by client, and each port keeps the set of ports it
+/// is connected to, so lookups never need to scan the whole graph.
+#[derive(Debug, Clone, Default, Eq)]
+pub struct PortGraph {
+    /// All ports currently in the graph, indexed by slot.
+    /// Slots freed by removed ports are `None` until they are reused.
+    ports: Vec<Option<PortData>>,
+
+    /// Slots in `self.ports` that are free to be reused.
+    free_slots: Vec<usize>,
+
+    /// Maps each port's name to its slot in `self.ports`.
+    port_indices: HashMap<PortFullname, usize>,
+
+    /// Maps each port alias to the slot of the port it belongs to.
+    alias_indices: HashMap<PortFullname, usize>,
+
+    /// The names of all clients with at least one port, in the order they first appeared.
+    client_order: Vec<String>,
+
+    /// The slots of each client's ports, in the order they were added.
+    client_ports: HashMap<String, Vec<usize>>,
+
+    /// The slots of all ports connected to each port, indexed by slot.
+    adjacency: Vec<BTreeSet<usize>>,
+
+    /// Extra backend-specific key/value information about each client.
+    client_properties: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl PortGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes all ports and connections from the graph.
+    pub fn clear(&mut self) {
+        self.ports.clear();
+        self.free_slots.clear();
+        self.port_indices.clear();
+        self.alias_indices.clear();
+        self.client_order.clear();
+        self.client_ports.clear();
+        self.adjacency.clear();
+        self.client_properties.clear();
+    }
+
+    /// Sets the extra key/value information for the client named `client`.
+    pub fn set_client_properties(&mut self, client: String, properties: BTreeMap<String, String>) {
+        self.client_properties.insert(client, properties);
+    }
+
+    /// Gets the extra key/value information for the client named `client`.
+    pub fn client_properties<'a>(
+        &'a self,
+        client: &str,
+    ) -> impl Iterator<Item = (&'a String, &'a String)> + 'a {
+        self.client_properties
+            .get(client)
+            .into_iter()
+            .flat_map(|props| props.iter())
+    }
+
+    /// Adds a port to the graph, replacing any existing port with the same name.
+    /// New ports are placed after the existing ports of the same client.
+    pub fn add_port(&mut self, data: PortData) {
+        if let Some(idx) = self.port_idx(&data.name) {
+            if let Some(old) = self.ports[idx].take() {
+                for alias in old.aliases.iter() {
+                    self.alias_indices.remove(alias);
+                }
             }
-            if source_idx.is_some() && dest_idx.is_some() {
-                break;
+            for alias in data.aliases.iter() {
+                self.alias_indices.insert(alias.clone(), idx);
             }
+            self.ports[idx] = Some(data);
+            self.place_in_client(idx);
+            return;
         }
-        if let (Some(source_idx), Some(dest_idx)) = (source_idx, dest_idx) {
-            let key = if source_idx < dest_idx {
-                (source_idx, dest_idx)
-            } else {
-                (dest_idx, source_idx)
-            };
-            if let Err(con_idx) = self.connections.binary_search(&key) {
-                self.connections.insert(con_idx, key);
+        let idx = match self.free_slots.pop() {
+            Some(idx) => idx,
+            None => {
+                self.ports.push(None);
+                self.adjacency.push(BTreeSet::new());
+                self.ports.len() - 1
             }
-            Ok(())
-        } else {
-            self.update()?;
-            Ok(())
+        };
+        let client = data.name.client_name();
+        if !self.client_ports.contains_key(client) {
+            self.client_order.push(client.to_owned());
+        }
+        self.port_indices.insert(data.name.clone(), idx);
+        for alias in data.aliases.iter() {
+            self.alias_indices.insert(alias.clone(), idx);
         }
+        self.ports[idx] = Some(data);
+        self.place_in_client(idx);
     }
 
-    /// Checks to see if the underlying `jack::Client` has unsynced updates that
-    /// should be pulled in.
-    pub fn needs_update(&self) -> bool {
-        self.update_flag.check()
+    /// (Re)inserts the port in slot `idx` into its client's port list.
+    /// Ports with an `order` come first, sorted by it; the rest follow in the order
+    /// they were added.
+    fn place_in_client(&mut self, idx: usize) {
+        fn sort_key(data: Option<&PortData>) -> (bool, i64) {
+            let order = data.and_then(|data| data.order);
+            (order.is_none(), order.unwrap_or(0))
+        }
+        let data = match self.ports[idx].as_ref() {
+            Some(data) => data,
+            None => {
+                return;
+            }
+        };
+        let key = sort_key(Some(data));
+        let ports = &self.ports;
+        let slots = self
+            .client_ports
+            .entry(data.name.client_name().to_owned())
+            .or_default();
+        slots.retain(|&slot| slot != idx);
+        let insert_idx = slots.partition_point(|&slot| sort_key(ports[slot].as_ref()) <= key);
+        slots.insert(insert_idx, idx);
     }
 
-    /// Refreshes the data in the interal graph cache with data from the underlying `jack::Client`.
-    pub fn update(&mut self) -> Result<(), GraphError> {
-        self.update_flag.reset();
-
-        let raw_names = self
-            .client
-            .as_client()
-            .ports(None, None, jack::PortFlags::empty());
-        let port_names = raw_names
-            .into_iter()
-            .map(PortFullname::try_from)
-            .collect::<Result<Vec<_>, _>>()?;
+    /// Removes a port, and all of its connections, from the graph.
+    pub fn remove_port(&mut self, name: &PortFullname) -> Option<PortData> {
+        let idx = self.port_indices.remove(name)?;
+        let retvl = self.ports[idx].take()?;
+        for alias in retvl.aliases.iter() {
+            self.alias_indices.remove(alias);
+        }
+        let neighbours = std::mem::take(&mut self.adjacency[idx]);
+        for other in neighbours {
+            self.adjacency[other].remove(&idx);
+        }
+        let client = retvl.name.client_name();
+        let is_empty = match self.client_ports.get_mut(client) {
+            Some(slots) => {
+                slots.retain(|&slot| slot != idx);
+                slots.is_empty()
+            }
+            None => false,
+        };
+        if is_empty {
+            self.client_ports.remove(client);
+            self.client_order.retain(|cur| cur != client);
+        }
+        self.free_slots.push(idx);
+        Some(retvl)
+    }
 
-        let client = &self.client;
-        let port_iter = port_names.iter().enumerate().filter_map(|(idx, name)| {
-            let data = client.as_client().port_by_name(name.as_ref())?;
-            Some((idx, name, data))
-        });
+    /// Removes all ports belonging to `client` from the graph.
+    pub fn remove_client(&mut self, client: &str) {
+        let names: Vec<_> = self
+            .client_ports(client)
+            .map(|data| data.name.clone())
+            .collect();
+        for name in names {
+            self.remove_port(&name);
+        }
+        self.client_properties.remove(client);
+    }
 
-        self.ports.clear();
-        self.connections.clear();
-        for (port_a_idx, port_a_name, port_a) in port_iter {
-            let direction = if port_a.flags().contains(jack::PortFlags::IS_INPUT) {
-                PortDirection::In
-            } else {
-                PortDirection::Out
-            };
-            let kindstr = port_a.port_type()?.to_lowercase();
-            let category = if kindstr.contains("midi") {
-                PortCategory::Midi
-            } else if kindstr.contains("audio") {
-                PortCategory::Audio
-            } else {
-                PortCategory::Unknown
-            };
-            let data = PortData {
-                name: port_a_name.clone(),
-                direction,
-                category,
-            };
-            self.ports.push(data);
-            for (port_b_idx, port_b) in port_names.iter().enumerate().skip(port_a_idx + 1) {
-                if port_a.is_connected_to(port_b.as_ref())? {
-                    self.connections.push((port_a_idx, port_b_idx));
-                }
+    /// Changes the name of port `old` to `new`, keeping its connections.
+    /// Returns `false` if `old` does not exist or if `new` belongs to a different client.
+    pub fn rename_port(&mut self, old: &PortFullname, new: PortFullname) -> bool {
+        if old.client_name() != new.client_name() || self.port_indices.contains_key(&new) {
+            return false;
+        }
+        let idx = match self.port_indices.remove(old) {
+            Some(idx) => idx,
+            None => {
+                return false;
             }
+        };
+        self.port_indices.insert(new.clone(), idx);
+        if let Some(data) = self.ports[idx].as_mut() {
+            data.name = new;
         }
-        Ok(())
+        true
+    }
+
+    /// Marks ports `a` and `b` as connected.
+    /// Returns `false` if either port is missing from the graph.
+    pub fn add_connection(&mut self, a: &PortFullname, b: &PortFullname) -> bool {
+        let (aidx, bidx) = match self.port_idx(a).zip(self.port_idx(b)) {
+            Some(k) => k,
+            None => {
+                return false;
+            }
+        };
+        self.adjacency[aidx].insert(bidx);
+        self.adjacency[bidx].insert(aidx);
+        true
+    }
+
+    /// Marks ports `a` and `b` as disconnected.
+    /// Returns `false` if the ports were not connected to begin with.
+    pub fn remove_connection(&mut self, a: &PortFullname, b: &PortFullname) -> bool {
+        let (aidx, bidx) = match self.port_idx(a).zip(self.port_idx(b)) {
+            Some(k) => k,
+            None => {
+                return false;
+            }
+        };
+        self.adjacency[bidx].remove(&aidx);
+        self.adjacency[aidx].remove(&bidx)
+    }
+
+    fn port_idx(&self, name: &PortFullname) -> Option<usize> {
+        self.port_indices.get(name).copied()
+    }
+
+    fn port_at(&self, idx: usize) -> Option<&PortData> {
+        self.ports.get(idx)?.as_ref()
     }
 
     /// Gets an iterator over all ports connected a provided port.
-    pub fn port_connections<'a, 'b>(
+    pub fn port_connections<'a>(
         &'a self,
-        name: &'b PortFullname,
+        name: &PortFullname,
     ) -> impl Iterator<Item = &'a PortData> + 'a {
-        let port_idx = self
-            .ports
-            .iter()
-            .map(|data| &data.name)
-            .enumerate()
-            .find(|(_, cur)| cur == &name)
-            .map(|(idx, _)| idx);
-        let con_ref = &self.connections;
-        port_idx
-            .map(|idx| {
-                con_ref.iter().filter_map(move |&(a, b)| {
-                    if a == idx {
-                        Some(b)
-                    } else if b == idx {
-                        Some(a)
-                    } else {
-                        None
-                    }
-                })
-            })
+        self.port_idx(name)
+            .map(move |idx| self.adjacency[idx].iter())
             .into_iter()
             .flatten()
-            .filter_map(move |con_idx| self.ports.get(con_idx))
+            .filter_map(move |&con_idx| self.port_at(con_idx))
     }
 
     /// Gets an iterator over all connections between all ports in the graph.
@@ -221,180 +465,156 @@ impl JackGraph {
     pub fn all_connections<'a>(
         &'a self,
     ) -> impl Iterator<Item = (&'a PortData, &'a PortData)> + 'a {
-        let conref = &self.connections;
-        conref.iter().filter_map(move |&(a, b)| {
-            let a_name = self.ports.get(a)?;
-            let b_name = self.ports.get(b)?;
-            Some((a_name, b_name))
-        })
+        self.adjacency
+            .iter()
+            .enumerate()
+            .flat_map(|(a, others)| others.range(a + 1..).map(move |&b| (a, b)))
+            .filter_map(move |(a, b)| Some((self.port_at(a)?, self.port_at(b)?)))
     }
 
     /// Gets the full metadata of a port with the given `name`.
-    pub fn port_by_name<'a, 'b>(&'a self, name: &'b PortFullname) -> Option<&'a PortData> {
-        self.ports.iter().find(|data| &data.name == name)
+    pub fn port_by_name(&self, name: &PortFullname) -> Option<&PortData> {
+        self.port_at(self.port_idx(name)?)
+    }
+
+    /// Gets the full metadata of the port with the given name or alias.
+    pub fn find_port(&self, name: &PortFullname) -> Option<&PortData> {
+        self.port_by_name(name)
+            .or_else(|| self.port_at(*self.alias_indices.get(name)?))
+    }
+
+    /// Gets the human-readable name of `client`, if it has one.
+    pub fn client_pretty_name(&self, client: &str) -> Option<&str> {
+        self.client_properties
+            .get(client)?
+            .get(metadata::PRETTY_NAME)
+            .map(|name| name.as_str())
+    }
+
+    /// Checks whether `client` owns any physical ports, meaning it represents hardware.
+    pub fn client_is_physical(&self, client: &str) -> bool {
+        self.client_ports(client).any(|port| port.is_physical)
     }
 
     /// Gets an iterator over the names of all clients in the graph.
     pub fn all_clients<'a>(&'a self) -> impl Iterator<Item = &'a str> + 'a {
-        let first_client = self.ports.first().map(|data| data.name.client_name());
-        let mut cur_client = first_client;
-        let rest_iter = self
-            .ports
-            .iter()
-            .map(|data| &data.name)
-            .map(|fullname| fullname.client_name())
-            .filter(move |&cur| {
-                if Some(cur) == cur_client {
-                    false
-                } else {
-                    cur_client = Some(cur);
-                    true
-                }
-            });
-        first_client.into_iter().chain(rest_iter)
+        self.client_order.iter().map(|name| name.as_str())
     }
 
     /// Gets an iterator over all ports available for a given client name.
-    pub fn client_ports<'a>(&'a self, client: &'a str) -> impl Iterator<Item = &PortData> + 'a {
-        self.ports
-            .iter()
-            .skip_while(move |fullname| fullname.name.client_name() != client)
-            .take_while(move |fullname| fullname.name.client_name() == client)
+    pub fn client_ports<'a>(&'a self, client: &str) -> impl Iterator<Item = &'a PortData> + 'a {
+        self.client_ports
+            .get(client)
+            .into_iter()
+            .flatten()
+            .filter_map(move |&idx| self.port_at(idx))
     }
 
+    /// Gets an iterator over all ports in the graph, grouped by client.
     pub fn all_ports(&self) -> impl Iterator<Item = &PortData> {
-        self.ports.iter()
+        self.all_clients()
+            .flat_map(move |client| self.client_ports(client))
     }
 
     pub fn is_connected(&self, a: &PortFullname, b: &PortFullname) -> bool {
-        let mut aidx = None;
-        let mut bidx = None;
-        for (idx, cur) in self.ports.iter().enumerate() {
-            if &cur.name == a {
-                aidx = Some(idx);
-                if bidx.is_some() {
-                    break;
-                }
-            }
-            if &cur.name == b {
-                bidx = Some(idx);
-                if aidx.is_some() {
-                    break;
-                }
-            }
+        match self.port_idx(a).zip(self.port_idx(b)) {
+            Some((aidx, bidx)) => self.adjacency[aidx].contains(&bidx),
+            None => false,
         }
-        let (aidx, bidx) = match aidx.zip(bidx) {
-            Some(v) => v,
-            None => {
-                return false;
-            }
-        };
-        let key = if aidx < bidx {
-            (aidx, bidx)
-        } else {
-            (bidx, aidx)
-        };
-        self.connections.binary_search(&key).is_ok()
     }
 }
 
-/// Internal flag used to signal to the parent `JackGraph` that its data is stale.
-/// This is done by registering this struct as a `NotificationHandler` on the backing `Client`
-/// and setting an internal flag.
-#[derive(Debug)]
-struct Notifier {
-    /// The backing notification flag.
-    rf: Arc<AtomicBool>,
-    /// Used to wait for updates.
-    /// The `Mutex` is only used due to the fact that `Condvar`s must be associated
-    /// with exactly 1 `Mutex`.
-    cvar: Arc<(Mutex<()>, Condvar)>,
+/// Two graphs are equal if they have the same ports in the same order with the same
+/// connections, regardless of how they are laid out internally.
+impl PartialEq for PortGraph {
+    fn eq(&self, other: &Self) -> bool {
+        let connections_of = |graph: &PortGraph, port: &PortData| {
+            graph
+                .port_connections(&port.name)
+                .map(|other| other.name.clone())
+                .collect::<BTreeSet<_>>()
+        };
+        self.client_properties == other.client_properties
+            && self.all_ports().eq(other.all_ports())
+            && self
+                .all_ports()
+                .all(|port| connections_of(self, port) == connections_of(other, port))
+    }
 }
 
-impl Notifier {
-    /// Constructs a new `Notifier`.
-    pub fn new() -> Self {
-        Self {
-            rf: Arc::new(AtomicBool::new(false)),
-            cvar: Arc::new((Mutex::new(()), Condvar::new())),
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PortDirection;
+    use crate::testing::port;
 
-    /// Sets the internal event flag to indicate that the backing `jack::Client` has been updated.
-    pub fn set(&self) {
-        self.rf.store(true, Ordering::Release);
-        self.cvar.1.notify_all();
-    }
-    /// Resets the internal event flag to indicate that all new changes in the backing `jack::Client`
-    /// have been processed.
-    pub fn reset(&self) {
-        // Need SeqCst b/c we need to gurantee that the graph updating occurs
-        // *after* the store, and that the actual jack client data is updated
-        // *before* the store. Otherwise, if the notifier gets a change mid-update
-        // call, that notification could be written over without the change being
-        // loaded.
-        self.rf.store(false, Ordering::SeqCst);
-    }
+    #[test]
+    fn test_meter_level() {
+        let silence = MeterLevel::measure(&[0.0; 64]);
+        assert_eq!(silence, MeterLevel::default());
+        assert_eq!(silence.peak_db(), f32::NEG_INFINITY);
 
-    /// Returns whether or not there are unprocessed changes to the backing `jack::Client`.
-    pub fn check(&self) -> bool {
-        // Since we aren't actually touching the data yet,
-        // we can load this Relaxed and worry about casuality later.
-        self.rf.load(Ordering::Relaxed)
-    }
+        let square: Vec<f32> = (0..64)
+            .map(|n| if n % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        let level = MeterLevel::measure(&square);
+        assert_eq!(level.peak, 0.5);
+        assert!((level.rms - 0.5).abs() < 1e-6);
+        assert!((level.peak_db() + 6.0206).abs() < 1e-3);
 
-    /// Creates a new watcher for the same backing client.
-    /// Any calls to `set`, `reset`, or `check` will be reflected between `self` and the returned value.
-    pub fn handle(&self) -> Self {
-        Self {
-            rf: Arc::clone(&self.rf),
-            cvar: Arc::clone(&self.cvar),
-        }
+        let full_scale = MeterLevel::measure(&[0.0, -1.0, 0.0, 0.0]);
+        assert_eq!(full_scale.peak_db(), 0.0);
+        assert!((full_scale.rms - 0.5).abs() < 1e-6);
     }
 
-    /// Blocks the calling thread until a new event appears on the backing client
-    /// with an optional timeout.
-    #[allow(dead_code)]
-    pub fn wait_timeout(&self, dur: Option<Duration>) {
-        if self.check() {
-            return;
-        }
-        let (mtx, cvar) = &*self.cvar;
-        let lk = match mtx.try_lock() {
-            Ok(lk) => lk,
-            Err(TryLockError::Poisoned(e)) => e.into_inner(),
-            Err(TryLockError::WouldBlock) => {
-                return;
-            }
-        };
-        let lk = if let Some(dur) = dur {
-            cvar.wait_timeout_while(lk, dur, |_| !self.check())
-                .unwrap_or_else(|e| e.into_inner())
-                .0
-        } else {
-            cvar.wait_while(lk, |_| !self.check())
-                .unwrap_or_else(|e| e.into_inner())
-        };
-        drop(lk);
-    }
-}
+    #[test]
+    fn test_incremental_edits() {
+        let synth_out = port("synth:out_1", PortDirection::Out);
+        let playback = port("system:playback_1", PortDirection::In);
+        let capture = port("system:capture_1", PortDirection::Out);
+        let synth_in = port("synth:in_1", PortDirection::In);
 
-impl jack::NotificationHandler for Notifier {
-    fn graph_reorder(&mut self, _: &JackClient) -> jack::Control {
-        self.set();
-        jack::Control::Continue
-    }
-    fn ports_connected(&mut self, _: &JackClient, _: jack::PortId, _: jack::PortId, _: bool) {
-        self.set();
-    }
-    fn client_registration(&mut self, _: &JackClient, _name: &str, _is_registered: bool) {
-        self.set();
-    }
-    fn port_registration(&mut self, _: &JackClient, _port_id: jack::PortId, _is_registered: bool) {
-        self.set();
-    }
-    fn port_rename(&mut self, _: &JackClient, _: jack::PortId, _: &str, _: &str) -> jack::Control {
-        self.set();
-        jack::Control::Continue
+        let mut graph = PortGraph::new();
+        graph.add_port(synth_out.clone());
+        graph.add_port(playback.clone());
+        graph.add_connection(&synth_out.name, &playback.name);
+        graph.add_port(capture.clone());
+        graph.add_port(synth_in.clone());
+        graph.add_connection(&capture.name, &synth_in.name);
+
+        let synth_ports: Vec<_> = graph.client_ports("synth").map(|p| &p.name).collect();
+        assert_eq!(synth_ports, vec![&synth_out.name, &synth_in.name]);
+        assert!(graph.is_connected(&synth_out.name, &playback.name));
+        assert!(graph.is_connected(&capture.name, &synth_in.name));
+
+        let renamed = PortFullname::new("synth:main_out".to_owned()).unwrap();
+        assert!(graph.rename_port(&synth_out.name, renamed.clone()));
+        assert!(graph.is_connected(&renamed, &playback.name));
+
+        graph.remove_port(&renamed);
+        assert!(graph.port_connections(&playback.name).next().is_none());
+        assert!(graph.is_connected(&capture.name, &synth_in.name));
+
+        // Ports of one client stay grouped no matter what order they arrive in.
+        let late_capture = port("system:capture_2", PortDirection::Out);
+        graph.add_port(late_capture.clone());
+        let system_ports: Vec<_> = graph.client_ports("system").map(|p| &p.name).collect();
+        assert_eq!(
+            system_ports,
+            vec![&playback.name, &capture.name, &late_capture.name]
+        );
+
+        // Ports with an explicit order are placed before the rest.
+        let mut synth_first = port("synth:zz_first", PortDirection::Out);
+        synth_first.order = Some(1);
+        graph.add_port(synth_first.clone());
+        assert_eq!(
+            graph.client_ports("synth").next().map(|p| &p.name),
+            Some(&synth_first.name)
+        );
+
+        graph.remove_client("synth");
+        assert_eq!(graph.all_clients().collect::<Vec<_>>(), vec!["system"]);
+        assert!(graph.all_connections().next().is_none());
     }
 }
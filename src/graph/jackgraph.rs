@@ -0,0 +1,1105 @@
+use super::{
+    metadata, Bbt, GraphBackend, GraphError, MeterLevel, PortGraph, ServerInfo, TransportInfo,
+    TransportState,
+};
+use crate::model::{
+    LatencyRange, MidiEvent, MidiMessage, PortCategory, PortData, PortDirection, PortFullname,
+};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::ffi::{c_void, CString};
+use std::fmt;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, TryLockError};
+use std::time::Duration;
+
+use jack::Client as JackClient;
+
+/// Callback run by the `Notifier` whenever the backing `jack::Client` reports a change.
+/// Returning `jack::Control::Quit` asks JACK to stop sending further notifications.
+pub type UpdateListener = Box<dyn FnMut() -> jack::Control + Send + Sync>;
+
+/// A wrapper around the graph of JACK clients and ports.
+/// Note that this struct also caches information, and can therefore get stale.
+/// It is therefore wise to periodically poll for graph changes via the `needs_update()`
+/// method and reloading the graph via `update()`.
+#[derive(Debug)]
+pub struct JackGraph {
+    /// The underlying `jack::Client` that will be used for synchronizing state.
+    client: jack::AsyncClient<Notifier, Monitors>,
+
+    /// The cached ports and connections.
+    graph: PortGraph,
+
+    /// Set by the backing `jack::Client` whenever the graph changes.
+    update_flag: Notifier,
+
+    /// The ports each metadata subject UUID refers to.
+    port_uuids: HashMap<u64, PortFullname>,
+
+    /// The clients each metadata subject UUID refers to.
+    client_uuids: HashMap<u64, String>,
+
+    /// Meter and MIDI tap ports seen in the graph, which are left out of the cached graph.
+    hidden_ports: HashSet<PortFullname>,
+
+    /// The ports measured by the process callback, shared with it.
+    meters: Arc<Mutex<Vec<MeterChannel>>>,
+
+    /// The ports whose MIDI is forwarded by the process callback, shared with it.
+    midi_taps: Arc<Mutex<Vec<MidiTap>>>,
+
+    /// The receiving ends of the MIDI forwarded by each tap.
+    midi_readers: Vec<MidiReader>,
+
+    /// Used to give each port trej registers a unique name.
+    next_port: usize,
+
+    /// The tempo used by the timebase callback, which is handed a pointer to it.
+    timebase: Arc<Timebase>,
+
+    /// Whether the timebase callback is registered.
+    is_timebase_master: bool,
+}
+
+impl JackGraph {
+    /// Constructs a new `JackGraph` wrapping the given `jack::Client`.
+    pub fn new(client: JackClient) -> Result<Self, GraphError> {
+        Self::build(client, Notifier::new())
+    }
+
+    /// Constructs a new `JackGraph` wrapping the given `jack::Client` which will also
+    /// call `listener` whenever the underlying graph changes.
+    pub fn with_listener(client: JackClient, listener: UpdateListener) -> Result<Self, GraphError> {
+        Self::build(client, Notifier::new().with_listener(listener))
+    }
+
+    fn build(mut client: JackClient, notifier: Notifier) -> Result<Self, GraphError> {
+        let update_flag = notifier.handle();
+        client.register_property_change_handler(notifier.handle())?;
+        let monitors = Monitors::new();
+        let meters = Arc::clone(&monitors.meters);
+        let midi_taps = Arc::clone(&monitors.midi_taps);
+        let client = client.activate_async(notifier, monitors)?;
+        let mut retvl = JackGraph {
+            client,
+            update_flag,
+            graph: PortGraph::new(),
+            port_uuids: HashMap::new(),
+            client_uuids: HashMap::new(),
+            hidden_ports: HashSet::new(),
+            meters,
+            midi_taps,
+            midi_readers: Vec::new(),
+            next_port: 0,
+            timebase: Arc::new(Timebase::default()),
+            is_timebase_master: false,
+        };
+        retvl.resync()?;
+        Ok(retvl)
+    }
+}
+
+impl GraphBackend for JackGraph {
+    fn graph(&self) -> &PortGraph {
+        &self.graph
+    }
+
+    fn disconnect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError> {
+        self.client
+            .as_client()
+            .disconnect_ports_by_name(source.as_ref(), dest.as_ref())?;
+        if !self.graph.remove_connection(source, dest) {
+            self.resync()?;
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError> {
+        self.client
+            .as_client()
+            .connect_ports_by_name(source.as_ref(), dest.as_ref())?;
+        if !self.graph.add_connection(source, dest) {
+            self.resync()?;
+        }
+        Ok(())
+    }
+
+    fn needs_update(&self) -> bool {
+        self.update_flag.check()
+    }
+
+    fn server_info(&self) -> Option<ServerInfo> {
+        let client = self.client.as_client();
+        let requested_name =
+            std::env::var("JACK_DEFAULT_SERVER").unwrap_or_else(|_| "default".to_owned());
+        let realtime = unsafe { jack_sys::jack_is_realtime(client.raw()) } != 0;
+        Some(ServerInfo {
+            requested_name,
+            sample_rate: client.sample_rate(),
+            buffer_size: client.buffer_size(),
+            dsp_load: client.cpu_load(),
+            realtime,
+            xruns: self.update_flag.xruns(),
+            freewheel: self.update_flag.freewheel(),
+        })
+    }
+
+    fn set_buffer_size(&mut self, frames: u32) -> Result<(), GraphError> {
+        self.client
+            .as_client()
+            .set_buffer_size(frames)
+            .map_err(|_| GraphError::Refused(format!("set the buffer size to {} frames", frames)))
+    }
+
+    fn set_freewheel(&mut self, enabled: bool) -> Result<(), GraphError> {
+        let client = self.client.as_client();
+        let res = unsafe { jack_sys::jack_set_freewheel(client.raw(), enabled as _) };
+        if res == 0 {
+            Ok(())
+        } else if enabled {
+            Err(GraphError::Refused("start freewheeling".to_owned()))
+        } else {
+            Err(GraphError::Refused("stop freewheeling".to_owned()))
+        }
+    }
+
+    fn transport(&self) -> Option<TransportInfo> {
+        let client = self.client.as_client();
+        let raw = client.transport().query().ok()?;
+        let state = match raw.state {
+            jack::TransportState::Stopped => TransportState::Stopped,
+            jack::TransportState::Starting => TransportState::Starting,
+            jack::TransportState::Rolling => TransportState::Rolling,
+        };
+        let bbt = raw.pos.bbt().map(|bbt| Bbt {
+            bar: bbt.bar as u32,
+            beat: bbt.beat as u32,
+            tick: bbt.tick as u32,
+            bar_start_tick: bbt.bar_start_tick,
+            beats_per_bar: bbt.sig_num,
+            beat_type: bbt.sig_denom,
+            ticks_per_beat: bbt.ticks_per_beat,
+            bpm: bbt.bpm,
+        });
+        Some(TransportInfo {
+            state,
+            frame: raw.pos.frame(),
+            frame_rate: raw
+                .pos
+                .frame_rate()
+                .unwrap_or_else(|| client.sample_rate() as u32),
+            bbt,
+        })
+    }
+
+    fn set_transport_rolling(&mut self, rolling: bool) -> Result<(), GraphError> {
+        let transport = self.client.as_client().transport();
+        if rolling {
+            transport.start()?;
+        } else {
+            transport.stop()?;
+        }
+        Ok(())
+    }
+
+    fn locate_transport(&mut self, frame: u32) -> Result<(), GraphError> {
+        self.client.as_client().transport().locate(frame)?;
+        Ok(())
+    }
+
+    fn set_timebase(&mut self, bpm: Option<f64>) -> Result<(), GraphError> {
+        let client = self.client.as_client();
+        let bpm = match bpm {
+            Some(bpm) => bpm,
+            None => {
+                if self.is_timebase_master {
+                    unsafe { jack_sys::jack_release_timebase(client.raw()) };
+                    self.is_timebase_master = false;
+                }
+                return Ok(());
+            }
+        };
+        self.timebase.set_bpm(bpm);
+        if self.is_timebase_master {
+            return Ok(());
+        }
+        // The callback is only ever handed a pointer to `self.timebase`, which lives
+        // at least as long as `self.client`.
+        let arg = Arc::as_ptr(&self.timebase) as *mut c_void;
+        let res = unsafe {
+            jack_sys::jack_set_timebase_callback(client.raw(), 0, Some(timebase_callback), arg)
+        };
+        if res != 0 {
+            return Err(GraphError::Refused(
+                "make trej the timebase master".to_owned(),
+            ));
+        }
+        self.is_timebase_master = true;
+        Ok(())
+    }
+
+    fn set_meters(&mut self, ports: &[PortFullname]) -> Result<(), GraphError> {
+        let client = self.client.as_client();
+        let mut channels = self.meters.lock().unwrap_or_else(|e| e.into_inner());
+        let (kept, dropped): (Vec<_>, Vec<_>) = channels
+            .drain(..)
+            .partition(|chan| ports.contains(&chan.source));
+        *channels = kept;
+        for chan in dropped {
+            client.unregister_port(chan.port)?;
+        }
+        for source in ports {
+            if channels.iter().any(|chan| &chan.source == source) {
+                continue;
+            }
+            let port_name = format!("meter_{}", self.next_port);
+            self.next_port += 1;
+            let port = client.register_port(&port_name, jack::AudioIn)?;
+            if let Err(e) = attach_monitor(client, &port, source) {
+                client.unregister_port(port).ok();
+                return Err(e.into());
+            }
+            channels.push(MeterChannel {
+                port,
+                source: source.clone(),
+                level: Arc::new(MeterCell::default()),
+            });
+        }
+        Ok(())
+    }
+
+    fn meter_levels(&self) -> Vec<(PortFullname, MeterLevel)> {
+        let channels = self.meters.lock().unwrap_or_else(|e| e.into_inner());
+        channels
+            .iter()
+            .map(|chan| (chan.source.clone(), chan.level.take()))
+            .collect()
+    }
+
+    fn set_midi_taps(&mut self, ports: &[PortFullname]) -> Result<(), GraphError> {
+        let client = self.client.as_client();
+        let mut taps = self.midi_taps.lock().unwrap_or_else(|e| e.into_inner());
+        let (kept, dropped): (Vec<_>, Vec<_>) =
+            taps.drain(..).partition(|tap| ports.contains(&tap.source));
+        *taps = kept;
+        self.midi_readers
+            .retain(|reader| ports.contains(&reader.source));
+        for tap in dropped {
+            client.unregister_port(tap.port)?;
+        }
+        for source in ports {
+            if taps.iter().any(|tap| &tap.source == source) {
+                continue;
+            }
+            let port_name = format!("midi_tap_{}", self.next_port);
+            self.next_port += 1;
+            let buffer = jack::RingBuffer::new(MIDI_TAP_BUFFER_SIZE)
+                .map_err(|()| jack::Error::NotEnoughSpace)?;
+            let port = client.register_port(&port_name, jack::MidiIn)?;
+            if let Err(e) = attach_monitor(client, &port, source) {
+                client.unregister_port(port).ok();
+                return Err(e.into());
+            }
+            let (reader, writer) = buffer.into_reader_writer();
+            self.midi_readers.push(MidiReader {
+                source: source.clone(),
+                reader,
+            });
+            taps.push(MidiTap {
+                port,
+                source: source.clone(),
+                writer,
+            });
+        }
+        Ok(())
+    }
+
+    fn take_midi_events(&mut self) -> Vec<(PortFullname, MidiEvent)> {
+        let mut retvl = Vec::new();
+        for reader in self.midi_readers.iter_mut() {
+            let mut record = [0; MIDI_RECORD_LEN];
+            while reader.reader.read_buffer(&mut record) == MIDI_RECORD_LEN {
+                retvl.push((reader.source.clone(), decode_midi_record(&record)));
+            }
+        }
+        retvl
+    }
+
+    /// Applies all changes queued by the backing `jack::Client` since the last update,
+    /// falling back to a full `resync()` if any of them cannot be applied.
+    fn update(&mut self) -> Result<(), GraphError> {
+        self.update_flag.reset();
+        let events = self.update_flag.take_events();
+        if events.contains(&GraphEvent::Resync) {
+            return self.resync();
+        }
+        for evt in events {
+            if !self.apply_event(evt)? {
+                return self.resync();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl JackGraph {
+    /// Throws away the cached graph and reloads it from the underlying `jack::Client`.
+    pub fn resync(&mut self) -> Result<(), GraphError> {
+        self.update_flag.reset();
+        self.update_flag.take_events();
+
+        let raw_names = self
+            .client
+            .as_client()
+            .ports(None, None, jack::PortFlags::empty());
+        let port_names = raw_names
+            .into_iter()
+            .map(PortFullname::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.graph.clear();
+        self.port_uuids.clear();
+        self.client_uuids.clear();
+        self.hidden_ports.clear();
+        let mut outputs = Vec::new();
+        let mut inputs = Vec::new();
+        for name in port_names {
+            let port = match self.client.as_client().port_by_name(name.as_ref()) {
+                Some(p) => p,
+                None => {
+                    continue;
+                }
+            };
+            let data = self.load_port(name, &port)?;
+            if self.is_hidden(&data) {
+                self.hidden_ports.insert(data.name);
+                continue;
+            }
+            if data.direction.is_output() {
+                outputs.push((data.name.clone(), port));
+            } else {
+                inputs.push(data.name.clone());
+            }
+            self.graph.add_port(data);
+        }
+        let clients: Vec<_> = self.graph.all_clients().map(|c| c.to_owned()).collect();
+        for client in clients {
+            self.load_client(client);
+        }
+        // Connections always go from an output to an input, so there is no need to
+        // check any other pairs.
+        for (output_name, output) in outputs.iter() {
+            for input_name in inputs.iter() {
+                if output.is_connected_to(input_name.as_ref())? {
+                    self.graph.add_connection(output_name, input_name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a single change to the cached graph.
+    /// Returns `false` if the change is inconsistent with the cache, meaning a full
+    /// resync is needed.
+    fn apply_event(&mut self, evt: GraphEvent) -> Result<bool, GraphError> {
+        let retvl = match evt {
+            GraphEvent::PortRegistered(name) => {
+                // If the port is already gone, its unregistration is also queued.
+                if let Some(port) = self.client.as_client().port_by_name(name.as_ref()) {
+                    let data = self.load_port(name, &port)?;
+                    self.store_port(data);
+                }
+                true
+            }
+            GraphEvent::PortUnregistered(name) => {
+                self.hidden_ports.remove(&name);
+                self.graph.remove_port(&name);
+                true
+            }
+            GraphEvent::PortRenamed { old, new } => {
+                for name in self.port_uuids.values_mut().filter(|name| **name == old) {
+                    *name = new.clone();
+                }
+                if self.hidden_ports.remove(&old) {
+                    self.hidden_ports.insert(new);
+                    return Ok(true);
+                }
+                self.graph.port_by_name(&old).is_none() || self.graph.rename_port(&old, new)
+            }
+            GraphEvent::Connected(a, b) => {
+                self.hidden_ports.contains(&a)
+                    || self.hidden_ports.contains(&b)
+                    || self.graph.add_connection(&a, &b)
+            }
+            GraphEvent::Disconnected(a, b) => {
+                self.graph.remove_connection(&a, &b);
+                true
+            }
+            GraphEvent::ClientRegistered(name) => {
+                self.load_client(name);
+                true
+            }
+            GraphEvent::ClientUnregistered(name) => {
+                self.graph.remove_client(&name);
+                true
+            }
+            GraphEvent::PropertyChanged(subject) => {
+                if let Some(name) = self.port_uuids.get(&subject).cloned() {
+                    if let Some(port) = self.client.as_client().port_by_name(name.as_ref()) {
+                        let data = self.load_port(name, &port)?;
+                        self.store_port(data);
+                    }
+                } else if let Some(client) = self.client_uuids.get(&subject).cloned() {
+                    self.load_client(client);
+                }
+                true
+            }
+            GraphEvent::LatencyChanged => {
+                self.reload_latencies();
+                true
+            }
+            GraphEvent::Resync => false,
+        };
+        Ok(retvl)
+    }
+
+    /// Reads the data and metadata of `port`, remembering its metadata UUID.
+    fn load_port<PS>(
+        &mut self,
+        name: PortFullname,
+        port: &jack::Port<PS>,
+    ) -> Result<PortData, GraphError> {
+        let mut retvl = port_data(name, port)?;
+        let uuid = unsafe { jack_sys::jack_port_uuid(port.raw()) };
+        retvl.properties = self.subject_properties(uuid);
+        metadata::apply_port_metadata(&mut retvl);
+        // CV ports share the audio port type, so they can only be told apart by metadata.
+        retvl.category =
+            PortCategory::from_port_type(&retvl.port_type, retvl.signal_type.as_deref());
+        self.port_uuids.insert(uuid, retvl.name.clone());
+        Ok(retvl)
+    }
+
+    /// Checks whether `data` is a meter or MIDI tap port, either one of ours or one
+    /// belonging to another instance of trej. These are kept out of the cached graph, so
+    /// they are neither shown nor subject to any locks.
+    fn is_hidden(&self, data: &PortData) -> bool {
+        data.name.client_name() == self.client.as_client().name()
+            || data.properties.contains_key(metadata::METER)
+    }
+
+    /// Adds `data` to the cached graph, or drops it from the graph if it turns out to be
+    /// a meter or MIDI tap port.
+    fn store_port(&mut self, data: PortData) {
+        if self.is_hidden(&data) {
+            self.graph.remove_port(&data.name);
+            self.hidden_ports.insert(data.name);
+        } else {
+            self.graph.add_port(data);
+        }
+    }
+
+    /// Re-reads the latency ranges of every port in the cached graph.
+    fn reload_latencies(&mut self) {
+        let names: Vec<_> = self.graph.all_ports().map(|p| p.name.clone()).collect();
+        for name in names {
+            let port = match self.client.as_client().port_by_name(name.as_ref()) {
+                Some(p) => p,
+                None => {
+                    continue;
+                }
+            };
+            let mut data = match self.graph.port_by_name(&name) {
+                Some(data) => data.clone(),
+                None => {
+                    continue;
+                }
+            };
+            let (capture, playback) = port_latencies(&port);
+            if data.capture_latency != capture || data.playback_latency != playback {
+                data.capture_latency = capture;
+                data.playback_latency = playback;
+                self.graph.add_port(data);
+            }
+        }
+    }
+
+    /// Reads the metadata of the client named `name` into the cached graph.
+    fn load_client(&mut self, name: String) {
+        let uuid = match client_uuid(self.client.as_client(), &name) {
+            Some(uuid) => uuid,
+            None => {
+                return;
+            }
+        };
+        let properties = self.subject_properties(uuid);
+        self.client_uuids.insert(uuid, name.clone());
+        self.graph.set_client_properties(name, properties);
+    }
+
+    /// Gets all metadata attached to `subject` as plain key/value pairs.
+    fn subject_properties(&self, subject: u64) -> BTreeMap<String, String> {
+        self.client
+            .as_client()
+            .property_get_subject(subject)
+            .into_iter()
+            .flatten()
+            .map(|(key, prop)| (key, prop.value().to_owned()))
+            .collect()
+    }
+}
+
+/// Looks up the metadata UUID of the client named `name`.
+fn client_uuid(client: &JackClient, name: &str) -> Option<u64> {
+    let name = CString::new(name).ok()?;
+    let mut uuid = 0;
+    let res = unsafe {
+        let raw = jack_sys::jack_get_uuid_for_client_name(client.raw(), name.as_ptr());
+        if raw.is_null() {
+            return None;
+        }
+        let res = jack_sys::jack_uuid_parse(raw, &mut uuid);
+        jack_sys::jack_free(raw as *mut _);
+        res
+    };
+    if res == 0 {
+        Some(uuid)
+    } else {
+        None
+    }
+}
+
+/// Tags `port` as monitoring `source` and connects `source` to it.
+fn attach_monitor<PS>(
+    client: &jack::Client,
+    port: &jack::Port<PS>,
+    source: &PortFullname,
+) -> Result<(), jack::Error> {
+    let uuid = unsafe { jack_sys::jack_port_uuid(port.raw()) };
+    let tag = jack::Property::new(source.as_ref(), None);
+    client.property_set(uuid, metadata::METER, &tag)?;
+    client.connect_ports_by_name(source.as_ref(), &port.name()?)?;
+    Ok(())
+}
+
+/// Reads the metadata of a JACK port into a `PortData`.
+fn port_data<PS>(name: PortFullname, port: &jack::Port<PS>) -> Result<PortData, GraphError> {
+    let flags = port.flags();
+    let direction = if flags.contains(jack::PortFlags::IS_INPUT) {
+        PortDirection::In
+    } else {
+        PortDirection::Out
+    };
+    let port_type = port.port_type()?;
+    let category = PortCategory::from_port_type(&port_type, None);
+    let mut retvl = PortData::new(name, category, direction);
+    retvl.port_type = port_type;
+    retvl.is_physical = flags.contains(jack::PortFlags::IS_PHYSICAL);
+    retvl.is_terminal = flags.contains(jack::PortFlags::IS_TERMINAL);
+    retvl.can_monitor = flags.contains(jack::PortFlags::CAN_MONITOR);
+    retvl.is_monitoring = port.is_monitoring_input()?;
+    let (capture, playback) = port_latencies(port);
+    retvl.capture_latency = capture;
+    retvl.playback_latency = playback;
+    // Aliases that aren't in `client:port` form can't be used in a config anyway.
+    retvl.aliases = port
+        .aliases()?
+        .into_iter()
+        .filter_map(|alias| PortFullname::try_from(alias).ok())
+        .collect();
+    Ok(retvl)
+}
+
+/// Reads the capture and playback latency ranges of a JACK port.
+/// JACK always reports both ranges, but only capture latency describes an output port
+/// and only playback latency describes an input port; the other range is left out
+/// unless a client has actually set it.
+fn port_latencies<PS>(port: &jack::Port<PS>) -> (Option<LatencyRange>, Option<LatencyRange>) {
+    let is_input = port.flags().contains(jack::PortFlags::IS_INPUT);
+    let read_range = |mode, applies| {
+        let (min, max) = port.get_latency_range(mode);
+        if applies || max != 0 {
+            Some(LatencyRange { min, max })
+        } else {
+            None
+        }
+    };
+    let capture = read_range(jack::LatencyType::Capture, !is_input);
+    let playback = read_range(jack::LatencyType::Playback, is_input);
+    (capture, playback)
+}
+
+/// The latest levels measured on a meter port.
+/// Both levels are stored as the bits of an `f32`; since they are never negative,
+/// comparing the bits as integers orders them the same way as comparing the floats.
+#[derive(Debug, Default)]
+struct MeterCell {
+    /// The highest peak seen since the levels were last taken.
+    peak: AtomicU32,
+    /// The RMS level of the most recent cycle.
+    rms: AtomicU32,
+}
+
+impl MeterCell {
+    /// Records the levels measured during a single cycle.
+    fn record(&self, level: MeterLevel) {
+        self.peak.fetch_max(level.peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(level.rms.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Gets the levels recorded so far, resetting the peak.
+    fn take(&self) -> MeterLevel {
+        let peak = f32::from_bits(self.peak.swap(0, Ordering::Relaxed));
+        let rms = f32::from_bits(self.rms.load(Ordering::Relaxed));
+        MeterLevel { peak, rms }
+    }
+}
+
+/// A hidden input port connected to the output port it measures.
+#[derive(Debug)]
+struct MeterChannel {
+    port: jack::Port<jack::AudioIn>,
+    /// The port being measured.
+    source: PortFullname,
+    level: Arc<MeterCell>,
+}
+
+/// The number of bytes each MIDI event takes up on its way out of the process callback:
+/// an 8 byte timestamp, the 4 byte length of the message, and its first 3 bytes.
+/// Nothing past the first 3 bytes is ever decoded, so longer messages such as SysEx
+/// are cut short rather than allocating in the process callback.
+const MIDI_RECORD_LEN: usize = 16;
+
+/// The size of the buffer between the process callback and each `MidiReader`.
+const MIDI_TAP_BUFFER_SIZE: usize = MIDI_RECORD_LEN * 1024;
+
+fn encode_midi_record(time_us: u64, bytes: &[u8]) -> [u8; MIDI_RECORD_LEN] {
+    let mut record = [0; MIDI_RECORD_LEN];
+    record[..8].copy_from_slice(&time_us.to_le_bytes());
+    record[8..12].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+    let prefix_len = bytes.len().min(3);
+    record[12..12 + prefix_len].copy_from_slice(&bytes[..prefix_len]);
+    record
+}
+
+fn decode_midi_record(record: &[u8; MIDI_RECORD_LEN]) -> MidiEvent {
+    let mut time_bytes = [0; 8];
+    time_bytes.copy_from_slice(&record[..8]);
+    let mut len_bytes = [0; 4];
+    len_bytes.copy_from_slice(&record[8..12]);
+    let len = u32::from_le_bytes(len_bytes) as usize;
+    let prefix_len = len.min(3);
+    MidiEvent {
+        time_us: u64::from_le_bytes(time_bytes),
+        message: MidiMessage::parse(&record[12..12 + prefix_len], len),
+    }
+}
+
+/// A hidden MIDI input connected to the output port it listens to.
+struct MidiTap {
+    port: jack::Port<jack::MidiIn>,
+    /// The port being listened to.
+    source: PortFullname,
+    /// Where the process callback sends the events it receives.
+    writer: jack::RingBufferWriter,
+}
+
+impl fmt::Debug for MidiTap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MidiTap")
+            .field("port", &self.port)
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+/// The receiving end of a `MidiTap`.
+struct MidiReader {
+    source: PortFullname,
+    reader: jack::RingBufferReader,
+}
+
+impl fmt::Debug for MidiReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MidiReader")
+            .field("source", &self.source)
+            .field("pending", &(self.reader.space() / MIDI_RECORD_LEN))
+            .finish()
+    }
+}
+
+/// The `ProcessHandler` of the backing `jack::Client`, which measures the signal
+/// arriving at each meter port and forwards the MIDI arriving at each MIDI tap.
+/// Both lists are shared with the parent `JackGraph`. The process callback only ever
+/// tries to lock them, and skips the cycle if the `JackGraph` is busy changing them.
+struct Monitors {
+    meters: Arc<Mutex<Vec<MeterChannel>>>,
+    midi_taps: Arc<Mutex<Vec<MidiTap>>>,
+}
+
+impl Monitors {
+    fn new() -> Self {
+        Self {
+            meters: Arc::new(Mutex::new(Vec::new())),
+            midi_taps: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl jack::ProcessHandler for Monitors {
+    fn process(&mut self, client: &jack::Client, ps: &jack::ProcessScope) -> jack::Control {
+        if let Ok(channels) = self.meters.try_lock() {
+            for chan in channels.iter() {
+                chan.level
+                    .record(MeterLevel::measure(chan.port.as_slice(ps)));
+            }
+        }
+        if let Ok(mut taps) = self.midi_taps.try_lock() {
+            let cycle_start = ps.last_frame_time();
+            for tap in taps.iter_mut() {
+                for evt in tap.port.iter(ps) {
+                    if tap.writer.space() < MIDI_RECORD_LEN {
+                        // Nobody is reading fast enough, so drop the rest.
+                        break;
+                    }
+                    let time_us = client.frames_to_time(cycle_start.wrapping_add(evt.time));
+                    tap.writer
+                        .write_buffer(&encode_midi_record(time_us, evt.bytes));
+                }
+            }
+        }
+        jack::Control::Continue
+    }
+}
+
+/// The tempo trej counts bars and beats at while it is the timebase master.
+/// Always in 4/4 time.
+#[derive(Debug)]
+struct Timebase {
+    /// The bits of the `f64` beats per minute.
+    bpm: AtomicU64,
+}
+
+impl Default for Timebase {
+    fn default() -> Self {
+        Self {
+            bpm: AtomicU64::new(120.0f64.to_bits()),
+        }
+    }
+}
+
+impl Timebase {
+    const BEATS_PER_BAR: f32 = 4.0;
+    const BEAT_TYPE: f32 = 4.0;
+
+    fn bpm(&self) -> f64 {
+        f64::from_bits(self.bpm.load(Ordering::Relaxed))
+    }
+
+    fn set_bpm(&self, bpm: f64) {
+        self.bpm.store(bpm.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Fills in the bars and beats of the transport position on behalf of JACK, while trej
+/// is the timebase master.
+/// `arg` must point to the `Timebase` owned by the registering `JackGraph`.
+unsafe extern "C" fn timebase_callback(
+    _state: jack_sys::jack_transport_state_t,
+    _nframes: jack_sys::jack_nframes_t,
+    pos: *mut jack_sys::jack_position_t,
+    _new_pos: c_int,
+    arg: *mut c_void,
+) {
+    let timebase = &*(arg as *const Timebase);
+    let bbt = Bbt::at_frame(
+        (*pos).frame,
+        (*pos).frame_rate,
+        timebase.bpm(),
+        Timebase::BEATS_PER_BAR,
+        Timebase::BEAT_TYPE,
+    );
+    (*pos).valid |= jack_sys::JackPositionBBT;
+    (*pos).bar = bbt.bar as i32;
+    (*pos).beat = bbt.beat as i32;
+    (*pos).tick = bbt.tick as i32;
+    (*pos).bar_start_tick = bbt.bar_start_tick;
+    (*pos).beats_per_bar = bbt.beats_per_bar;
+    (*pos).beat_type = bbt.beat_type;
+    (*pos).ticks_per_beat = bbt.ticks_per_beat;
+    (*pos).beats_per_minute = bbt.bpm;
+}
+
+/// A single change to the JACK graph, as reported to the `Notifier`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum GraphEvent {
+    PortRegistered(PortFullname),
+    PortUnregistered(PortFullname),
+    PortRenamed {
+        old: PortFullname,
+        new: PortFullname,
+    },
+    Connected(PortFullname, PortFullname),
+    Disconnected(PortFullname, PortFullname),
+    ClientRegistered(String),
+    ClientUnregistered(String),
+    /// JACK recomputed the latencies of some or all ports.
+    LatencyChanged,
+    /// The metadata of the port or client with the given UUID changed.
+    PropertyChanged(u64),
+    /// The change could not be described, so the whole graph needs to be reloaded.
+    Resync,
+}
+
+/// The maximum number of events the `Notifier` will hold onto before giving up
+/// and requesting a full resync instead.
+const MAX_QUEUED_EVENTS: usize = 4096;
+
+/// Internal flag used to signal to the parent `JackGraph` that its data is stale.
+/// This is done by registering this struct as a `NotificationHandler` on the backing `Client`,
+/// queueing up each change it reports, and setting an internal flag.
+struct Notifier {
+    /// The backing notification flag.
+    rf: Arc<AtomicBool>,
+    /// Changes reported by the backing `jack::Client` that have not yet been applied.
+    events: Arc<Mutex<VecDeque<GraphEvent>>>,
+    /// The number of xruns reported by the backing `jack::Client`.
+    xruns: Arc<AtomicU64>,
+    /// Whether the server is currently freewheeling.
+    freewheel: Arc<AtomicBool>,
+    /// Used to wait for updates.
+    /// The `Mutex` is only used due to the fact that `Condvar`s must be associated
+    /// with exactly 1 `Mutex`.
+    cvar: Arc<(Mutex<()>, Condvar)>,
+    /// Optional extra callback run after the flag is set.
+    /// Shared between handles, since both the notification and the property change
+    /// handlers registered with the `jack::Client` need to call it.
+    listener: Arc<Mutex<Option<UpdateListener>>>,
+}
+
+impl fmt::Debug for Notifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Notifier")
+            .field("rf", &self.rf)
+            .field("events", &self.events)
+            .field("xruns", &self.xruns)
+            .field("freewheel", &self.freewheel)
+            .field("cvar", &self.cvar)
+            .field("listener", &self.listener.lock().map(|l| l.is_some()).ok())
+            .finish()
+    }
+}
+
+impl Notifier {
+    /// Constructs a new `Notifier`.
+    pub fn new() -> Self {
+        Self {
+            rf: Arc::new(AtomicBool::new(false)),
+            events: Arc::new(Mutex::new(VecDeque::new())),
+            xruns: Arc::new(AtomicU64::new(0)),
+            freewheel: Arc::new(AtomicBool::new(false)),
+            cvar: Arc::new((Mutex::new(()), Condvar::new())),
+            listener: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Adds a callback to run whenever the backing `jack::Client` has been updated.
+    pub fn with_listener(self, listener: UpdateListener) -> Self {
+        Self {
+            listener: Arc::new(Mutex::new(Some(listener))),
+            ..self
+        }
+    }
+
+    /// Sets the internal event flag to indicate that the backing `jack::Client` has been updated.
+    pub fn set(&mut self) -> jack::Control {
+        self.rf.store(true, Ordering::Release);
+        self.cvar.1.notify_all();
+        let mut listener = self.listener.lock().unwrap_or_else(|e| e.into_inner());
+        match listener.as_mut() {
+            Some(listener) => listener(),
+            None => jack::Control::Continue,
+        }
+    }
+    /// Queues up a change to the backing `jack::Client` and then sets the internal event flag.
+    pub fn push(&mut self, evt: GraphEvent) -> jack::Control {
+        {
+            let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+            if events.len() >= MAX_QUEUED_EVENTS {
+                events.clear();
+                events.push_back(GraphEvent::Resync);
+            } else if events.back() != Some(&GraphEvent::Resync) {
+                events.push_back(evt);
+            }
+        }
+        self.set()
+    }
+
+    /// Removes and returns all changes queued so far.
+    pub fn take_events(&self) -> Vec<GraphEvent> {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events.drain(..).collect()
+    }
+
+    /// Gets the number of xruns reported by the backing `jack::Client` so far.
+    pub fn xruns(&self) -> u64 {
+        self.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Gets whether the backing `jack::Client` last reported freewheel mode as on.
+    pub fn freewheel(&self) -> bool {
+        self.freewheel.load(Ordering::Relaxed)
+    }
+
+    /// Resets the internal event flag to indicate that all new changes in the backing `jack::Client`
+    /// have been processed.
+    pub fn reset(&self) {
+        // Need SeqCst b/c we need to gurantee that the graph updating occurs
+        // *after* the store, and that the actual jack client data is updated
+        // *before* the store. Otherwise, if the notifier gets a change mid-update
+        // call, that notification could be written over without the change being
+        // loaded.
+        self.rf.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns whether or not there are unprocessed changes to the backing `jack::Client`.
+    pub fn check(&self) -> bool {
+        // Since we aren't actually touching the data yet,
+        // we can load this Relaxed and worry about casuality later.
+        self.rf.load(Ordering::Relaxed)
+    }
+
+    /// Creates a new watcher for the same backing client.
+    /// Any calls to `set`, `reset`, or `check` will be reflected between `self` and the returned value.
+    pub fn handle(&self) -> Self {
+        Self {
+            rf: Arc::clone(&self.rf),
+            events: Arc::clone(&self.events),
+            xruns: Arc::clone(&self.xruns),
+            freewheel: Arc::clone(&self.freewheel),
+            cvar: Arc::clone(&self.cvar),
+            listener: Arc::clone(&self.listener),
+        }
+    }
+
+    /// Blocks the calling thread until a new event appears on the backing client
+    /// with an optional timeout.
+    #[allow(dead_code)]
+    pub fn wait_timeout(&self, dur: Option<Duration>) {
+        if self.check() {
+            return;
+        }
+        let (mtx, cvar) = &*self.cvar;
+        let lk = match mtx.try_lock() {
+            Ok(lk) => lk,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                return;
+            }
+        };
+        let lk = if let Some(dur) = dur {
+            cvar.wait_timeout_while(lk, dur, |_| !self.check())
+                .unwrap_or_else(|e| e.into_inner())
+                .0
+        } else {
+            cvar.wait_while(lk, |_| !self.check())
+                .unwrap_or_else(|e| e.into_inner())
+        };
+        drop(lk);
+    }
+}
+
+/// Looks up the full name of the port with the given ID.
+fn port_name(client: &JackClient, port_id: jack::PortId) -> Option<PortFullname> {
+    let raw = client.port_by_id(port_id)?.name().ok()?;
+    PortFullname::try_from(raw).ok()
+}
+
+impl jack::NotificationHandler for Notifier {
+    fn xrun(&mut self, _: &JackClient) -> jack::Control {
+        // Xruns don't change the graph, so there is nothing to flag; the count is
+        // just read the next time the server info is.
+        self.xruns.fetch_add(1, Ordering::Relaxed);
+        jack::Control::Continue
+    }
+    fn buffer_size(&mut self, _: &JackClient, _: jack::Frames) -> jack::Control {
+        self.set()
+    }
+    fn freewheel(&mut self, _: &JackClient, is_enabled: bool) {
+        self.freewheel.store(is_enabled, Ordering::Relaxed);
+        self.set();
+    }
+    fn latency(&mut self, _: &JackClient, mode: jack::LatencyType) {
+        // JACK calls this once per mode, so only react to one of them.
+        if let jack::LatencyType::Playback = mode {
+            self.push(GraphEvent::LatencyChanged);
+        }
+    }
+    fn graph_reorder(&mut self, _: &JackClient) -> jack::Control {
+        self.set()
+    }
+    fn ports_connected(
+        &mut self,
+        client: &JackClient,
+        port_a: jack::PortId,
+        port_b: jack::PortId,
+        are_connected: bool,
+    ) {
+        let names = port_name(client, port_a).zip(port_name(client, port_b));
+        let evt = match names {
+            Some((a, b)) if are_connected => GraphEvent::Connected(a, b),
+            Some((a, b)) => GraphEvent::Disconnected(a, b),
+            None => GraphEvent::Resync,
+        };
+        self.push(evt);
+    }
+    fn client_registration(&mut self, _: &JackClient, name: &str, is_registered: bool) {
+        let evt = if is_registered {
+            GraphEvent::ClientRegistered(name.to_owned())
+        } else {
+            GraphEvent::ClientUnregistered(name.to_owned())
+        };
+        self.push(evt);
+    }
+    fn port_registration(
+        &mut self,
+        client: &JackClient,
+        port_id: jack::PortId,
+        is_registered: bool,
+    ) {
+        let evt = match port_name(client, port_id) {
+            Some(name) if is_registered => GraphEvent::PortRegistered(name),
+            Some(name) => GraphEvent::PortUnregistered(name),
+            None => GraphEvent::Resync,
+        };
+        self.push(evt);
+    }
+    fn port_rename(
+        &mut self,
+        _: &JackClient,
+        _: jack::PortId,
+        old_name: &str,
+        new_name: &str,
+    ) -> jack::Control {
+        let old = PortFullname::try_from(old_name);
+        let new = PortFullname::try_from(new_name);
+        let evt = match (old, new) {
+            (Ok(old), Ok(new)) => GraphEvent::PortRenamed { old, new },
+            _ => GraphEvent::Resync,
+        };
+        self.push(evt)
+    }
+}
+
+impl jack::PropertyChangeHandler for Notifier {
+    fn property_changed(&mut self, change: &jack::PropertyChange) {
+        let subject = match change {
+            jack::PropertyChange::Created { subject, .. }
+            | jack::PropertyChange::Changed { subject, .. }
+            | jack::PropertyChange::Deleted { subject, .. } => *subject,
+        };
+        self.push(GraphEvent::PropertyChanged(subject));
+    }
+}
@@ -0,0 +1,160 @@
+use super::{GraphBackend, GraphError, PortGraph};
+#[cfg(test)]
+use crate::model::PortData;
+use crate::model::PortFullname;
+use std::fmt;
+
+/// A modification made to a `MemoryGraph` via its `GraphBackend` implementation.
+/// Each entry is stored as `(source, dest)`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum GraphChange {
+    Connect(PortFullname, PortFullname),
+    Disconnect(PortFullname, PortFullname),
+}
+
+impl fmt::Display for GraphChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphChange::Connect(src, dst) => write!(f, "connect    {} -> {}", src, dst),
+            GraphChange::Disconnect(src, dst) => write!(f, "disconnect {} -> {}", src, dst),
+        }
+    }
+}
+
+/// A port graph that lives entirely in memory, without any backing audio server.
+/// Used to run config enforcement, the UI, and the daemon against canned topologies.
+///
+/// Changes made through the inherent `add_port`, `add_connection`, and `remove_connection`
+/// methods simulate another program modifying the graph, and will therefore be
+/// reported by `needs_update()`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MemoryGraph {
+    graph: PortGraph,
+    dirty: bool,
+    /// Every successful `connect` and `disconnect` call, in order.
+    history: Vec<GraphChange>,
+}
+
+impl MemoryGraph {
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a new `MemoryGraph` starting with the given ports and connections.
+    pub fn from_graph(graph: PortGraph) -> Self {
+        Self {
+            graph,
+            dirty: false,
+            history: Vec::new(),
+        }
+    }
+
+    /// Removes and returns all changes made via `connect` and `disconnect` so far.
+    pub fn take_history(&mut self) -> Vec<GraphChange> {
+        std::mem::take(&mut self.history)
+    }
+
+    /// Registers a new port in the graph.
+    #[cfg(test)]
+    pub fn add_port(&mut self, data: PortData) {
+        self.graph.add_port(data);
+        self.dirty = true;
+    }
+
+    /// Connects two ports without any validation.
+    #[cfg(test)]
+    pub fn add_connection(&mut self, a: &PortFullname, b: &PortFullname) {
+        self.dirty |= self.graph.add_connection(a, b);
+    }
+
+    /// Disconnects two ports without any validation.
+    #[cfg(test)]
+    pub fn remove_connection(&mut self, a: &PortFullname, b: &PortFullname) {
+        self.dirty |= self.graph.remove_connection(a, b);
+    }
+}
+
+impl GraphBackend for MemoryGraph {
+    fn graph(&self) -> &PortGraph {
+        &self.graph
+    }
+
+    fn disconnect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError> {
+        if self.graph.remove_connection(source, dest) {
+            self.history
+                .push(GraphChange::Disconnect(source.clone(), dest.clone()));
+            Ok(())
+        } else {
+            Err(GraphError::NotConnected(source.clone(), dest.clone()))
+        }
+    }
+
+    fn connect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError> {
+        let source_data = self
+            .graph
+            .port_by_name(source)
+            .ok_or_else(|| GraphError::NoSuchPort(source.clone()))?;
+        let dest_data = self
+            .graph
+            .port_by_name(dest)
+            .ok_or_else(|| GraphError::NoSuchPort(dest.clone()))?;
+        let is_valid = source_data.direction.is_output()
+            && dest_data.direction.is_input()
+            && source_data.is_compatible(dest_data)
+            && !self.graph.is_connected(source, dest);
+        if !is_valid {
+            return Err(GraphError::InvalidConnection(source.clone(), dest.clone()));
+        }
+        self.graph.add_connection(source, dest);
+        self.history
+            .push(GraphChange::Connect(source.clone(), dest.clone()));
+        Ok(())
+    }
+
+    fn needs_update(&self) -> bool {
+        self.dirty
+    }
+
+    fn update(&mut self) -> Result<(), GraphError> {
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{PortCategory, PortDirection};
+    use crate::testing::typed_port;
+
+    #[test]
+    fn test_memory_connect() {
+        let mut graph = MemoryGraph::new();
+        let out = typed_port("synth:out", PortCategory::Audio, PortDirection::Out);
+        let inp = typed_port("system:playback_1", PortCategory::Audio, PortDirection::In);
+        let midi = typed_port("keys:midi_in", PortCategory::Midi, PortDirection::In);
+        graph.add_port(out.clone());
+        graph.add_port(inp.clone());
+        graph.add_port(midi.clone());
+        assert!(graph.needs_update());
+        graph.update().unwrap();
+        assert!(!graph.needs_update());
+
+        graph.connect(&out.name, &inp.name).unwrap();
+        assert!(graph.is_connected(&inp.name, &out.name));
+        assert!(graph.connect(&out.name, &inp.name).is_err());
+        assert!(graph.connect(&inp.name, &out.name).is_err());
+        assert!(graph.connect(&out.name, &midi.name).is_err());
+
+        graph.disconnect(&out.name, &inp.name).unwrap();
+        assert!(!graph.is_connected(&out.name, &inp.name));
+        assert!(graph.disconnect(&out.name, &inp.name).is_err());
+        assert!(!graph.needs_update());
+
+        graph.add_connection(&out.name, &inp.name);
+        assert!(graph.needs_update());
+        let clients: Vec<_> = graph.graph().all_clients().collect();
+        assert_eq!(clients, vec!["synth", "system", "keys"]);
+    }
+}
@@ -0,0 +1,32 @@
+//! Well-known keys from the JACK metadata API, along with helpers for reading them.
+//! See <https://jackaudio.org/metadata/> for what each key means.
+
+use crate::model::PortData;
+
+/// A human-readable name for a client or port.
+pub const PRETTY_NAME: &str = "http://jackaudio.org/metadata/pretty-name";
+
+/// The name of the group a port belongs to, such as the channels of a stereo pair.
+pub const PORT_GROUP: &str = "http://jackaudio.org/metadata/port-group";
+
+/// An integer giving the position of a port among its client's ports.
+pub const ORDER: &str = "http://jackaudio.org/metadata/order";
+
+/// The kind of signal carried by an audio port, either `AUDIO` or `CV`.
+pub const SIGNAL_TYPE: &str = "http://jackaudio.org/metadata/signal-type";
+
+/// Set by trej on the ports it registers to meter or listen to other ports, with the
+/// name of the port being watched as its value. Ports with this key are hidden from
+/// the graph.
+pub const METER: &str = "urn:trej:meter";
+
+/// Fills in the metadata fields of `port` from the well-known keys in its `properties`.
+pub fn apply_port_metadata(port: &mut PortData) {
+    port.pretty_name = port.properties.get(PRETTY_NAME).cloned();
+    port.port_group = port.properties.get(PORT_GROUP).cloned();
+    port.order = port
+        .properties
+        .get(ORDER)
+        .and_then(|raw| raw.trim().parse().ok());
+    port.signal_type = port.properties.get(SIGNAL_TYPE).cloned();
+}
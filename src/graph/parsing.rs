@@ -0,0 +1,100 @@
+use super::{GraphError, PortGraph};
+use crate::model::{PortCategory, PortData, PortDirection, PortFullname};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// A declarative description of a port graph, used to load canned topologies
+/// from disk.
+///
+/// Each top-level key is a client name, mapping the short names of that client's ports
+/// to their kind and any ports they are connected to:
+///
+/// ```toml
+/// [system]
+/// capture_1 = { direction = "out", category = "audio", connections = ["reverb:in"] }
+/// playback_1 = { direction = "in", category = "audio", aliases = ["alsa_pcm:playback_1"] }
+/// ```
+///
+/// A connection only needs to be listed from one of its two ends, but both ends
+/// must be declared as ports somewhere in the file.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GraphFile {
+    pub clients: BTreeMap<String, BTreeMap<String, PortEntry>>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PortEntry {
+    pub direction: PortDirection,
+    pub category: PortCategory,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub port_type: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub physical: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub terminal: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<PortFullname>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub connections: Vec<PortFullname>,
+}
+
+impl TryFrom<GraphFile> for PortGraph {
+    type Error = GraphError;
+    fn try_from(fl: GraphFile) -> Result<Self, GraphError> {
+        let mut retvl = PortGraph::new();
+        let mut connections = Vec::new();
+        for (client_name, ports) in fl.clients {
+            for (shortname, entry) in ports {
+                let name = PortFullname::new(format!("{}:{}", client_name, shortname))?;
+                for other in entry.connections {
+                    connections.push((name.clone(), other));
+                }
+                let mut data = PortData::new(name, entry.category, entry.direction);
+                data.port_type = entry.port_type;
+                data.is_physical = entry.physical;
+                data.is_terminal = entry.terminal;
+                data.aliases = entry.aliases;
+                retvl.add_port(data);
+            }
+        }
+        for (a, b) in connections {
+            if !retvl.add_connection(&a, &b) {
+                return Err(GraphError::UndeclaredPort(b, a));
+            }
+        }
+        Ok(retvl)
+    }
+}
+
+impl<'a> From<&'a PortGraph> for GraphFile {
+    fn from(graph: &'a PortGraph) -> Self {
+        let mut clients: BTreeMap<String, BTreeMap<String, PortEntry>> = BTreeMap::new();
+        for port in graph.all_ports() {
+            // Each connection is only written out from its output's side.
+            let connections = if port.direction.is_output() {
+                graph
+                    .port_connections(&port.name)
+                    .map(|other| other.name.clone())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let entry = PortEntry {
+                direction: port.direction,
+                category: port.category,
+                port_type: port.port_type.clone(),
+                physical: port.is_physical,
+                terminal: port.is_terminal,
+                aliases: port.aliases.clone(),
+                connections,
+            };
+            clients
+                .entry(port.name.client_name().to_owned())
+                .or_default()
+                .insert(port.name.port_shortname().to_owned(), entry);
+        }
+        GraphFile { clients }
+    }
+}
@@ -0,0 +1,514 @@
+use super::{GraphBackend, GraphError, PortGraph, UpdateListener};
+use crate::model::{PortCategory, PortData, PortDirection, PortFullname};
+
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, BufRead, BufReader};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const NODE_TYPE: &str = "PipeWire:Interface:Node";
+const PORT_TYPE: &str = "PipeWire:Interface:Port";
+const LINK_TYPE: &str = "PipeWire:Interface:Link";
+
+/// A wrapper around the graph of PipeWire nodes and ports, bypassing the JACK
+/// compatibility layer.
+/// Clients in the graph correspond to PipeWire nodes and are named by their
+/// `node.name` property; ports are named `<node.name>:<port.name>`. Nodes sharing a
+/// `node.name` are told apart the way JACK tells apart clients with the same name: the
+/// node with the lowest id keeps it, and the rest are numbered `<node.name>-01`,
+/// `<node.name>-02` and so on in order of id, so the same nodes get the same names
+/// however often they are recreated. Nodes without a `node.name` are called `node-<id>`.
+///
+/// Rather than using the PipeWire client API, this drives PipeWire's command line
+/// tools: the graph is read by parsing the JSON output of `pw-dump` and modified by
+/// running `pw-link`, so both need to be installed and on the `PATH`. Ports are passed to
+/// `pw-link` by their object id, so the generated names are only ever used for display and
+/// config matching.
+///
+/// Like `JackGraph`, this struct caches the graph, so `needs_update()` should be polled to
+/// know when to call `update()`.
+#[derive(Debug)]
+pub struct PipewireGraph {
+    /// The cached ports and connections.
+    graph: PortGraph,
+
+    /// The PipeWire object id of each port in `graph`.
+    port_ids: HashMap<PortFullname, u64>,
+
+    /// The ports left out of `graph` the last time it was read, each with the reason why.
+    skipped: BTreeSet<String>,
+
+    /// Warnings about newly skipped ports that have yet to be taken.
+    warnings: Vec<String>,
+
+    /// Set by the monitor thread whenever the graph changes.
+    update_flag: Arc<AtomicBool>,
+
+    /// The `pw-dump --monitor` process feeding the monitor thread.
+    monitor: Child,
+}
+
+impl PipewireGraph {
+    /// Connects to the local PipeWire daemon.
+    pub fn new() -> Result<Self, GraphError> {
+        Self::build(None)
+    }
+
+    /// Connects to the local PipeWire daemon, calling `listener` whenever the
+    /// underlying graph changes.
+    pub fn with_listener(listener: UpdateListener) -> Result<Self, GraphError> {
+        Self::build(Some(listener))
+    }
+
+    fn build(listener: Option<UpdateListener>) -> Result<Self, GraphError> {
+        let update_flag = Arc::new(AtomicBool::new(false));
+        let monitor = spawn_monitor(Arc::clone(&update_flag), listener)?;
+        let mut retvl = Self {
+            graph: PortGraph::new(),
+            port_ids: HashMap::new(),
+            skipped: BTreeSet::new(),
+            warnings: Vec::new(),
+            update_flag,
+            monitor,
+        };
+        retvl.update()?;
+        Ok(retvl)
+    }
+}
+
+impl Drop for PipewireGraph {
+    fn drop(&mut self) {
+        let _ = self.monitor.kill();
+        let _ = self.monitor.wait();
+    }
+}
+
+impl GraphBackend for PipewireGraph {
+    fn graph(&self) -> &PortGraph {
+        &self.graph
+    }
+
+    fn disconnect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError> {
+        let (source_id, dest_id) = (self.port_id(source)?, self.port_id(dest)?);
+        run_pw_link(&["-d", &source_id, &dest_id])?;
+        if !self.graph.remove_connection(source, dest) {
+            self.update()?;
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, source: &PortFullname, dest: &PortFullname) -> Result<(), GraphError> {
+        let (source_id, dest_id) = (self.port_id(source)?, self.port_id(dest)?);
+        run_pw_link(&[&source_id, &dest_id])?;
+        if !self.graph.add_connection(source, dest) {
+            self.update()?;
+        }
+        Ok(())
+    }
+
+    fn needs_update(&self) -> bool {
+        self.update_flag.load(Ordering::Relaxed)
+    }
+
+    fn update(&mut self) -> Result<(), GraphError> {
+        // Reset before dumping so that changes made mid-dump are picked up next time.
+        self.update_flag.store(false, Ordering::SeqCst);
+        let output = run_tool("pw-dump", &["--no-colors"])?;
+        let objects: Vec<Value> = serde_json::from_slice(&output.stdout)?;
+        let dump = parse_dump(&objects);
+        self.graph = dump.graph;
+        self.port_ids = dump.port_ids;
+        let fresh = note_skipped(&mut self.skipped, dump.skipped);
+        self.warnings.extend(fresh);
+        Ok(())
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+impl PipewireGraph {
+    /// Gets the object id `pw-link` should use to refer to `port`.
+    fn port_id(&self, port: &PortFullname) -> Result<String, GraphError> {
+        self.port_ids
+            .get(port)
+            .map(|id| id.to_string())
+            .ok_or_else(|| GraphError::NoSuchPort(port.clone()))
+    }
+}
+
+fn run_pw_link(args: &[&str]) -> Result<(), GraphError> {
+    run_tool("pw-link", args).map(|_| ())
+}
+
+/// Runs one of PipeWire's command line tools to completion, turning a failed exit
+/// into an `Err` carrying whatever the tool wrote to stderr.
+fn run_tool(tool: &'static str, args: &[&str]) -> Result<Output, GraphError> {
+    let output = Command::new(tool)
+        .args(args)
+        .output()
+        .map_err(|e| tool_error(tool, e))?;
+    if output.status.success() {
+        Ok(output)
+    } else {
+        let msg = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        Err(GraphError::Pipewire(msg))
+    }
+}
+
+fn tool_error(tool: &'static str, err: io::Error) -> GraphError {
+    if err.kind() == io::ErrorKind::NotFound {
+        GraphError::MissingTool(tool)
+    } else {
+        GraphError::Io(err)
+    }
+}
+
+/// Starts `pw-dump --monitor` along with a thread that sets `flag` and calls `listener`
+/// whenever it reports a change.
+fn spawn_monitor(
+    flag: Arc<AtomicBool>,
+    listener: Option<UpdateListener>,
+) -> Result<Child, GraphError> {
+    let mut child = Command::new("pw-dump")
+        .arg("--monitor")
+        .arg("--no-colors")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| tool_error("pw-dump", e))?;
+    let stdout = match child.stdout.take() {
+        Some(out) => out,
+        None => {
+            let _ = child.kill();
+            return Err(GraphError::Pipewire(
+                "Could not read from pw-dump.".to_owned(),
+            ));
+        }
+    };
+    thread::spawn(move || {
+        let mut listener = listener;
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => {
+                    break;
+                }
+            };
+            // Each batch of changes is printed as a single top-level JSON array.
+            if line != "]" {
+                continue;
+            }
+            flag.store(true, Ordering::Release);
+            let res = listener.as_mut().map(|listener| listener());
+            if res == Some(jack::Control::Quit) {
+                listener = None;
+            }
+        }
+    });
+    Ok(child)
+}
+
+/// The graph read from a `pw-dump` listing.
+struct Dump {
+    graph: PortGraph,
+    /// The PipeWire object id of each port in `graph`.
+    port_ids: HashMap<PortFullname, u64>,
+    /// The ports left out of `graph`, each with the reason why.
+    skipped: Vec<String>,
+}
+
+/// Builds a `PortGraph` out of the objects in a `pw-dump` listing, along with the
+/// object id of each port in it.
+/// Ports whose names are too long to fit in a `PortFullname`, or already taken by
+/// another port, are left out.
+fn parse_dump(objects: &[Value]) -> Dump {
+    let mut node_objs: BTreeMap<u64, BTreeMap<String, String>> = BTreeMap::new();
+    for obj in objects.iter().filter(|obj| obj["type"] == NODE_TYPE) {
+        if let Some(id) = obj["id"].as_u64() {
+            node_objs.insert(id, property_map(&obj["info"]["props"]));
+        }
+    }
+    // Visit the nodes by id so that the oldest node keeps a shared name, and the rest
+    // are numbered in the order they were created.
+    let mut nodes: HashMap<u64, (String, BTreeMap<String, String>)> = HashMap::new();
+    let mut node_names = HashSet::new();
+    let mut duplicates = Vec::new();
+    for (id, props) in node_objs {
+        let name = props
+            .get("node.name")
+            .cloned()
+            .unwrap_or_else(|| format!("node-{}", id));
+        if node_names.insert(name.clone()) {
+            nodes.insert(id, (name, props));
+        } else {
+            duplicates.push((id, name, props));
+        }
+    }
+    // Numbered only once every node has claimed its own name, so that a node actually
+    // called `<name>-01` keeps that name.
+    let mut counters = HashMap::new();
+    for (id, name, props) in duplicates {
+        let counter = counters.entry(name.clone()).or_insert(0);
+        let numbered = loop {
+            *counter += 1;
+            let numbered = format!("{}-{:02}", name, counter);
+            if node_names.insert(numbered.clone()) {
+                break numbered;
+            }
+        };
+        nodes.insert(id, (numbered, props));
+    }
+
+    let mut skipped = Vec::new();
+    // Keyed by `(node id, port id)` so that each node's ports stay together.
+    let mut ports: BTreeMap<(u64, u64), PortData> = BTreeMap::new();
+    for obj in objects.iter().filter(|obj| obj["type"] == PORT_TYPE) {
+        let info = &obj["info"];
+        let props = property_map(&info["props"]);
+        let id = obj["id"].as_u64();
+        let node_id = info["props"]["node.id"].as_u64();
+        let (id, node_id) = match id.zip(node_id) {
+            Some(ids) => ids,
+            None => {
+                continue;
+            }
+        };
+        let node_name = match nodes.get(&node_id) {
+            Some((name, _)) => name,
+            None => {
+                continue;
+            }
+        };
+        let port_name = props
+            .get("port.name")
+            .cloned()
+            .unwrap_or_else(|| format!("port-{}", id));
+        let direction = if info["direction"] == "input" {
+            PortDirection::In
+        } else {
+            PortDirection::Out
+        };
+        let port_type = props.get("format.dsp").cloned().unwrap_or_default();
+        let category = PortCategory::from_port_type(&port_type, None);
+        let name = match PortFullname::new(format!("{}:{}", node_name, port_name)) {
+            Ok(name) => name,
+            Err(e) => {
+                skipped.push(format!("{}:{}: {}", node_name, port_name, e));
+                continue;
+            }
+        };
+        let mut data = PortData::new(name, category, direction);
+        data.port_type = port_type;
+        data.is_physical = props.get("port.physical").map(|v| v == "true") == Some(true);
+        data.is_terminal = props.get("port.terminal").map(|v| v == "true") == Some(true);
+        data.aliases = props
+            .get("port.alias")
+            .and_then(|alias| PortFullname::new(alias.clone()).ok())
+            .into_iter()
+            .collect();
+        data.properties = props;
+        ports.insert((node_id, id), data);
+    }
+
+    let mut retvl = PortGraph::new();
+    let mut names_by_id = HashMap::new();
+    let mut port_ids = HashMap::new();
+    for ((_, port_id), data) in ports {
+        if port_ids.contains_key(&data.name) {
+            skipped.push(format!("{}: the name is already in use", data.name));
+            continue;
+        }
+        names_by_id.insert(port_id, data.name.clone());
+        port_ids.insert(data.name.clone(), port_id);
+        retvl.add_port(data);
+    }
+    for (name, props) in nodes.into_values() {
+        retvl.set_client_properties(name, props);
+    }
+    for obj in objects.iter().filter(|obj| obj["type"] == LINK_TYPE) {
+        let info = &obj["info"];
+        let output = info["output-port-id"]
+            .as_u64()
+            .and_then(|id| names_by_id.get(&id));
+        let input = info["input-port-id"]
+            .as_u64()
+            .and_then(|id| names_by_id.get(&id));
+        if let Some((output, input)) = output.zip(input) {
+            retvl.add_connection(output, input);
+        }
+    }
+    Dump {
+        graph: retvl,
+        port_ids,
+        skipped,
+    }
+}
+
+/// Replaces the ports recorded in `skipped` with those in `now_skipped`, and gets a
+/// warning for each one that wasn't already recorded.
+/// This way, ports are only warned about once rather than every time the graph changes.
+fn note_skipped(skipped: &mut BTreeSet<String>, now_skipped: Vec<String>) -> Vec<String> {
+    let now_skipped: BTreeSet<String> = now_skipped.into_iter().collect();
+    let retvl = now_skipped
+        .difference(skipped)
+        .map(|port| format!("Skipped PipeWire port {}", port))
+        .collect();
+    *skipped = now_skipped;
+    retvl
+}
+
+/// Flattens a PipeWire property dictionary into strings.
+fn property_map(props: &Value) -> BTreeMap<String, String> {
+    props
+        .as_object()
+        .into_iter()
+        .flat_map(|map| map.iter())
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dump() {
+        let raw = r#"[
+            { "id": 40, "type": "PipeWire:Interface:Node",
+              "info": { "props": { "node.name": "alsa_output.usb", "media.class": "Audio/Sink" } } },
+            { "id": 41, "type": "PipeWire:Interface:Port",
+              "info": { "direction": "input",
+                        "props": { "node.id": 40, "port.name": "playback_FL",
+                                   "port.alias": "USB Audio:playback_FL",
+                                   "format.dsp": "32 bit float mono audio" } } },
+            { "id": 50, "type": "PipeWire:Interface:Node",
+              "info": { "props": { "node.name": "synth" } } },
+            { "id": 51, "type": "PipeWire:Interface:Port",
+              "info": { "direction": "output",
+                        "props": { "node.id": 50, "port.name": "out_L",
+                                   "format.dsp": "32 bit float mono audio" } } },
+            { "id": 52, "type": "PipeWire:Interface:Port",
+              "info": { "direction": "output",
+                        "props": { "node.id": 50, "port.name": "midi_out",
+                                   "format.dsp": "8 bit raw midi" } } },
+            { "id": 60, "type": "PipeWire:Interface:Link",
+              "info": { "output-port-id": 51, "input-port-id": 41 } }
+        ]"#;
+        let objects: Vec<Value> = serde_json::from_str(raw).unwrap();
+        let Dump {
+            graph,
+            port_ids,
+            skipped,
+        } = parse_dump(&objects);
+
+        let clients: Vec<_> = graph.all_clients().collect();
+        assert_eq!(clients, vec!["alsa_output.usb", "synth"]);
+        assert!(skipped.is_empty());
+        let sink = PortFullname::new("alsa_output.usb:playback_FL".to_owned()).unwrap();
+        let out = PortFullname::new("synth:out_L".to_owned()).unwrap();
+        let midi = PortFullname::new("synth:midi_out".to_owned()).unwrap();
+        assert!(graph.is_connected(&out, &sink));
+        assert_eq!(
+            graph.port_by_name(&sink).unwrap().direction,
+            PortDirection::In
+        );
+        let alias = PortFullname::new("USB Audio:playback_FL".to_owned()).unwrap();
+        assert_eq!(graph.find_port(&alias).unwrap().name, sink);
+        assert_eq!(
+            graph.port_by_name(&midi).unwrap().category,
+            PortCategory::Midi
+        );
+        let media_class = graph
+            .client_properties("alsa_output.usb")
+            .find(|(key, _)| *key == "media.class")
+            .map(|(_, value)| value.as_str());
+        assert_eq!(media_class, Some("Audio/Sink"));
+        assert_eq!(port_ids.get(&out), Some(&51));
+    }
+
+    #[test]
+    fn test_parse_dump_names() {
+        let long_name = "x".repeat(*jack::CLIENT_NAME_SIZE + 8);
+        let raw = format!(
+            r#"[
+            {{ "id": 30, "type": "PipeWire:Interface:Node",
+              "info": {{ "props": {{ "node.name": "{}" }} }} }},
+            {{ "id": 31, "type": "PipeWire:Interface:Port",
+              "info": {{ "direction": "output", "props": {{ "node.id": 30, "port.name": "out" }} }} }},
+            {{ "id": 40, "type": "PipeWire:Interface:Node",
+              "info": {{ "props": {{ "node.name": "bluez_output" }} }} }},
+            {{ "id": 41, "type": "PipeWire:Interface:Port",
+              "info": {{ "direction": "input", "props": {{ "node.id": 40, "port.name": "in" }} }} }},
+            {{ "id": 50, "type": "PipeWire:Interface:Node",
+              "info": {{ "props": {{ "node.name": "bluez_output" }} }} }},
+            {{ "id": 51, "type": "PipeWire:Interface:Port",
+              "info": {{ "direction": "input", "props": {{ "node.id": 50, "port.name": "in" }} }} }},
+            {{ "id": 52, "type": "PipeWire:Interface:Node",
+              "info": {{ "props": {{ "node.name": "bluez_output" }} }} }},
+            {{ "id": 53, "type": "PipeWire:Interface:Port",
+              "info": {{ "direction": "input", "props": {{ "node.id": 52, "port.name": "in" }} }} }},
+            {{ "id": 54, "type": "PipeWire:Interface:Node",
+              "info": {{ "props": {{ "node.name": "bluez_output-01" }} }} }},
+            {{ "id": 60, "type": "PipeWire:Interface:Node",
+              "info": {{ "props": {{ "node.description": "Unnamed" }} }} }},
+            {{ "id": 61, "type": "PipeWire:Interface:Port",
+              "info": {{ "direction": "output", "props": {{ "node.id": 60, "port.name": "out" }} }} }}
+        ]"#,
+            long_name
+        );
+        let objects: Vec<Value> = serde_json::from_str(&raw).unwrap();
+        let Dump {
+            graph,
+            port_ids,
+            skipped,
+        } = parse_dump(&objects);
+
+        let clients: Vec<_> = graph.all_clients().collect();
+        assert_eq!(
+            clients,
+            vec![
+                "bluez_output",
+                "bluez_output-02",
+                "bluez_output-03",
+                "node-60"
+            ]
+        );
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].starts_with(&format!("{}:out: ", long_name)));
+        let first = PortFullname::new("bluez_output:in".to_owned()).unwrap();
+        let second = PortFullname::new("bluez_output-02:in".to_owned()).unwrap();
+        let third = PortFullname::new("bluez_output-03:in".to_owned()).unwrap();
+        let unnamed = PortFullname::new("node-60:out".to_owned()).unwrap();
+        assert_eq!(port_ids.get(&first), Some(&41));
+        assert_eq!(port_ids.get(&second), Some(&51));
+        assert_eq!(port_ids.get(&third), Some(&53));
+        assert_eq!(port_ids.get(&unnamed), Some(&61));
+        assert_eq!(port_ids.len(), 4);
+    }
+
+    #[test]
+    fn test_skipped_ports_warn_once() {
+        let mut skipped = BTreeSet::new();
+        let first = vec!["a:out: too long".to_owned()];
+        assert_eq!(
+            note_skipped(&mut skipped, first.clone()),
+            vec!["Skipped PipeWire port a:out: too long"]
+        );
+        assert!(note_skipped(&mut skipped, first.clone()).is_empty());
+        assert!(note_skipped(&mut skipped, Vec::new()).is_empty());
+        // A port that comes back after going away is warned about again.
+        assert_eq!(note_skipped(&mut skipped, first).len(), 1);
+    }
+}
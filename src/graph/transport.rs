@@ -0,0 +1,108 @@
+/// Whether the transport is moving.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TransportState {
+    Stopped,
+    /// The transport has been asked to start, and is waiting for clients to get ready.
+    Starting,
+    Rolling,
+}
+
+/// A musical position, in bars, beats and ticks, along with the tempo and time
+/// signature used to work it out.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Bbt {
+    /// The current bar, starting at 1.
+    pub bar: u32,
+    /// The current beat within the bar, starting at 1.
+    pub beat: u32,
+    /// The current tick within the beat, starting at 0.
+    pub tick: u32,
+    /// The number of ticks between the start of the song and the start of the bar.
+    pub bar_start_tick: f64,
+    pub beats_per_bar: f32,
+    pub beat_type: f32,
+    pub ticks_per_beat: f64,
+    pub bpm: f64,
+}
+
+impl Bbt {
+    /// The number of ticks per beat used when trej is the timebase master.
+    pub const DEFAULT_TICKS_PER_BEAT: f64 = 1920.0;
+
+    /// Works out the position `frame` frames into the song, assuming the tempo and time
+    /// signature never change.
+    pub fn at_frame(
+        frame: u32,
+        sample_rate: u32,
+        bpm: f64,
+        beats_per_bar: f32,
+        beat_type: f32,
+    ) -> Self {
+        let ticks_per_beat = Self::DEFAULT_TICKS_PER_BEAT;
+        // Multiply before dividing, so whole beats come out exact.
+        let abs_tick = if sample_rate == 0 {
+            0.0
+        } else {
+            f64::from(frame) * bpm * ticks_per_beat / (f64::from(sample_rate) * 60.0)
+        };
+        let abs_beat = (abs_tick / ticks_per_beat).floor();
+        let beats_per_bar_f = f64::from(beats_per_bar).max(1.0);
+        let bar = (abs_beat / beats_per_bar_f).floor();
+        let beat = abs_beat - bar * beats_per_bar_f;
+        let tick = abs_tick - abs_beat * ticks_per_beat;
+        Self {
+            bar: bar as u32 + 1,
+            beat: beat as u32 + 1,
+            tick: tick as u32,
+            bar_start_tick: bar * beats_per_bar_f * ticks_per_beat,
+            beats_per_bar,
+            beat_type,
+            ticks_per_beat,
+            bpm,
+        }
+    }
+}
+
+/// A snapshot of the state of the server's transport.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TransportInfo {
+    pub state: TransportState,
+    /// The current position, in frames since the start of the song.
+    pub frame: u32,
+    /// Frames per second, used to convert `frame` into a time.
+    pub frame_rate: u32,
+    /// The musical position, if the timebase master provides one.
+    pub bbt: Option<Bbt>,
+}
+
+impl TransportInfo {
+    /// Gets the current position in seconds since the start of the song.
+    pub fn seconds(&self) -> f64 {
+        if self.frame_rate == 0 {
+            return 0.0;
+        }
+        f64::from(self.frame) / f64::from(self.frame_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bbt_at_frame() {
+        let start = Bbt::at_frame(0, 48000, 120.0, 4.0, 4.0);
+        assert_eq!((start.bar, start.beat, start.tick), (1, 1, 0));
+
+        // At 120 BPM, each beat is half a second long.
+        let beat_two = Bbt::at_frame(24000, 48000, 120.0, 4.0, 4.0);
+        assert_eq!((beat_two.bar, beat_two.beat, beat_two.tick), (1, 2, 0));
+
+        let bar_two = Bbt::at_frame(96000 + 12000, 48000, 120.0, 4.0, 4.0);
+        assert_eq!((bar_two.bar, bar_two.beat, bar_two.tick), (2, 1, 960));
+        assert_eq!(bar_two.bar_start_tick, 4.0 * Bbt::DEFAULT_TICKS_PER_BEAT);
+
+        let waltz = Bbt::at_frame(48000 * 3, 48000, 60.0, 3.0, 4.0);
+        assert_eq!((waltz.bar, waltz.beat, waltz.tick), (2, 1, 0));
+    }
+}
@@ -10,6 +10,9 @@ mod ui;
 mod daemon;
 
 mod state;
+#[cfg(test)]
+mod testing;
+use graph::GraphBackend;
 use state::TrejState;
 
 #[derive(Debug, Error)]
@@ -29,6 +32,12 @@ pub enum Error {
     #[error(transparent)]
     ConfigParser(#[from] toml::de::Error),
 
+    #[error(transparent)]
+    ConfigSerializer(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    ConfigLoader(#[from] crate::config::ConfigLoadError),
+
     #[error(transparent)]
     NameParser(#[from] crate::model::NameError),
 
@@ -40,57 +49,119 @@ pub enum Error {
 }
 
 const SHOULD_BE_DAEMON: bool = true;
-use daemon::{DaemonArgs, TrejDaemon};
+use daemon::{CheckArgs, DaemonArgs, SimulateArgs, SnapshotArgs, TrejDaemon};
 
 fn main() {
+    match run() {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs the subcommand named on the command line, returning the code to exit with.
+fn run() -> Result<i32, Error> {
+    if std::env::args().nth(1).as_deref() == Some("simulate") {
+        let args = SimulateArgs::from_args(std::env::args().skip(1))?;
+        daemon::run_simulation(&args)?;
+        return Ok(0);
+    }
+    if std::env::args().nth(1).as_deref() == Some("check") {
+        let args = CheckArgs::from_args(std::env::args().skip(1))?;
+        let problems = daemon::run_check(&args)?;
+        return Ok(if problems == 0 { 0 } else { 1 });
+    }
+    if std::env::args().nth(1).as_deref() == Some("snapshot") {
+        let args = SnapshotArgs::from_args(std::env::args().skip(1))?;
+        daemon::run_snapshot(&args)?;
+        return Ok(0);
+    }
     if SHOULD_BE_DAEMON {
-        let args = DaemonArgs::from_args(std::env::args()).unwrap();
-        let (daemon, _) = TrejDaemon::new(args).unwrap();
-        return daemon.run().unwrap();
+        let args = DaemonArgs::from_args(std::env::args())?;
+        if args.use_pipewire() {
+            let (daemon, _) = TrejDaemon::new_pipewire(args)?;
+            daemon.run()?;
+            return Ok(0);
+        }
+        let (daemon, _) = TrejDaemon::new(args)?;
+        daemon.run()?;
+        return Ok(0);
     }
-    let config_path = std::env::args().skip(1).last();
-    let mut state = match config_path {
-        Some(config) => TrejState::load_file(config).unwrap(),
-        None => TrejState::load_no_config().unwrap(),
+    let use_pipewire = std::env::args().skip(1).any(|arg| arg == "-p");
+    let config_path = std::env::args().skip(1).rfind(|arg| arg != "-p");
+    if use_pipewire {
+        let graph = graph::PipewireGraph::new()?;
+        let state = match config_path {
+            Some(config) => TrejState::load_file_with_graph(config, graph)?,
+            None => TrejState::with_graph(config::LockConfig::new(), graph),
+        };
+        run_ui(state)?;
+        return Ok(0);
+    }
+    let state = match config_path {
+        Some(config) => TrejState::load_file(config)?,
+        None => TrejState::load_no_config()?,
     };
+    run_ui(state)?;
+    Ok(0)
+}
+
+fn run_ui<G: GraphBackend>(mut state: TrejState<G>) -> Result<(), Error> {
     //let mut ui = ui::GraphView::new(state);
     let mut ui_state = ui::GraphViewState::new();
-    let output = ui::ScreenWrapper::new().unwrap();
-    let mut output = tui::Terminal::new(tui::backend::CrosstermBackend::new(output)).unwrap();
-    output
-        .draw(|f| {
-            let w = ui::GraphViewWidget::new(&state.graph(), &state.config());
-            f.render_stateful_widget(w, f.size(), &mut ui_state);
-        })
-        .unwrap();
+    ui_state.show_warnings(&mut state.graph);
+    let output = ui::ScreenWrapper::new()?;
+    let mut output = tui::Terminal::new(tui::backend::CrosstermBackend::new(output))?;
+    output.draw(|f| {
+        let w = ui::GraphViewWidget::new(state.graph().graph(), state.config())
+            .with_server_info(state.graph().server_info())
+            .with_meter_levels(state.graph().meter_levels())
+            .with_transport(state.graph().transport());
+        f.render_stateful_widget(w, f.size(), &mut ui_state);
+    })?;
     loop {
         let has_graph_update = state.graph().needs_update();
         if has_graph_update {
-            state.reload().unwrap();
-            state.apply_config().unwrap();
+            state.reload()?;
+            state.apply_config()?;
+            ui_state.show_warnings(&mut state.graph);
+        }
+        let rolling = match state.graph().transport() {
+            Some(info) => info.state != graph::TransportState::Stopped,
+            None => false,
+        };
+        let timeout = if ui_state.is_live() || rolling {
+            Duration::from_millis(50)
+        } else {
+            Duration::from_millis(1000)
+        };
+        let ui_event_opt =
+            ui_state.handle_pending_event(&mut state.graph, &mut state.config, Some(timeout))?;
+        if ui_event_opt == Some(ui::UiAction::ApplyConfig) {
+            state.apply_config()?;
         }
-        let ui_event_opt = ui_state
-            .handle_pending_event(
-                &mut state.graph,
-                &mut state.config,
-                Some(Duration::from_millis(1000)),
-            )
-            .unwrap();
 
         match ui_event_opt {
             Some(ui::UiAction::Close) => {
-                return;
+                return Ok(());
             }
-            None if !has_graph_update => {
-                // No updates in state or UI, so no redrawing
+            None if !has_graph_update
+                && state.graph().server_info().is_none()
+                && !ui_state.is_live() =>
+            {
+                // No updates in state or UI, and no live server stats, meters or MIDI to
+                // refresh, so no redrawing
             }
             _ => {
-                output
-                    .draw(|f| {
-                        let w = ui::GraphViewWidget::new(&state.graph(), &state.config());
-                        f.render_stateful_widget(w, f.size(), &mut ui_state);
-                    })
-                    .unwrap();
+                output.draw(|f| {
+                    let w = ui::GraphViewWidget::new(state.graph().graph(), state.config())
+                        .with_server_info(state.graph().server_info())
+                        .with_meter_levels(state.graph().meter_levels())
+                        .with_transport(state.graph().transport());
+                    f.render_stateful_widget(w, f.size(), &mut ui_state);
+                })?;
             }
         }
     }
@@ -0,0 +1,248 @@
+use std::fmt;
+
+/// A decoded MIDI message.
+/// Channels are numbered from 1 to 16, the way they are usually displayed.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// A pitch bend, centered on 0 and ranging from -8192 to 8191.
+    PitchBend {
+        channel: u8,
+        value: i16,
+    },
+    /// A system exclusive message `len` bytes long, including its start and end bytes.
+    SysEx {
+        len: usize,
+    },
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+    /// Any other message, identified by its status byte.
+    Other {
+        status: u8,
+        len: usize,
+    },
+}
+
+impl MidiMessage {
+    /// Decodes a message from its first few bytes and its total length.
+    /// `prefix` only needs to hold the first 3 bytes of the message, since nothing
+    /// past that is decoded.
+    pub fn parse(prefix: &[u8], len: usize) -> Self {
+        let status = match prefix.first() {
+            Some(&status) => status,
+            None => {
+                return MidiMessage::Other { status: 0, len };
+            }
+        };
+        let data = |idx: usize| prefix.get(idx).copied().unwrap_or(0) & 0x7f;
+        let channel = (status & 0x0f) + 1;
+        match status & 0xf0 {
+            // By convention, a note on with no velocity is a note off.
+            0x90 if data(2) == 0 => MidiMessage::NoteOff {
+                channel,
+                note: data(1),
+                velocity: 0,
+            },
+            0x90 => MidiMessage::NoteOn {
+                channel,
+                note: data(1),
+                velocity: data(2),
+            },
+            0x80 => MidiMessage::NoteOff {
+                channel,
+                note: data(1),
+                velocity: data(2),
+            },
+            0xa0 => MidiMessage::PolyPressure {
+                channel,
+                note: data(1),
+                pressure: data(2),
+            },
+            0xb0 => MidiMessage::ControlChange {
+                channel,
+                controller: data(1),
+                value: data(2),
+            },
+            0xc0 => MidiMessage::ProgramChange {
+                channel,
+                program: data(1),
+            },
+            0xd0 => MidiMessage::ChannelPressure {
+                channel,
+                pressure: data(1),
+            },
+            0xe0 => {
+                let raw = i16::from(data(1)) | (i16::from(data(2)) << 7);
+                MidiMessage::PitchBend {
+                    channel,
+                    value: raw - 8192,
+                }
+            }
+            _ => match status {
+                0xf0 => MidiMessage::SysEx { len },
+                0xf8 => MidiMessage::Clock,
+                0xfa => MidiMessage::Start,
+                0xfb => MidiMessage::Continue,
+                0xfc => MidiMessage::Stop,
+                0xfe => MidiMessage::ActiveSensing,
+                0xff => MidiMessage::Reset,
+                _ => MidiMessage::Other { status, len },
+            },
+        }
+    }
+
+    /// Gets the channel the message was sent on, if it is a channel message.
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::PolyPressure { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+/// Describes the message without its channel; see `MidiMessage::channel`.
+impl fmt::Display for MidiMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MidiMessage::NoteOn { note, velocity, .. } => {
+                write!(f, "Note On   {:<4} vel {}", note_name(note), velocity)
+            }
+            MidiMessage::NoteOff { note, velocity, .. } => {
+                write!(f, "Note Off  {:<4} vel {}", note_name(note), velocity)
+            }
+            MidiMessage::PolyPressure { note, pressure, .. } => {
+                write!(f, "Aftertouch {:<4} {}", note_name(note), pressure)
+            }
+            MidiMessage::ControlChange {
+                controller, value, ..
+            } => write!(f, "CC {:<3} = {}", controller, value),
+            MidiMessage::ProgramChange { program, .. } => write!(f, "Program {}", program),
+            MidiMessage::ChannelPressure { pressure, .. } => {
+                write!(f, "Channel Pressure {}", pressure)
+            }
+            MidiMessage::PitchBend { value, .. } => write!(f, "Pitch Bend {:+}", value),
+            MidiMessage::SysEx { len } => write!(f, "SysEx ({} bytes)", len),
+            MidiMessage::Clock => f.write_str("Clock"),
+            MidiMessage::Start => f.write_str("Start"),
+            MidiMessage::Continue => f.write_str("Continue"),
+            MidiMessage::Stop => f.write_str("Stop"),
+            MidiMessage::ActiveSensing => f.write_str("Active Sensing"),
+            MidiMessage::Reset => f.write_str("Reset"),
+            MidiMessage::Other { status, len } => {
+                write!(f, "Status {:#04x} ({} bytes)", status, len)
+            }
+        }
+    }
+}
+
+/// Gets the name of a MIDI note number, where note 60 is middle C (`C4`).
+fn note_name(note: u8) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    let octave = i32::from(note / 12) - 1;
+    format!("{}{}", NAMES[usize::from(note % 12)], octave)
+}
+
+/// A MIDI message received from a port, along with when it was received.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MidiEvent {
+    /// The time the message was sent, in microseconds on the server's clock.
+    pub time_us: u64,
+    pub message: MidiMessage,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_midi() {
+        assert_eq!(
+            MidiMessage::parse(&[0x92, 60, 100], 3),
+            MidiMessage::NoteOn {
+                channel: 3,
+                note: 60,
+                velocity: 100
+            }
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x90, 60, 0], 3),
+            MidiMessage::NoteOff {
+                channel: 1,
+                note: 60,
+                velocity: 0
+            }
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xbf, 7, 127], 3),
+            MidiMessage::ControlChange {
+                channel: 16,
+                controller: 7,
+                value: 127
+            }
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xe0, 0x00, 0x40], 3),
+            MidiMessage::PitchBend {
+                channel: 1,
+                value: 0
+            }
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xe0, 0x00, 0x00], 3),
+            MidiMessage::PitchBend {
+                channel: 1,
+                value: -8192
+            }
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xf0, 0x7e, 0x7f], 22),
+            MidiMessage::SysEx { len: 22 }
+        );
+        assert_eq!(MidiMessage::parse(&[0xf8], 1), MidiMessage::Clock);
+        assert_eq!(MidiMessage::parse(&[0xc4, 12], 2).channel(), Some(5));
+        assert_eq!(MidiMessage::Clock.channel(), None);
+        assert_eq!(
+            MidiMessage::parse(&[0x90, 61, 1], 3).to_string(),
+            "Note On   C#4  vel 1"
+        );
+    }
+}
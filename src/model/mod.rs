@@ -3,3 +3,6 @@ pub use ports::*;
 
 mod pathing;
 pub use pathing::*;
+
+mod midi;
+pub use midi::*;
@@ -1,5 +1,6 @@
 use serde::de::Error as SerdeError;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fmt;
 
@@ -94,7 +95,8 @@ impl AsRef<str> for PortFullname {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PortDirection {
     In,
     Out,
@@ -119,18 +121,114 @@ impl PortDirection {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PortCategory {
     Midi,
     Audio,
+    /// Audio-rate control voltage; uses the audio port type, but is flagged as CV via
+    /// metadata.
+    Cv,
+    /// Open Sound Control messages.
+    Osc,
     Unknown,
 }
 
+impl PortCategory {
+    /// Works out the category of a port from its exact type string and its
+    /// `signal-type` metadata, if any.
+    pub fn from_port_type(port_type: &str, signal_type: Option<&str>) -> Self {
+        let kindstr = port_type.to_lowercase();
+        if kindstr.contains("midi") {
+            PortCategory::Midi
+        } else if kindstr.contains("osc") {
+            PortCategory::Osc
+        } else if kindstr.contains("audio") {
+            match signal_type {
+                Some(sig) if sig.eq_ignore_ascii_case("cv") => PortCategory::Cv,
+                _ => PortCategory::Audio,
+            }
+        } else {
+            PortCategory::Unknown
+        }
+    }
+}
+
+/// The range of delays, in frames, a signal can experience on its way to or from a port.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct LatencyRange {
+    pub min: u32,
+    pub max: u32,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct PortData {
     pub name: PortFullname,
     pub category: PortCategory,
     pub direction: PortDirection,
+    /// The exact type string the backend reports for the port, or empty if unknown.
+    pub port_type: String,
+    /// Whether the port corresponds to a physical connector, such as a sound card jack.
+    pub is_physical: bool,
+    /// Whether data entering or leaving the port does not flow through to any other port.
+    pub is_terminal: bool,
+    /// Whether the port supports input monitoring.
+    pub can_monitor: bool,
+    /// Whether input monitoring has been requested for the port.
+    pub is_monitoring: bool,
+    /// How long it takes data arriving at the port to have been captured, if known.
+    pub capture_latency: Option<LatencyRange>,
+    /// How long it takes data leaving the port to be played back, if known.
+    pub playback_latency: Option<LatencyRange>,
+    /// Alternate names for the port, such as the hardware names JACK gives `system` ports.
+    pub aliases: Vec<PortFullname>,
+    /// A human-readable name for the port, if it has one.
+    pub pretty_name: Option<String>,
+    /// The name of the group of related ports this port belongs to, if any.
+    pub port_group: Option<String>,
+    /// Where this port should be placed among its client's ports, if it cares.
+    pub order: Option<i64>,
+    /// The kind of signal the port carries beyond its data type, such as `CV`.
+    pub signal_type: Option<String>,
+    /// Extra backend-specific key/value information about the port.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl PortData {
+    pub fn new(name: PortFullname, category: PortCategory, direction: PortDirection) -> Self {
+        Self {
+            name,
+            category,
+            direction,
+            port_type: String::new(),
+            is_physical: false,
+            is_terminal: false,
+            can_monitor: false,
+            is_monitoring: false,
+            capture_latency: None,
+            playback_latency: None,
+            aliases: Vec::new(),
+            pretty_name: None,
+            port_group: None,
+            order: None,
+            signal_type: None,
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// Checks whether `self` and `other` carry the same kind of data, and could therefore
+    /// be connected if their directions allow it.
+    pub fn is_compatible(&self, other: &PortData) -> bool {
+        let types_match = self.port_type.is_empty()
+            || other.port_type.is_empty()
+            || self.port_type == other.port_type;
+        self.category == other.category && types_match
+    }
+
+    /// Gets an iterator over the port's name followed by all of its aliases.
+    pub fn all_names(&self) -> impl Iterator<Item = &PortFullname> {
+        std::iter::once(&self.name).chain(self.aliases.iter())
+    }
 }
 
 #[cfg(test)]
@@ -151,4 +249,29 @@ mod tests {
         let parsed: HashMap<&str, Vec<PortFullname>> = toml::de::from_str(&ser_mapped).unwrap();
         assert_eq!(map1, parsed);
     }
+
+    #[test]
+    fn test_category_from_port_type() {
+        let audio = "32 bit float mono audio";
+        assert_eq!(
+            PortCategory::from_port_type(audio, None),
+            PortCategory::Audio
+        );
+        assert_eq!(
+            PortCategory::from_port_type(audio, Some("CV")),
+            PortCategory::Cv
+        );
+        assert_eq!(
+            PortCategory::from_port_type("8 bit raw midi", None),
+            PortCategory::Midi
+        );
+        assert_eq!(
+            PortCategory::from_port_type("8 bit raw OSC", None),
+            PortCategory::Osc
+        );
+        assert_eq!(
+            PortCategory::from_port_type("something else", None),
+            PortCategory::Unknown
+        );
+    }
 }
@@ -1,14 +1,12 @@
-use crate::config::LockConfig;
-use crate::graph::JackGraph;
+use crate::config::{self, LockConfig};
+use crate::graph::{GraphBackend, JackGraph};
 
-use std::fs::OpenOptions;
-use std::io::Read;
 use std::path::{Path, PathBuf};
 
-pub(crate) struct TrejState {
+pub(crate) struct TrejState<G: GraphBackend = JackGraph> {
     pub config: LockConfig,
     pub config_path: Option<PathBuf>,
-    pub graph: JackGraph,
+    pub graph: G,
 }
 
 impl TrejState {
@@ -29,22 +27,33 @@ impl TrejState {
         })
     }
     pub fn load_file<T: AsRef<Path>>(path: T) -> Result<Self, crate::Error> {
-        let config_path = Some(path.as_ref().to_owned());
-        let mut conf_fh = OpenOptions::new().read(true).open(&path)?;
-        let mut raw_conf = String::new();
-        conf_fh.read_to_string(&mut raw_conf)?;
-        let config = toml::from_str(&raw_conf)?;
         let graph = Self::init_graph()?;
+        Self::load_file_with_graph(path, graph)
+    }
+}
+
+impl<G: GraphBackend> TrejState<G> {
+    pub fn load_file_with_graph<T: AsRef<Path>>(path: T, graph: G) -> Result<Self, crate::Error> {
+        let config_path = Some(path.as_ref().to_owned());
+        let config = config::load_config(path.as_ref(), &config::default_conf_dirs())?.config;
         Ok(Self {
             config,
             config_path,
             graph,
         })
     }
+    /// Constructs a new `TrejState` around an already-initialized graph backend.
+    pub fn with_graph(config: LockConfig, graph: G) -> Self {
+        Self {
+            config,
+            config_path: None,
+            graph,
+        }
+    }
     pub fn config(&self) -> &LockConfig {
         &self.config
     }
-    pub fn graph(&self) -> &JackGraph {
+    pub fn graph(&self) -> &G {
         &self.graph
     }
     pub fn reload_config(&mut self) -> Result<(), crate::Error> {
@@ -54,10 +63,9 @@ impl TrejState {
                 return Ok(());
             }
         };
-        let mut conf_fh = OpenOptions::new().read(true).open(path)?;
-        let mut raw_conf = String::new();
-        conf_fh.read_to_string(&mut raw_conf)?;
-        self.config = toml::from_str(&raw_conf)?;
+        let mut config = config::load_config(path, &config::default_conf_dirs())?.config;
+        config.keep_runtime_state(&self.config);
+        self.config = config;
         Ok(())
     }
     pub fn reload_graph(&mut self) -> Result<(), crate::Error> {
@@ -71,41 +79,40 @@ impl TrejState {
         Ok(())
     }
     pub fn apply_config(&mut self) -> Result<(), crate::Error> {
-        let graph = &mut self.graph;
-        let conf = &self.config;
-        let should_disconnect = graph
-            .all_connections()
-            .filter(|(a, b)| conf.connection_status(&a.name, &b.name).should_block())
-            .map(|(a, b)| (a.clone(), b.clone()))
-            .collect::<Vec<_>>();
-        for (a, b) in should_disconnect {
-            let (src, dst) = if a.direction.is_output() {
-                (a, b)
-            } else {
-                (b, a)
-            };
-            graph.disconnect(&src.name, &dst.name)?;
-        }
-        for (a, b) in conf.forced_connections() {
-            let adata = match graph.port_by_name(a) {
-                Some(dt) => dt,
-                None => {
-                    continue;
-                }
-            };
-            if graph.port_by_name(b).is_none() {
-                continue;
-            }
-            if graph.port_connections(a).any(|other| &other.name == b) {
-                continue;
-            }
-            let (src, dst) = if adata.direction.is_output() {
-                (a, b)
-            } else {
-                (b, a)
-            };
-            graph.connect(src, dst)?;
-        }
+        self.config.enforce(&mut self.graph)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::MemoryGraph;
+    use crate::model::PortDirection;
+    use crate::testing::port;
+
+    #[test]
+    fn test_apply_config() {
+        let synth_out = port("synth:out", PortDirection::Out);
+        let playback_1 = port("system:playback_1", PortDirection::In);
+        let playback_2 = port("system:playback_2", PortDirection::In);
+        let mut graph = MemoryGraph::new();
+        graph.add_port(synth_out.clone());
+        graph.add_port(playback_1.clone());
+        graph.add_port(playback_2.clone());
+        graph.add_connection(&synth_out.name, &playback_2.name);
+
+        let raw_conf = r#"
+            [synth]
+            lock = "full"
+            [synth.out]
+            connections = ["system:playback_1"]
+        "#;
+        let config = toml::from_str(raw_conf).unwrap();
+        let mut state = TrejState::with_graph(config, graph);
+        state.apply_config().unwrap();
+        let graph = state.graph();
+        assert!(graph.is_connected(&synth_out.name, &playback_1.name));
+        assert!(!graph.is_connected(&synth_out.name, &playback_2.name));
+    }
+}
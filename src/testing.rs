@@ -0,0 +1,40 @@
+//! Helpers shared by the tests.
+
+use crate::model::{PortCategory, PortData, PortDirection, PortFullname};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Makes an audio port called `name`, which must be a valid port name.
+pub fn port(name: &str, direction: PortDirection) -> PortData {
+    typed_port(name, PortCategory::Audio, direction)
+}
+
+/// Makes a port of `category` called `name`, which must be a valid port name.
+pub fn typed_port(name: &str, category: PortCategory, direction: PortDirection) -> PortData {
+    let name = PortFullname::new(name.to_owned()).unwrap();
+    PortData::new(name, category, direction)
+}
+
+/// A directory under the system temp directory, unique to a test and the process
+/// running it, which is removed along with its contents when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a fresh directory for the test called `name`.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("trej_{}_{}", name, std::process::id()));
+        // Left over from an earlier process with the same id.
+        fs::remove_dir_all(&path).ok();
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
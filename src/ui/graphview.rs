@@ -1,15 +1,20 @@
 use crate::config::LockConfig;
-use crate::graph::JackGraph;
+use crate::graph::{
+    GraphBackend, GraphError, MeterLevel, PortGraph, ServerInfo, TransportInfo, TransportState,
+};
 use crate::ui::UiAction;
 
-use crate::model::ItemKey;
+use crate::model::{ItemDataRef, ItemKey, PortCategory, PortFullname};
 
 use crossterm::event;
 use crossterm::event::{KeyCode, KeyModifiers};
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 use tui::buffer::Buffer;
-use tui::layout::{Constraint, Layout, Rect};
-use tui::widgets::{StatefulWidget, Widget};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Modifier, Style};
+use tui::text::Span;
+use tui::widgets::{Paragraph, StatefulWidget, Widget};
 
 use std::convert::{TryFrom, TryInto};
 
@@ -23,69 +28,255 @@ mod connect;
 use connect::*;
 mod disconnect;
 use disconnect::*;
+mod buffersize;
+use buffersize::*;
+mod meters;
+use meters::*;
+mod midimonitor;
+use midimonitor::*;
+mod transport;
+use transport::*;
+
+/// How long a MIDI activity LED stays lit after each event.
+const LED_HOLD: Duration = Duration::from_millis(150);
 
 #[derive(Debug, Default)]
 pub struct GraphViewState {
     connect_popup: Option<AddConnectionState>,
     disconnect_popup: Option<DelConnectionState>,
+    buffer_popup: Option<BufferSizeState>,
+    midi_popup: Option<MidiMonitorState>,
     tree_state: JackTreeState,
+    pretty_names: bool,
+    /// The outcome of the last server command, shown at the bottom of the view.
+    status: Option<String>,
+    /// Whether the outputs of the selected client or port should be metered.
+    metering: bool,
+    /// The ports the graph backend is currently metering.
+    metered: Vec<PortFullname>,
+    /// Whether to show activity LEDs beside MIDI outputs.
+    midi_leds: bool,
+    /// The ports the graph backend is currently listening to the MIDI of.
+    tapped: Vec<PortFullname>,
+    /// When each tapped port last sent any MIDI.
+    midi_seen: HashMap<PortFullname, Instant>,
+    /// Whether trej has been made the timebase master.
+    timebase_master: bool,
+    /// The tempo to count at as timebase master, if changed from `DEFAULT_BPM`.
+    bpm: Option<f64>,
 }
 
 impl GraphViewState {
     pub fn new() -> Self {
         Self::default()
     }
-    fn resolve_tree_state(&mut self, graph: &JackGraph) {
+    fn resolve_tree_state(&mut self, graph: &PortGraph) {
         let current_selection = self.tree_state.selected();
         let next_selection = resolve_partial(graph, current_selection);
         self.tree_state.select(next_selection);
     }
-    pub fn handle_pending_event(
+    pub fn handle_pending_event<G: GraphBackend>(
         &mut self,
-        graph: &mut JackGraph,
+        graph: &mut G,
         conf: &mut LockConfig,
         timeout: Option<Duration>,
     ) -> Result<Option<UiAction>, crate::Error> {
+        let retvl = if event::poll(timeout.unwrap_or_else(|| Duration::from_micros(0)))? {
+            let raw = event::read()?;
+            self.handle_event(raw, graph, conf)?
+        } else {
+            None
+        };
+        // Either the selection or the graph under it may have changed.
+        self.sync_meters(graph);
+        self.sync_midi(graph);
+        Ok(retvl)
+    }
+    /// Checks whether any meters, MIDI monitors or activity LEDs are shown, in which
+    /// case the view needs to be redrawn frequently.
+    pub fn is_live(&self) -> bool {
+        self.metering || self.midi_leds || self.midi_popup.is_some()
+    }
+    /// Points the backend's meters at the outputs of the current selection, or turns
+    /// them off if metering is disabled.
+    pub fn sync_meters<G: GraphBackend>(&mut self, graph: &mut G) {
+        let targets = if self.metering {
+            let selected = self.tree_state.selected();
+            let resolved = resolve(graph.graph(), selected).unwrap_or_else(ItemDataRef::root);
+            meter_targets(graph.graph(), &resolved)
+        } else {
+            Vec::new()
+        };
+        if targets == self.metered {
+            return;
+        }
+        match graph.set_meters(&targets) {
+            Ok(()) => {
+                self.metered = targets;
+            }
+            Err(e) => {
+                // Don't leave any half-attached meters behind.
+                graph.set_meters(&[]).ok();
+                self.metering = false;
+                self.metered.clear();
+                self.status = Some(e.to_string());
+            }
+        }
+    }
+    /// Points the backend's MIDI taps at the monitored port and, if the activity LEDs
+    /// are on, at every MIDI output, and then collects whatever they have received.
+    pub fn sync_midi<G: GraphBackend>(&mut self, graph: &mut G) {
+        let mut targets: Vec<_> = if self.midi_leds {
+            graph
+                .graph()
+                .all_ports()
+                .filter(|data| data.category == PortCategory::Midi && data.direction.is_output())
+                .map(|data| data.name.clone())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if let Some(popup) = self.midi_popup.as_ref() {
+            if !targets.contains(popup.port()) {
+                targets.push(popup.port().clone());
+            }
+        }
+        if targets != self.tapped {
+            match graph.set_midi_taps(&targets) {
+                Ok(()) => {
+                    self.tapped = targets;
+                }
+                Err(e) => {
+                    // Don't leave any half-attached taps behind.
+                    graph.set_midi_taps(&[]).ok();
+                    self.midi_leds = false;
+                    self.midi_popup = None;
+                    self.tapped.clear();
+                    self.status = Some(e.to_string());
+                }
+            }
+            let tapped = &self.tapped;
+            self.midi_seen.retain(|port, _| tapped.contains(port));
+        }
+        let now = Instant::now();
+        for (port, evt) in graph.take_midi_events() {
+            if let Some(popup) = self.midi_popup.as_mut() {
+                if popup.port() == &port {
+                    popup.push(evt);
+                }
+            }
+            self.midi_seen.insert(port, now);
+        }
+    }
+    fn bpm(&self) -> f64 {
+        self.bpm.unwrap_or(DEFAULT_BPM)
+    }
+    /// Changes the tempo used as timebase master by `delta` beats per minute.
+    fn nudge_bpm<G: GraphBackend>(&mut self, graph: &mut G, delta: f64) {
+        let (min, max) = BPM_RANGE;
+        let bpm = (self.bpm() + delta).clamp(min, max);
+        self.bpm = Some(bpm);
+        let msg = format!("Tempo set to {:.0} BPM.", bpm);
+        if self.timebase_master {
+            let res = graph.set_timebase(Some(bpm));
+            self.report(res, Some(msg));
+        } else {
+            self.status = Some(msg);
+        }
+    }
+    /// Gets the ports whose activity LEDs should currently be lit.
+    fn lit_ports(&self) -> HashSet<PortFullname> {
+        self.midi_seen
+            .iter()
+            .filter(|(_, seen)| seen.elapsed() < LED_HOLD)
+            .map(|(port, _)| port.clone())
+            .collect()
+    }
+    /// Shows the warnings the graph backend has raised since they were last shown, if
+    /// there are any.
+    pub fn show_warnings<G: GraphBackend>(&mut self, graph: &mut G) {
+        let warnings = graph.take_warnings();
+        if !warnings.is_empty() {
+            self.status = Some(warnings.join("; "));
+        }
+    }
+    /// Shows the outcome of a command sent to the graph backend, rather than
+    /// bailing out if it failed.
+    fn report<T>(&mut self, res: Result<T, GraphError>, success: Option<String>) {
+        self.status = match res {
+            Ok(_) => success,
+            Err(e) => Some(e.to_string()),
+        };
+    }
+    pub fn handle_event<G: GraphBackend>(
+        &mut self,
+        raw: event::Event,
+        graph: &mut G,
+        conf: &mut LockConfig,
+    ) -> Result<Option<UiAction>, crate::Error> {
+        if let event::Event::Key(_) = raw {
+            self.status = None;
+        }
+        if let Some(mut midipop) = self.midi_popup.take() {
+            let rs = midipop.handle_event(raw);
+            if let Some(UiAction::Close) = rs {
+                return Ok(Some(UiAction::Redraw));
+            } else {
+                self.midi_popup = Some(midipop);
+                return Ok(rs);
+            }
+        }
+        if let Some(mut bufpop) = self.buffer_popup.take() {
+            let rs = bufpop.handle_event(raw);
+            if let Some(UiAction::Close) = rs {
+                if let Some(frames) = bufpop.into_selection() {
+                    let res = graph.set_buffer_size(frames);
+                    self.report(res, Some(format!("Buffer size set to {} frames.", frames)));
+                }
+                return Ok(Some(UiAction::Redraw));
+            } else {
+                self.buffer_popup = Some(bufpop);
+                return Ok(rs);
+            }
+        }
         if let Some(mut conpop) = self.connect_popup.take() {
-            let conres = conpop.handle_pending_event(timeout);
-            if let Ok(Some(UiAction::Close)) = conres {
-                let (port_a, port_b_opt) = conpop.into_selection(graph, conf);
+            let conres = conpop.handle_event(raw);
+            if let Some(UiAction::Close) = conres {
+                let (port_a, port_b_opt) = conpop.into_selection(graph.graph(), conf);
                 if let Some(port_b) = port_b_opt {
                     let (src, dst) = if port_a.direction.is_input() {
                         (port_b.clone(), port_a)
                     } else {
                         (port_a, port_b.clone())
                     };
-                    graph.connect(&src.name, &dst.name)?;
+                    let res = graph.connect(&src.name, &dst.name);
+                    self.report(res, None);
                 }
                 return Ok(Some(UiAction::Redraw));
             } else {
                 self.connect_popup = Some(conpop);
-                return conres;
+                return Ok(conres);
             }
         }
         if let Some(mut dispop) = self.disconnect_popup.take() {
-            let rs = dispop.handle_pending_event(timeout);
-            if let Ok(Some(UiAction::Close)) = rs {
-                let (port_a, port_b_opt) = dispop.into_selection(graph, conf);
+            let rs = dispop.handle_event(raw);
+            if let Some(UiAction::Close) = rs {
+                let (port_a, port_b_opt) = dispop.into_selection(graph.graph(), conf);
                 if let Some(port_b) = port_b_opt {
                     let (src, dst) = if port_a.direction.is_input() {
                         (port_b.clone(), port_a)
                     } else {
                         (port_a, port_b.clone())
                     };
-                    graph.disconnect(&src.name, &dst.name)?;
+                    let res = graph.disconnect(&src.name, &dst.name);
+                    self.report(res, None);
                 }
                 return Ok(Some(UiAction::Redraw));
             } else {
                 self.disconnect_popup = Some(dispop);
-                return rs;
+                return Ok(rs);
             }
         }
-        if !event::poll(timeout.unwrap_or_else(|| Duration::from_micros(0)))? {
-            return Ok(None);
-        }
-        let raw = event::read()?;
         if let event::Event::Resize(_, _) = raw {
             return Ok(Some(UiAction::Redraw));
         }
@@ -97,6 +288,133 @@ impl GraphViewState {
         };
         match parsed {
             GraphUiEvent::Quit => Ok(Some(UiAction::Close)),
+            GraphUiEvent::SetBufferSize => {
+                match graph.server_info() {
+                    Some(server) => {
+                        self.buffer_popup = Some(BufferSizeState::new(server.buffer_size));
+                    }
+                    None => {
+                        let res = Err::<(), _>(GraphError::Unsupported("changing the buffer size"));
+                        self.report(res, None);
+                    }
+                }
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::ToggleFreewheel => {
+                let enable = !graph.server_info().map(|s| s.freewheel).unwrap_or(false);
+                let res = graph.set_freewheel(enable);
+                let msg = if enable {
+                    "Freewheeling started."
+                } else {
+                    "Freewheeling stopped."
+                };
+                self.report(res, Some(msg.to_owned()));
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::ToggleMeters => {
+                self.metering = !self.metering;
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::MonitorMidi => {
+                let selected = self.tree_state.selected();
+                let resolved = resolve(graph.graph(), selected).unwrap_or_else(ItemDataRef::root);
+                match resolved.port() {
+                    Some(port)
+                        if port.category == PortCategory::Midi && port.direction.is_output() =>
+                    {
+                        self.midi_popup = Some(MidiMonitorState::new(port));
+                    }
+                    _ => {
+                        self.status = Some("Only MIDI outputs can be monitored.".to_owned());
+                    }
+                }
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::ToggleMidiLeds => {
+                self.midi_leds = !self.midi_leds;
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::ToggleTransport => {
+                let rolling = match graph.transport() {
+                    Some(info) => info.state != TransportState::Stopped,
+                    None => false,
+                };
+                let res = graph.set_transport_rolling(!rolling);
+                self.report(res, None);
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::LocateStart => {
+                let res = graph.locate_transport(0);
+                self.report(res, None);
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::SeekBack | GraphUiEvent::SeekForward => {
+                const SEEK_SECONDS: u32 = 5;
+                let info = match graph.transport() {
+                    Some(info) => info,
+                    None => {
+                        let res = graph.locate_transport(0);
+                        self.report(res, None);
+                        return Ok(Some(UiAction::Redraw));
+                    }
+                };
+                let step = info.frame_rate.saturating_mul(SEEK_SECONDS);
+                let frame = if parsed == GraphUiEvent::SeekBack {
+                    info.frame.saturating_sub(step)
+                } else {
+                    info.frame.saturating_add(step)
+                };
+                let res = graph.locate_transport(frame);
+                self.report(res, None);
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::ToggleTimebase => {
+                if self.timebase_master {
+                    let res = graph.set_timebase(None);
+                    self.timebase_master = res.is_err();
+                    self.report(res, Some("No longer the timebase master.".to_owned()));
+                } else {
+                    let bpm = self.bpm();
+                    let res = graph.set_timebase(Some(bpm));
+                    self.timebase_master = res.is_ok();
+                    let msg = format!("Now the timebase master at {:.0} BPM.", bpm);
+                    self.report(res, Some(msg));
+                }
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::TempoUp => {
+                self.nudge_bpm(graph, 1.0);
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::TempoDown => {
+                self.nudge_bpm(graph, -1.0);
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::TogglePrettyNames => {
+                self.pretty_names = !self.pretty_names;
+                Ok(Some(UiAction::Redraw))
+            }
+            GraphUiEvent::NextProfile => {
+                let next = {
+                    let names: Vec<_> = conf.profile_names().collect();
+                    let cur = conf
+                        .active_profile()
+                        .and_then(|active| names.iter().position(|name| *name == active));
+                    let idx = cur.map_or(0, |idx| (idx + 1) % names.len());
+                    names.get(idx).map(|name| (*name).to_owned())
+                };
+                match next {
+                    Some(name) => {
+                        conf.select_profile(&name);
+                        self.status = Some(format!("Switched to profile \"{}\".", name));
+                        Ok(Some(UiAction::ApplyConfig))
+                    }
+                    None => {
+                        self.status = Some("The config has no profiles.".to_owned());
+                        Ok(Some(UiAction::Redraw))
+                    }
+                }
+            }
             GraphUiEvent::MoveUp => {
                 let cur = self.tree_state.selected();
                 let mut nxt = cur
@@ -144,6 +462,7 @@ impl GraphViewState {
                         return Ok(None);
                     }
                 };
+                let graph = graph.graph();
                 let client = match graph.all_clients().nth(client_idx) {
                     Some(c) => c,
                     None => {
@@ -172,6 +491,7 @@ impl GraphViewState {
                         return Ok(None);
                     }
                 };
+                let graph = graph.graph();
                 let client = match graph.all_clients().nth(client_idx) {
                     Some(c) => c,
                     None => {
@@ -194,13 +514,38 @@ impl GraphViewState {
 }
 
 pub struct GraphViewWidget<'a> {
-    graph: &'a JackGraph,
+    graph: &'a PortGraph,
     config: &'a LockConfig,
+    server: Option<ServerInfo>,
+    meters: Vec<(PortFullname, MeterLevel)>,
+    transport: Option<TransportInfo>,
 }
 
 impl<'a> GraphViewWidget<'a> {
-    pub fn new(graph: &'a JackGraph, config: &'a LockConfig) -> Self {
-        Self { graph, config }
+    pub fn new(graph: &'a PortGraph, config: &'a LockConfig) -> Self {
+        Self {
+            graph,
+            config,
+            server: None,
+            meters: Vec::new(),
+            transport: None,
+        }
+    }
+
+    /// Sets the server state shown on the root panel, which is also used to convert
+    /// frame counts into times.
+    pub fn with_server_info(self, server: Option<ServerInfo>) -> Self {
+        Self { server, ..self }
+    }
+
+    /// Sets the levels to show beside the data panel; no meters are shown if this is empty.
+    pub fn with_meter_levels(self, meters: Vec<(PortFullname, MeterLevel)>) -> Self {
+        Self { meters, ..self }
+    }
+
+    /// Sets the transport state to show at the bottom of the view, if any.
+    pub fn with_transport(self, transport: Option<TransportInfo>) -> Self {
+        Self { transport, ..self }
     }
 }
 
@@ -212,53 +557,79 @@ impl<'a> StatefulWidget for GraphViewWidget<'a> {
         let graph = self.graph;
         let conf = self.config;
 
+        let mut area = area;
+        if let Some(status) = state.status.as_deref() {
+            let status_rect = Rect::new(area.x, area.bottom().saturating_sub(1), area.width, 1);
+            area.height = area.height.saturating_sub(1);
+            let status_style = Style::default().add_modifier(Modifier::BOLD);
+            Paragraph::new(Span::styled(status, status_style)).render(status_rect, buf);
+        }
+        if let Some(info) = self.transport.as_ref() {
+            let height = TRANSPORT_HEIGHT.min(area.height);
+            let transport_rect = Rect::new(area.x, area.bottom() - height, area.width, height);
+            area.height -= height;
+            let master_bpm = if state.timebase_master {
+                Some(state.bpm())
+            } else {
+                None
+            };
+            TransportWidget::new(info)
+                .with_master_bpm(master_bpm)
+                .render(transport_rect, buf);
+        }
+
         let mut height_resolver = Layout::default()
             .constraints([Constraint::Ratio(2, 3), Constraint::Ratio(1, 3)])
             .split(area);
 
         let info_rect = height_resolver.pop().unwrap();
         let list_rect = height_resolver.pop().unwrap();
-        JackTree::new(graph).render(list_rect, buf, &mut state.tree_state);
+        let mut tree = JackTree::new(graph).with_pretty_names(state.pretty_names);
+        if state.midi_leds {
+            tree = tree.with_midi_activity(state.lit_ports());
+        }
+        tree.render(list_rect, buf, &mut state.tree_state);
 
-        let dataview = make_dataview(selected, graph, conf);
+        let info_rect = if self.meters.is_empty() || !state.metering {
+            info_rect
+        } else {
+            let mut width_resolver = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+                .split(info_rect);
+            let meter_rect = width_resolver.pop().unwrap();
+            MeterWidget::new(graph, &self.meters)
+                .with_pretty_names(state.pretty_names)
+                .render(meter_rect, buf);
+            width_resolver.pop().unwrap()
+        };
+        let dataview = make_dataview(selected, graph, conf, self.server.as_ref());
         dataview.render(info_rect, buf);
 
         if let Some(constate) = state.connect_popup.as_mut() {
             let widget = AddConnectionWidget::new(graph, conf);
             let (width, height) = widget.dims(constate);
-
-            // Center the list horizontally.
-            let extra_space = area.width.saturating_sub(width);
-            let left_pad = extra_space / 2;
-            let mut list_area = area;
-            list_area.x += left_pad;
-            list_area.width -= extra_space;
-
-            list_area.y += 4; // 4 spaces of vertical padding.
-            list_area.height -= 8; // 4 to offset the y padding + 4 to pad the bottom
-            let extra_height = list_area.height.saturating_sub(height);
-            list_area.height -= extra_height; // Align as far up as possible.
-
+            let list_area = popup_rect(area, width, height);
             widget.render(list_area, buf, constate);
         }
         if let Some(constate) = state.disconnect_popup.as_mut() {
             let widget = DelConnectionWidget::new(graph, conf);
             let (width, height) = widget.dims(constate);
-
-            // Center the list horizontally.
-            let extra_space = area.width.saturating_sub(width);
-            let left_pad = extra_space / 2;
-            let mut list_area = area;
-            list_area.x += left_pad;
-            list_area.width -= extra_space;
-
-            list_area.y += 4; // 4 spaces of vertical padding.
-            list_area.height -= 8; // 4 to offset the y padding + 4 to pad the bottom
-            let extra_height = list_area.height.saturating_sub(height);
-            list_area.height -= extra_height; // Align as far up as possible.
-
+            let list_area = popup_rect(area, width, height);
             widget.render(list_area, buf, constate);
         }
+        if let Some(bufstate) = state.buffer_popup.as_mut() {
+            let widget = BufferSizeWidget::new();
+            let (width, height) = widget.dims();
+            let list_area = popup_rect(area, width, height);
+            widget.render(list_area, buf, bufstate);
+        }
+        if let Some(midistate) = state.midi_popup.as_mut() {
+            let widget = MidiMonitorWidget::new();
+            let (width, height) = widget.dims(midistate);
+            let list_area = popup_rect(area, width, height);
+            widget.render(list_area, buf, midistate);
+        }
     }
 }
 
@@ -270,6 +641,20 @@ pub enum GraphUiEvent {
     MoveRight,
     AddConnection,
     DelConnection,
+    SetBufferSize,
+    ToggleFreewheel,
+    ToggleMeters,
+    MonitorMidi,
+    ToggleMidiLeds,
+    ToggleTransport,
+    LocateStart,
+    SeekBack,
+    SeekForward,
+    ToggleTimebase,
+    TempoUp,
+    TempoDown,
+    TogglePrettyNames,
+    NextProfile,
     Quit,
 }
 
@@ -282,6 +667,20 @@ impl TryFrom<event::KeyEvent> for GraphUiEvent {
         const RIGHT_CODES: &[KeyCode] = &[KeyCode::Right, KeyCode::Char('d'), KeyCode::Char('l')];
         const CONNECT_CODES: &[KeyCode] = &[KeyCode::Char('c')];
         const DISCONNECT_CODES: &[KeyCode] = &[KeyCode::Char('d')];
+        const PRETTY_NAME_CODES: &[KeyCode] = &[KeyCode::Char('p')];
+        const PROFILE_CODES: &[KeyCode] = &[KeyCode::Char('P')];
+        const BUFFER_SIZE_CODES: &[KeyCode] = &[KeyCode::Char('b')];
+        const FREEWHEEL_CODES: &[KeyCode] = &[KeyCode::Char('f')];
+        const METER_CODES: &[KeyCode] = &[KeyCode::Char('m')];
+        const MIDI_MONITOR_CODES: &[KeyCode] = &[KeyCode::Char('i')];
+        const MIDI_LED_CODES: &[KeyCode] = &[KeyCode::Char('e')];
+        const TRANSPORT_CODES: &[KeyCode] = &[KeyCode::Char(' ')];
+        const LOCATE_START_CODES: &[KeyCode] = &[KeyCode::Home, KeyCode::Char('0')];
+        const SEEK_BACK_CODES: &[KeyCode] = &[KeyCode::Char('[')];
+        const SEEK_FORWARD_CODES: &[KeyCode] = &[KeyCode::Char(']')];
+        const TIMEBASE_CODES: &[KeyCode] = &[KeyCode::Char('t')];
+        const TEMPO_UP_CODES: &[KeyCode] = &[KeyCode::Char('+'), KeyCode::Char('=')];
+        const TEMPO_DOWN_CODES: &[KeyCode] = &[KeyCode::Char('-')];
 
         let code = value.code;
         let modifiers = value.modifiers;
@@ -293,6 +692,34 @@ impl TryFrom<event::KeyEvent> for GraphUiEvent {
             Ok(GraphUiEvent::AddConnection)
         } else if DISCONNECT_CODES.contains(&code) {
             Ok(GraphUiEvent::DelConnection)
+        } else if PRETTY_NAME_CODES.contains(&code) {
+            Ok(GraphUiEvent::TogglePrettyNames)
+        } else if PROFILE_CODES.contains(&code) {
+            Ok(GraphUiEvent::NextProfile)
+        } else if BUFFER_SIZE_CODES.contains(&code) {
+            Ok(GraphUiEvent::SetBufferSize)
+        } else if FREEWHEEL_CODES.contains(&code) {
+            Ok(GraphUiEvent::ToggleFreewheel)
+        } else if METER_CODES.contains(&code) {
+            Ok(GraphUiEvent::ToggleMeters)
+        } else if MIDI_MONITOR_CODES.contains(&code) {
+            Ok(GraphUiEvent::MonitorMidi)
+        } else if MIDI_LED_CODES.contains(&code) {
+            Ok(GraphUiEvent::ToggleMidiLeds)
+        } else if TRANSPORT_CODES.contains(&code) {
+            Ok(GraphUiEvent::ToggleTransport)
+        } else if LOCATE_START_CODES.contains(&code) {
+            Ok(GraphUiEvent::LocateStart)
+        } else if SEEK_BACK_CODES.contains(&code) {
+            Ok(GraphUiEvent::SeekBack)
+        } else if SEEK_FORWARD_CODES.contains(&code) {
+            Ok(GraphUiEvent::SeekForward)
+        } else if TIMEBASE_CODES.contains(&code) {
+            Ok(GraphUiEvent::ToggleTimebase)
+        } else if TEMPO_UP_CODES.contains(&code) {
+            Ok(GraphUiEvent::TempoUp)
+        } else if TEMPO_DOWN_CODES.contains(&code) {
+            Ok(GraphUiEvent::TempoDown)
         } else if UP_CODES.contains(&code) {
             Ok(GraphUiEvent::MoveUp)
         } else if DOWN_CODES.contains(&code) {
@@ -324,7 +751,22 @@ impl TryFrom<event::Event> for GraphUiEvent {
     }
 }
 
-fn resolve_partial(graph: &JackGraph, path: ItemKey) -> ItemKey {
+/// Gets where to draw a popup of at most `width` by `height` cells within `area`:
+/// centered horizontally, and as far up as possible below 4 rows of padding, with
+/// another 4 left free at the bottom.
+fn popup_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let extra_space = area.width.saturating_sub(width);
+    let left_pad = extra_space / 2;
+    let top_pad = area.height.min(4);
+    Rect::new(
+        area.x + left_pad,
+        area.y + top_pad,
+        area.width - extra_space,
+        area.height.saturating_sub(8).min(height),
+    )
+}
+
+fn resolve_partial(graph: &PortGraph, path: ItemKey) -> ItemKey {
     macro_rules! do_layer {
         ($idx:expr, $itr:expr, $retvl:expr) => {{
             let (cur_idx, cur_key) = match $idx.and_then(|n| Some((n, $itr.nth(n)?))) {
@@ -348,3 +790,18 @@ fn resolve_partial(graph: &JackGraph, path: ItemKey) -> ItemKey {
 
     retvl
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_popup_rect() {
+        let area = Rect::new(0, 0, 80, 40);
+        assert_eq!(popup_rect(area, 20, 10), Rect::new(30, 4, 20, 10));
+        assert_eq!(popup_rect(area, 100, 100), Rect::new(0, 4, 80, 32));
+        // Terminals too small for the padding get an empty popup inside the area.
+        let tiny = Rect::new(0, 2, 10, 3);
+        assert_eq!(popup_rect(tiny, 20, 10), Rect::new(0, 5, 10, 0));
+    }
+}
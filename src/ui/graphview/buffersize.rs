@@ -0,0 +1,159 @@
+use crate::ui::UiAction;
+
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::text::Span;
+use tui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, ListState, StatefulWidget, Widget,
+};
+
+use crossterm::event::{self, KeyCode};
+
+use std::convert::{TryFrom, TryInto};
+
+/// The buffer sizes, in frames, that can be picked from the popup.
+const BUFFER_SIZES: &[u32] = &[16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+
+#[derive(Debug)]
+pub struct BufferSizeState {
+    selected_idx: ListState,
+}
+
+impl BufferSizeState {
+    /// Constructs a new popup with `current`, if it is one of the options, preselected.
+    pub fn new(current: u32) -> Self {
+        let mut selected_idx = ListState::default();
+        selected_idx.select(BUFFER_SIZES.iter().position(|&size| size == current));
+        Self { selected_idx }
+    }
+    pub fn into_selection(self) -> Option<u32> {
+        let idx = self.selected_idx.selected()?;
+        BUFFER_SIZES.get(idx).copied()
+    }
+    pub fn handle_event(&mut self, raw: event::Event) -> Option<UiAction> {
+        let parsed = match raw.try_into() {
+            Ok(evt) => evt,
+            Err(()) => {
+                return None;
+            }
+        };
+        match parsed {
+            BufferSizeEvent::MoveUp => {
+                let cur = self.selected_idx.selected();
+                let nxt = match cur {
+                    Some(n) => Some(n.saturating_sub(1)),
+                    None => Some(0),
+                };
+                self.selected_idx.select(nxt);
+                Some(UiAction::Redraw)
+            }
+            BufferSizeEvent::MoveDown => {
+                let cur = self.selected_idx.selected();
+                let nxt = match cur {
+                    Some(n) => Some((n + 1).min(BUFFER_SIZES.len() - 1)),
+                    None => Some(0),
+                };
+                self.selected_idx.select(nxt);
+                Some(UiAction::Redraw)
+            }
+            BufferSizeEvent::Cancel => {
+                self.selected_idx.select(None);
+                Some(UiAction::Close)
+            }
+            BufferSizeEvent::Select => Some(UiAction::Close),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BufferSizeWidget {}
+
+impl BufferSizeWidget {
+    pub fn new() -> Self {
+        Self {}
+    }
+    pub fn dims(&self) -> (u16, u16) {
+        const TITLE_LEN: usize = "Buffer Size".len() + 3;
+        let max_item_size = BUFFER_SIZES
+            .iter()
+            .map(|&size| format_size(size).len())
+            .max()
+            .unwrap_or(0);
+        let item_width = max_item_size.max(TITLE_LEN) as u16;
+        let w = item_width + 4; // Left border + left padding + right border + right padding
+        let h = (BUFFER_SIZES.len() as u16) + 2; // Top border + bottom border
+        (w, h)
+    }
+}
+
+impl StatefulWidget for BufferSizeWidget {
+    type State = BufferSizeState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let list_items: Vec<_> = BUFFER_SIZES
+            .iter()
+            .map(|&size| ListItem::new(format_size(size)))
+            .collect();
+        let list = List::new(list_items)
+            .block(make_block())
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        Widget::render(Clear {}, area, buf);
+        StatefulWidget::render(list, area, buf, &mut state.selected_idx);
+    }
+}
+
+fn format_size(size: u32) -> String {
+    format!("{} frames", size)
+}
+
+fn make_block<'a>() -> Block<'a> {
+    let title_style = Style::default()
+        .add_modifier(Modifier::BOLD)
+        .add_modifier(Modifier::UNDERLINED);
+    let title = Span::styled("Buffer Size", title_style);
+    Block::default()
+        .borders(Borders::all())
+        .border_type(BorderType::Double)
+        .border_style(Style::default().add_modifier(Modifier::BOLD))
+        .title(title)
+}
+
+enum BufferSizeEvent {
+    MoveUp,
+    MoveDown,
+    Cancel,
+    Select,
+}
+
+impl TryFrom<event::KeyEvent> for BufferSizeEvent {
+    type Error = ();
+    fn try_from(value: event::KeyEvent) -> Result<Self, Self::Error> {
+        const UP_CODES: &[KeyCode] = &[KeyCode::Up, KeyCode::Char('w'), KeyCode::Char('k')];
+        const DOWN_CODES: &[KeyCode] = &[KeyCode::Down, KeyCode::Char('s'), KeyCode::Char('j')];
+
+        let code = value.code;
+
+        if UP_CODES.contains(&code) {
+            Ok(BufferSizeEvent::MoveUp)
+        } else if DOWN_CODES.contains(&code) {
+            Ok(BufferSizeEvent::MoveDown)
+        } else if code == KeyCode::Esc || code == KeyCode::Backspace {
+            Ok(BufferSizeEvent::Cancel)
+        } else if code == KeyCode::Enter {
+            Ok(BufferSizeEvent::Select)
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl TryFrom<event::Event> for BufferSizeEvent {
+    type Error = ();
+    fn try_from(value: event::Event) -> Result<Self, Self::Error> {
+        match value {
+            event::Event::Key(keyevent) => keyevent.try_into(),
+            _ => Err(()),
+        }
+    }
+}
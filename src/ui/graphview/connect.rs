@@ -1,5 +1,5 @@
 use crate::config::LockConfig;
-use crate::graph::JackGraph;
+use crate::graph::PortGraph;
 use crate::model::PortData;
 use crate::ui::UiAction;
 
@@ -14,7 +14,6 @@ use tui::widgets::{
 use crossterm::event::{self, KeyCode};
 
 use std::convert::{TryFrom, TryInto};
-use std::time::Duration;
 
 #[derive(Debug)]
 pub struct AddConnectionState {
@@ -29,7 +28,7 @@ impl AddConnectionState {
             selected_idx: ListState::default(),
         }
     }
-    pub fn resolve_tree_state(&mut self, graph: &JackGraph, conf: &LockConfig) {
+    pub fn resolve_tree_state(&mut self, graph: &PortGraph, conf: &LockConfig) {
         let cur_idx = match self.selected_idx.selected() {
             Some(n) => n,
             None => {
@@ -45,7 +44,7 @@ impl AddConnectionState {
     }
     pub fn into_selection<'a>(
         self,
-        graph: &'a JackGraph,
+        graph: &'a PortGraph,
         locks: &LockConfig,
     ) -> (PortData, Option<&'a PortData>) {
         let idx = match self.selected_idx.selected() {
@@ -57,18 +56,11 @@ impl AddConnectionState {
         let con = available_ports(&self.port, graph, locks).nth(idx);
         (self.port, con)
     }
-    pub fn handle_pending_event(
-        &mut self,
-        timeout: Option<Duration>,
-    ) -> Result<Option<UiAction>, crate::Error> {
-        if !event::poll(timeout.unwrap_or_else(|| Duration::from_micros(0)))? {
-            return Ok(None);
-        }
-        let raw = event::read()?;
+    pub fn handle_event(&mut self, raw: event::Event) -> Option<UiAction> {
         let parsed = match raw.try_into() {
             Ok(evt) => evt,
             Err(()) => {
-                return Ok(None);
+                return None;
             }
         };
         match parsed {
@@ -79,7 +71,7 @@ impl AddConnectionState {
                     None => Some(0),
                 };
                 self.selected_idx.select(nxt);
-                Ok(Some(UiAction::Redraw))
+                Some(UiAction::Redraw)
             }
             AddConnectionEvent::MoveDown => {
                 let cur = self.selected_idx.selected();
@@ -88,24 +80,24 @@ impl AddConnectionState {
                     None => Some(0),
                 };
                 self.selected_idx.select(nxt);
-                Ok(Some(UiAction::Redraw))
+                Some(UiAction::Redraw)
             }
             AddConnectionEvent::Cancel => {
                 self.selected_idx.select(None);
-                Ok(Some(UiAction::Close))
+                Some(UiAction::Close)
             }
-            AddConnectionEvent::Select => Ok(Some(UiAction::Close)),
+            AddConnectionEvent::Select => Some(UiAction::Close),
         }
     }
 }
 
 pub struct AddConnectionWidget<'a> {
-    graph: &'a JackGraph,
+    graph: &'a PortGraph,
     conf: &'a LockConfig,
 }
 
 impl<'a> AddConnectionWidget<'a> {
-    pub fn new(graph: &'a JackGraph, conf: &'a LockConfig) -> Self {
+    pub fn new(graph: &'a PortGraph, conf: &'a LockConfig) -> Self {
         Self { graph, conf }
     }
 }
@@ -130,7 +122,7 @@ impl<'a> StatefulWidget for AddConnectionWidget<'a> {
     type State = AddConnectionState;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
         state.resolve_tree_state(self.graph, self.conf);
-        let graph: &JackGraph = self.graph;
+        let graph: &PortGraph = self.graph;
         let conf: &LockConfig = self.conf;
         let selected: &mut ListState = &mut state.selected_idx;
         let port: &PortData = &state.port;
@@ -151,15 +143,15 @@ impl<'a> StatefulWidget for AddConnectionWidget<'a> {
 
 fn available_ports<'a, 'b: 'a>(
     port: &'a PortData,
-    graph: &'b JackGraph,
+    graph: &'b PortGraph,
     conf: &'a LockConfig,
 ) -> impl Iterator<Item = &'b PortData> + 'a {
     graph.all_ports().filter(move |cur| {
         cur.name != port.name
-            && cur.category == port.category
+            && cur.is_compatible(port)
             && cur.direction == port.direction.flip()
             && !graph.is_connected(&port.name, &cur.name)
-            && !conf.connection_status(&port.name, &cur.name).should_block()
+            && !conf.connection_status(port, cur).should_block()
     })
 }
 
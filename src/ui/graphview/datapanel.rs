@@ -1,8 +1,8 @@
 use crate::config::{LockConfig, LockStatus};
-use crate::graph::JackGraph;
+use crate::graph::{PortGraph, ServerInfo};
 use crate::model::ItemDataRef;
 use crate::model::ItemKey;
-use crate::model::{PortCategory, PortData, PortDirection};
+use crate::model::{LatencyRange, PortCategory, PortData, PortDirection};
 
 use std::borrow::Cow;
 
@@ -44,15 +44,55 @@ where
 
 /// Makes the default, root-level data view panel.
 fn make_default_dataview<'a>(
-    _graph: &JackGraph,
-    _conf: &LockConfig,
+    _graph: &PortGraph,
+    conf: &LockConfig,
+    server: Option<&ServerInfo>,
 ) -> DataviewWidget<'a, impl AsRef<[DataField<'a>]> + 'a> {
-    DataviewWidget::new([])
+    let mut fields = Vec::new();
+    if let Some(profile) = conf.active_profile() {
+        fields.push(DataField::new("Profile", format!("\"{}\"", profile)));
+    }
+    if !conf.conflicts().is_empty() {
+        let count = format!("{}", conf.conflicts().len());
+        fields.push(DataField::new("Rule Conflicts", count));
+    }
+    for (name, _, holds) in conf.conditions() {
+        let label = format!("When \"{}\"", name);
+        fields.push(DataField::new(
+            label,
+            if holds { "Holds" } else { "Doesn't hold" },
+        ));
+    }
+    let server = match server {
+        Some(server) => server,
+        None => {
+            return DataviewWidget::new(fields);
+        }
+    };
+    let name_widget = DataField::new("Requested Server", format!("\"{}\"", server.requested_name));
+    let rate_widget = DataField::new("Sample Rate", format!("{} Hz", server.sample_rate));
+    let buffer_widget = DataField::new("Buffer Size", format!("{} frames", server.buffer_size));
+    let latency_widget = DataField::new("Latency", format!("{:.2} ms", server.cycle_latency_ms()));
+    let load_widget = DataField::new("DSP Load", format!("{:.1}%", server.dsp_load));
+    let realtime_widget = DataField::new("Realtime", if server.realtime { "Yes" } else { "No" });
+    let xrun_widget = DataField::new("Xruns", format!("{}", server.xruns));
+    let freewheel_widget = DataField::new("Freewheel", if server.freewheel { "On" } else { "Off" });
+    fields.extend(vec![
+        name_widget,
+        rate_widget,
+        buffer_widget,
+        latency_widget,
+        load_widget,
+        realtime_widget,
+        xrun_widget,
+        freewheel_widget,
+    ]);
+    DataviewWidget::new(fields)
 }
 
 /// Makes the data view panel for a JACK Client.
 fn make_client_dataview<'a>(
-    graph: &JackGraph,
+    graph: &PortGraph,
     conf: &LockConfig,
     client_name: &str,
 ) -> DataviewWidget<'a, impl AsRef<[DataField<'a>]> + 'a> {
@@ -62,49 +102,100 @@ fn make_client_dataview<'a>(
         LockStatus::Block => "Blocking New",
         LockStatus::Force => "Forcing Old",
         LockStatus::Full => "Locked",
+        LockStatus::Exclusive => "Exclusive",
     };
     let lock_widget = DataField::new("Lock Status", lock_str);
-    let (midi_inputs, midi_outputs, audio_inputs, audio_outputs) = graph
-        .client_ports(client_name)
-        .map(|port| match (port.category, port.direction) {
-            (PortCategory::Midi, PortDirection::In) => (1, 0, 0, 0),
-            (PortCategory::Midi, PortDirection::Out) => (0, 1, 0, 0),
-            (PortCategory::Audio, PortDirection::In) => (0, 0, 1, 0),
-            (PortCategory::Audio, PortDirection::Out) => (0, 0, 0, 1),
-            (PortCategory::Unknown, _) => (0, 0, 0, 0),
-        })
-        .fold((0, 0, 0, 0), |acc, cur| {
-            (acc.0 + cur.0, acc.1 + cur.1, acc.2 + cur.2, acc.3 + cur.3)
-        });
+    // Counts of (inputs, outputs) for each category.
+    let mut midi_counts = (0, 0);
+    let mut audio_counts = (0, 0);
+    let mut cv_counts = (0, 0);
+    let mut osc_counts = (0, 0);
+    for port in graph.client_ports(client_name) {
+        let counts = match port.category {
+            PortCategory::Midi => &mut midi_counts,
+            PortCategory::Audio => &mut audio_counts,
+            PortCategory::Cv => &mut cv_counts,
+            PortCategory::Osc => &mut osc_counts,
+            PortCategory::Unknown => {
+                continue;
+            }
+        };
+        match port.direction {
+            PortDirection::In => counts.0 += 1,
+            PortDirection::Out => counts.1 += 1,
+        }
+    }
 
     let client_widget = DataField::new("Name", format!("\"{}\"", client_name));
-    let midiin_widget = DataField::new("Midi Inputs", format!("{}", midi_inputs));
+    let midiin_widget = DataField::new("Midi Inputs", format!("{}", midi_counts.0));
 
-    let midiout_widget = DataField::new("Midi Outputs", format!("{}", midi_outputs));
-    let audioin_widget = DataField::new("Audio Inputs", format!("{}", audio_inputs));
-    let audioout_widget = DataField::new("Audio Outputs", format!("{}", audio_outputs));
-    DataviewWidget::new([
+    let midiout_widget = DataField::new("Midi Outputs", format!("{}", midi_counts.1));
+    let audioin_widget = DataField::new("Audio Inputs", format!("{}", audio_counts.0));
+    let audioout_widget = DataField::new("Audio Outputs", format!("{}", audio_counts.1));
+    let hardware = if graph.client_is_physical(client_name) {
+        "Yes"
+    } else {
+        "No"
+    };
+    let hardware_widget = DataField::new("Hardware", hardware);
+    let mut fields = vec![
         client_widget,
+        hardware_widget,
         midiin_widget,
         midiout_widget,
         audioin_widget,
         audioout_widget,
-        lock_widget,
-    ])
+    ];
+    // CV and OSC ports are rare enough that they are only listed when present.
+    if cv_counts != (0, 0) {
+        fields.push(DataField::new("CV Inputs", format!("{}", cv_counts.0)));
+        fields.push(DataField::new("CV Outputs", format!("{}", cv_counts.1)));
+    }
+    if osc_counts != (0, 0) {
+        fields.push(DataField::new("OSC Inputs", format!("{}", osc_counts.0)));
+        fields.push(DataField::new("OSC Outputs", format!("{}", osc_counts.1)));
+    }
+    fields.push(lock_widget);
+    if let Some(source) = conf.client_lock_source(client_name) {
+        fields.push(DataField::new("Lock Rule", source.to_string()));
+    }
+    match conf.client_binding(client_name) {
+        Some((Some(entry), mode)) => {
+            let binding = format!("\"{}\" ({} instance)", entry, mode);
+            fields.push(DataField::new("Binding", binding));
+        }
+        Some((None, mode)) => {
+            let holders = conf.bound_instances(client_name).unwrap_or_default();
+            let binding = if holders.is_empty() {
+                format!("Unbound ({} instance)", mode)
+            } else {
+                format!("Unbound, held by \"{}\"", holders.join("\", \""))
+            };
+            fields.push(DataField::new("Binding", binding));
+        }
+        None => {}
+    }
+    let property_widgets = graph
+        .client_properties(client_name)
+        .map(|(key, value)| DataField::new(key.clone(), value.clone()));
+    fields.extend(property_widgets);
+    DataviewWidget::new(fields)
 }
 
 /// Makes the data view panel for a JACK Port.
 fn make_port_dataview<'a>(
-    _graph: &JackGraph,
+    _graph: &PortGraph,
     conf: &LockConfig,
     port: &PortData,
+    sample_rate: Option<usize>,
 ) -> DataviewWidget<'a, impl AsRef<[DataField<'a>]> + 'a> {
-    let lock = conf.port_status(&port.name);
+    let lock = conf.port_status(port);
     let lock_str = match lock {
         LockStatus::None => "Unlocked",
         LockStatus::Block => "Blocking New",
         LockStatus::Force => "Forcing Old",
         LockStatus::Full => "Locked",
+        LockStatus::Exclusive => "Exclusive",
     };
     let lock_widget = DataField::new("Lock Status", lock_str);
     let kind = match (port.category, port.direction) {
@@ -112,6 +203,10 @@ fn make_port_dataview<'a>(
         (PortCategory::Audio, PortDirection::Out) => "Audio Output",
         (PortCategory::Midi, PortDirection::In) => "Midi Input",
         (PortCategory::Midi, PortDirection::Out) => "Midi Output",
+        (PortCategory::Cv, PortDirection::In) => "CV Input",
+        (PortCategory::Cv, PortDirection::Out) => "CV Output",
+        (PortCategory::Osc, PortDirection::In) => "OSC Input",
+        (PortCategory::Osc, PortDirection::Out) => "OSC Output",
         (PortCategory::Unknown, PortDirection::In) => "Unknown Input",
         (PortCategory::Unknown, PortDirection::Out) => "Unknown Output",
     };
@@ -119,13 +214,80 @@ fn make_port_dataview<'a>(
     let client_widget = DataField::new("Client", format!("\"{}\"", port.name.client_name()));
     let name_widget = DataField::new("Name", format!("\"{}\"", port.name.port_shortname()));
     let kind_widget = DataField::new("Kind", kind);
+    let type_widget = DataField::new("Type", format!("\"{}\"", port.port_type));
+
+    let flags = [
+        (port.is_physical, "Physical"),
+        (port.is_terminal, "Terminal"),
+        (port.can_monitor, "Can Monitor"),
+        (port.is_monitoring, "Monitoring"),
+    ];
+    let flag_names = flags
+        .iter()
+        .filter(|(is_set, _)| *is_set)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+    let flags_widget = DataField::new("Flags", flag_names.join(", "));
+    let mut fields = vec![
+        name_widget,
+        client_widget,
+        kind_widget,
+        type_widget,
+        flags_widget,
+        lock_widget,
+    ];
+    if let Some(source) = conf.port_lock_source(port) {
+        fields.push(DataField::new("Lock Rule", source.to_string()));
+    }
+    if let Some(range) = port.capture_latency {
+        let value = format_latency(range, sample_rate);
+        fields.push(DataField::new("Capture Latency", value));
+    }
+    if let Some(range) = port.playback_latency {
+        let value = format_latency(range, sample_rate);
+        fields.push(DataField::new("Playback Latency", value));
+    }
+    if !port.aliases.is_empty() {
+        let aliases = port
+            .aliases
+            .iter()
+            .map(|alias| format!("\"{}\"", alias.as_ref()))
+            .collect::<Vec<_>>();
+        fields.push(DataField::new("Aliases", aliases.join(", ")));
+    }
+    let property_widgets = port
+        .properties
+        .iter()
+        .map(|(key, value)| DataField::new(key.clone(), value.clone()));
+    fields.extend(property_widgets);
+    DataviewWidget::new(fields)
+}
 
-    DataviewWidget::new([name_widget, client_widget, kind_widget, lock_widget])
+/// Formats a latency range in frames, along with milliseconds if the sample rate is known.
+fn format_latency(range: LatencyRange, sample_rate: Option<usize>) -> String {
+    let frames = if range.min == range.max {
+        format!("{} frames", range.min)
+    } else {
+        format!("{}-{} frames", range.min, range.max)
+    };
+    let sample_rate = match sample_rate {
+        Some(rate) if rate > 0 => rate as f64,
+        _ => {
+            return frames;
+        }
+    };
+    let min_ms = f64::from(range.min) * 1000.0 / sample_rate;
+    let max_ms = f64::from(range.max) * 1000.0 / sample_rate;
+    if range.min == range.max {
+        format!("{} ({:.2} ms)", frames, min_ms)
+    } else {
+        format!("{} ({:.2}-{:.2} ms)", frames, min_ms, max_ms)
+    }
 }
 
 /// Makes the data view panel for a connection between two ports.  
 fn make_connection_dataview<'a>(
-    _graph: &'a JackGraph,
+    _graph: &'a PortGraph,
     conf: &'a LockConfig,
     port_a: &'a PortData,
     port_b: &'a PortData,
@@ -138,14 +300,15 @@ fn make_connection_dataview<'a>(
     let data_kind = match port_a.category {
         PortCategory::Midi => "Midi",
         PortCategory::Audio => "Audio",
+        PortCategory::Cv => "CV",
+        PortCategory::Osc => "OSC",
         PortCategory::Unknown => "Unknown",
     };
-    let lock = conf.connection_status(&input_port.name, &output_port.name);
+    let lock = conf.connection_status(input_port, output_port);
     let lock_str = match lock {
-        LockStatus::None => "Unlocked",
-        LockStatus::Block => "Unlocked",
-        LockStatus::Force => "Locked",
-        LockStatus::Full => "Locked",
+        _ if conf.is_superseded(input_port, output_port) => "Replaced",
+        LockStatus::None | LockStatus::Block | LockStatus::Exclusive => "Unlocked",
+        LockStatus::Force | LockStatus::Full => "Locked",
     };
     let lock_widget = DataField::new("Lock Status", lock_str);
 
@@ -205,8 +368,9 @@ impl<'a, T: AsRef<[DataField<'a>]> + 'a> DataviewWidget<'a, T> {
 
 pub fn make_dataview<'a>(
     path: ItemKey,
-    graph: &'a JackGraph,
+    graph: &'a PortGraph,
     conf: &'a LockConfig,
+    server: Option<&ServerInfo>,
 ) -> DataviewWidget<'a, impl AsRef<[DataField<'a>]> + 'a> {
     macro_rules! unwrap_or_ret {
         ($itm:expr, $ret:expr) => {{
@@ -223,7 +387,7 @@ pub fn make_dataview<'a>(
 
     let client_name = unwrap_or_ret!(
         resolved.client(),
-        make_default_dataview(graph, conf).map_items(ArrayWrapper::Default)
+        make_default_dataview(graph, conf, server).map_items(ArrayWrapper::Default)
     );
 
     let port_data = unwrap_or_ret!(
@@ -233,7 +397,8 @@ pub fn make_dataview<'a>(
 
     let con_data = unwrap_or_ret!(
         resolved.connection(),
-        make_port_dataview(graph, conf, port_data).map_items(ArrayWrapper::Port)
+        make_port_dataview(graph, conf, port_data, server.map(|s| s.sample_rate))
+            .map_items(ArrayWrapper::Port)
     );
     make_connection_dataview(graph, conf, port_data, con_data).map_items(ArrayWrapper::Con)
 }
@@ -333,7 +498,7 @@ impl<'a> DataField<'a> {
     }
 }
 
-fn resolve(graph: &JackGraph, path: ItemKey) -> Option<ItemDataRef<'_>> {
+pub fn resolve(graph: &PortGraph, path: ItemKey) -> Option<ItemDataRef<'_>> {
     let mut retvl = ItemDataRef::root();
     let client = match path.client_idx() {
         Some(n) => graph.all_clients().nth(n)?,
@@ -1,5 +1,5 @@
 use crate::config::LockConfig;
-use crate::graph::JackGraph;
+use crate::graph::PortGraph;
 use crate::model::PortData;
 use crate::ui::UiAction;
 
@@ -14,7 +14,6 @@ use tui::widgets::{
 use crossterm::event::{self, KeyCode};
 
 use std::convert::{TryFrom, TryInto};
-use std::time::Duration;
 
 #[derive(Debug)]
 pub struct DelConnectionState {
@@ -29,7 +28,7 @@ impl DelConnectionState {
             selected_idx: ListState::default(),
         }
     }
-    pub fn resolve_tree_state(&mut self, graph: &JackGraph, conf: &LockConfig) {
+    pub fn resolve_tree_state(&mut self, graph: &PortGraph, conf: &LockConfig) {
         let cur_idx = match self.selected_idx.selected() {
             Some(n) => n,
             None => {
@@ -45,7 +44,7 @@ impl DelConnectionState {
     }
     pub fn into_selection<'a>(
         self,
-        graph: &'a JackGraph,
+        graph: &'a PortGraph,
         locks: &LockConfig,
     ) -> (PortData, Option<&'a PortData>) {
         let idx = match self.selected_idx.selected() {
@@ -57,18 +56,11 @@ impl DelConnectionState {
         let con = connected_ports(&self.port, graph, locks).nth(idx);
         (self.port, con)
     }
-    pub fn handle_pending_event(
-        &mut self,
-        timeout: Option<Duration>,
-    ) -> Result<Option<UiAction>, crate::Error> {
-        if !event::poll(timeout.unwrap_or_else(|| Duration::from_micros(0)))? {
-            return Ok(None);
-        }
-        let raw = event::read()?;
+    pub fn handle_event(&mut self, raw: event::Event) -> Option<UiAction> {
         let parsed = match raw.try_into() {
             Ok(evt) => evt,
             Err(()) => {
-                return Ok(None);
+                return None;
             }
         };
         match parsed {
@@ -79,7 +71,7 @@ impl DelConnectionState {
                     None => Some(0),
                 };
                 self.selected_idx.select(nxt);
-                Ok(Some(UiAction::Redraw))
+                Some(UiAction::Redraw)
             }
             DelConnectionEvent::MoveDown => {
                 let cur = self.selected_idx.selected();
@@ -88,24 +80,24 @@ impl DelConnectionState {
                     None => Some(0),
                 };
                 self.selected_idx.select(nxt);
-                Ok(Some(UiAction::Redraw))
+                Some(UiAction::Redraw)
             }
             DelConnectionEvent::Cancel => {
                 self.selected_idx.select(None);
-                Ok(Some(UiAction::Close))
+                Some(UiAction::Close)
             }
-            DelConnectionEvent::Select => Ok(Some(UiAction::Close)),
+            DelConnectionEvent::Select => Some(UiAction::Close),
         }
     }
 }
 
 pub struct DelConnectionWidget<'a> {
-    graph: &'a JackGraph,
+    graph: &'a PortGraph,
     conf: &'a LockConfig,
 }
 
 impl<'a> DelConnectionWidget<'a> {
-    pub fn new(graph: &'a JackGraph, conf: &'a LockConfig) -> Self {
+    pub fn new(graph: &'a PortGraph, conf: &'a LockConfig) -> Self {
         Self { graph, conf }
     }
 }
@@ -130,7 +122,7 @@ impl<'a> StatefulWidget for DelConnectionWidget<'a> {
     type State = DelConnectionState;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
         state.resolve_tree_state(self.graph, self.conf);
-        let graph: &JackGraph = self.graph;
+        let graph: &PortGraph = self.graph;
         let conf: &LockConfig = self.conf;
         let selected: &mut ListState = &mut state.selected_idx;
         let port: &PortData = &state.port;
@@ -151,14 +143,12 @@ impl<'a> StatefulWidget for DelConnectionWidget<'a> {
 
 fn connected_ports<'a, 'b: 'a>(
     port: &'a PortData,
-    graph: &'b JackGraph,
+    graph: &'b PortGraph,
     conf: &'a LockConfig,
 ) -> impl Iterator<Item = &'b PortData> + 'a {
-    graph.port_connections(&port.name).filter(move |other| {
-        !conf
-            .connection_status(&port.name, &other.name)
-            .should_force()
-    })
+    graph
+        .port_connections(&port.name)
+        .filter(move |other| !conf.connection_status(port, other).should_force())
 }
 
 fn make_block<'a>() -> Block<'a> {
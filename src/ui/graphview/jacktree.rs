@@ -1,11 +1,13 @@
 use tui::buffer::Buffer;
 use tui::layout::{Constraint, Direction, Layout, Rect};
 use tui::style::{Modifier, Style};
-use tui::text::{Span, Text};
+use tui::text::{Span, Spans, Text};
 use tui::widgets::{Block, BorderType, Borders, List, ListItem, ListState, StatefulWidget};
 
-use crate::graph::JackGraph;
-use crate::model::ItemKey;
+use crate::graph::PortGraph;
+use crate::model::{ItemKey, PortCategory, PortFullname};
+
+use std::collections::HashSet;
 
 #[derive(Debug, Default)]
 pub struct JackTreeState {
@@ -28,12 +30,34 @@ impl JackTreeState {
     }
 }
 pub struct JackTree<'a> {
-    graph: &'a JackGraph,
+    graph: &'a PortGraph,
+    pretty_names: bool,
+    midi_activity: Option<HashSet<PortFullname>>,
 }
 
 impl<'a> JackTree<'a> {
-    pub fn new(graph: &'a JackGraph) -> Self {
-        Self { graph }
+    pub fn new(graph: &'a PortGraph) -> Self {
+        Self {
+            graph,
+            pretty_names: false,
+            midi_activity: None,
+        }
+    }
+
+    /// Shows clients and ports by their metadata pretty names, where they have one.
+    pub fn with_pretty_names(self, pretty_names: bool) -> Self {
+        Self {
+            pretty_names,
+            ..self
+        }
+    }
+
+    /// Shows an activity LED beside each MIDI output, lit for the ports in `lit`.
+    pub fn with_midi_activity(self, lit: HashSet<PortFullname>) -> Self {
+        Self {
+            midi_activity: Some(lit),
+            ..self
+        }
     }
 }
 
@@ -41,10 +65,25 @@ impl<'a> StatefulWidget for JackTree<'a> {
     type State = JackTreeState;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
         let graph = self.graph;
+        let pretty_names = self.pretty_names;
         let selected = state.selected();
         let (client_list, longest_client, selected_client) = make_list(
             graph.all_clients(),
-            |a| a,
+            |a| {
+                let label = match graph.client_pretty_name(a) {
+                    Some(pretty) if pretty_names => pretty,
+                    _ => a,
+                };
+                if graph.client_is_physical(a) {
+                    let hw_style = Style::default().add_modifier(Modifier::DIM);
+                    Text::from(Spans::from(vec![
+                        Span::raw(label),
+                        Span::styled(" [hw]", hw_style),
+                    ]))
+                } else {
+                    Text::raw(label)
+                }
+            },
             selected.client_idx(),
             "Clients",
             false,
@@ -54,9 +93,31 @@ impl<'a> StatefulWidget for JackTree<'a> {
             .into_iter()
             .flatten();
 
+        let midi_activity = self.midi_activity.as_ref();
         let (port_list, longest_port, selected_port) = make_list(
             port_itr,
-            |data| data.name.port_shortname(),
+            |data| {
+                let label = match data.pretty_name.as_deref() {
+                    Some(pretty) if pretty_names => pretty,
+                    _ => data.name.port_shortname(),
+                };
+                let lit = match midi_activity {
+                    Some(lit)
+                        if data.category == PortCategory::Midi && data.direction.is_output() =>
+                    {
+                        lit.contains(&data.name)
+                    }
+                    _ => {
+                        return Text::raw(label);
+                    }
+                };
+                let led = if lit {
+                    Span::styled("● ", Style::default().add_modifier(Modifier::BOLD))
+                } else {
+                    Span::styled("○ ", Style::default().add_modifier(Modifier::DIM))
+                };
+                Text::from(Spans::from(vec![led, Span::raw(label)]))
+            },
             selected.port_idx(),
             "Ports",
             false,
@@ -69,7 +130,10 @@ impl<'a> StatefulWidget for JackTree<'a> {
 
         let (con_list, longest_con, _selected_con) = make_list(
             con_itr,
-            |data| data.name.as_ref(),
+            |data| match data.pretty_name.as_deref() {
+                Some(pretty) if pretty_names => pretty,
+                _ => data.name.as_ref(),
+            },
             selected.connection_idx(),
             "Connections",
             true,
@@ -0,0 +1,147 @@
+use crate::graph::{MeterLevel, PortGraph};
+use crate::model::{ItemDataRef, PortCategory, PortData, PortFullname};
+
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, BorderType, Borders, Paragraph, Widget};
+
+/// The quietest level, in dBFS, shown on a meter; anything below it shows as empty.
+const METER_FLOOR_DB: f32 = -60.0;
+
+/// Picks the ports to meter for the given selection: the selected port itself, or
+/// every output of the selected client.
+/// Only audio outputs can be metered, so anything else is skipped.
+pub fn meter_targets(graph: &PortGraph, selected: &ItemDataRef<'_>) -> Vec<PortFullname> {
+    let is_meterable =
+        |data: &PortData| data.category == PortCategory::Audio && data.direction.is_output();
+    match selected {
+        ItemDataRef::Root => Vec::new(),
+        ItemDataRef::Client(client) => graph
+            .client_ports(client)
+            .filter(|data| is_meterable(data))
+            .map(|data| data.name.clone())
+            .collect(),
+        ItemDataRef::Port(port) | ItemDataRef::Connection(port, _) => {
+            if is_meterable(port) {
+                vec![port.name.clone()]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Draws a bar gauge for each metered port.
+/// The solid part of each bar is the RMS level and the shaded part reaches up to the peak.
+pub struct MeterWidget<'a> {
+    graph: &'a PortGraph,
+    levels: &'a [(PortFullname, MeterLevel)],
+    pretty_names: bool,
+}
+
+impl<'a> MeterWidget<'a> {
+    pub fn new(graph: &'a PortGraph, levels: &'a [(PortFullname, MeterLevel)]) -> Self {
+        Self {
+            graph,
+            levels,
+            pretty_names: false,
+        }
+    }
+
+    /// Labels each meter with its port's metadata pretty name, where it has one.
+    pub fn with_pretty_names(self, pretty_names: bool) -> Self {
+        Self {
+            pretty_names,
+            ..self
+        }
+    }
+
+    fn label(&self, name: &'a PortFullname) -> &'a str {
+        let pretty = self
+            .graph
+            .port_by_name(name)
+            .and_then(|data| data.pretty_name.as_deref());
+        match pretty {
+            Some(pretty) if self.pretty_names => pretty,
+            _ => name.port_shortname(),
+        }
+    }
+}
+
+impl<'a> Widget for MeterWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title("Meters")
+            .borders(Borders::all())
+            .border_type(BorderType::Rounded);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let label_width = self
+            .levels
+            .iter()
+            .map(|(name, _)| Span::raw(self.label(name)).width())
+            .max()
+            .unwrap_or(0);
+        const VALUE_WIDTH: usize = " -60.0 dB".len();
+        let bar_width = usize::from(inner.width)
+            .saturating_sub(label_width + 1)
+            .saturating_sub(VALUE_WIDTH);
+
+        let lines: Vec<_> = self
+            .levels
+            .iter()
+            .map(|(name, level)| {
+                let label = format!("{:<width$} ", self.label(name), width = label_width);
+                let mut spans = vec![Span::raw(label)];
+                spans.extend(make_bar(*level, bar_width));
+                spans.push(make_value(*level));
+                Spans::from(spans)
+            })
+            .collect();
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+/// Gets how many of `width` cells a level of `db` should fill.
+fn bar_cells(db: f32, width: usize) -> usize {
+    let fraction = (db - METER_FLOOR_DB) / -METER_FLOOR_DB;
+    let fraction = if fraction.is_nan() {
+        0.0
+    } else {
+        fraction.clamp(0.0, 1.0)
+    };
+    (fraction * width as f32).round() as usize
+}
+
+fn make_bar<'a>(level: MeterLevel, width: usize) -> Vec<Span<'a>> {
+    let rms_cells = bar_cells(level.rms_db(), width);
+    let peak_cells = bar_cells(level.peak_db(), width).max(rms_cells);
+    vec![
+        Span::raw("█".repeat(rms_cells)),
+        Span::raw("▒".repeat(peak_cells - rms_cells)),
+        Span::styled(
+            "·".repeat(width - peak_cells),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+    ]
+}
+
+fn make_value<'a>(level: MeterLevel) -> Span<'a> {
+    let peak_db = level.peak_db();
+    if peak_db < METER_FLOOR_DB {
+        return Span::raw("     -inf");
+    }
+    let text = format!(" {:>5.1} dB", peak_db);
+    if peak_db >= 0.0 {
+        // The signal is clipping.
+        Span::styled(
+            text,
+            Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        )
+    } else {
+        Span::raw(text)
+    }
+}
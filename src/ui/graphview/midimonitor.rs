@@ -0,0 +1,208 @@
+use crate::model::{MidiEvent, PortData, PortFullname};
+use crate::ui::UiAction;
+
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::text::Span;
+use tui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, ListState, StatefulWidget, Widget,
+};
+
+use crossterm::event::{self, KeyCode};
+
+use std::collections::VecDeque;
+use std::convert::{TryFrom, TryInto};
+
+/// The most events the monitor will remember; older ones scroll off the top.
+const MAX_EVENTS: usize = 512;
+
+/// The width of a single decoded event line.
+const LINE_WIDTH: u16 = 48;
+
+#[derive(Debug)]
+pub struct MidiMonitorState {
+    port: PortFullname,
+    events: VecDeque<MidiEvent>,
+    /// The time of the first event received, which the others are shown relative to.
+    start_us: Option<u64>,
+    /// The selected line, if the user has scrolled back; otherwise the list follows
+    /// the newest event.
+    selected_idx: ListState,
+}
+
+impl MidiMonitorState {
+    pub fn new(port: &PortData) -> Self {
+        Self {
+            port: port.name.clone(),
+            events: VecDeque::new(),
+            start_us: None,
+            selected_idx: ListState::default(),
+        }
+    }
+    /// Gets the name of the port being monitored.
+    pub fn port(&self) -> &PortFullname {
+        &self.port
+    }
+    pub fn push(&mut self, evt: MidiEvent) {
+        self.start_us.get_or_insert(evt.time_us);
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+            let selected = self.selected_idx.selected();
+            self.selected_idx
+                .select(selected.map(|n| n.saturating_sub(1)));
+        }
+        self.events.push_back(evt);
+    }
+    pub fn handle_event(&mut self, raw: event::Event) -> Option<UiAction> {
+        let parsed = match raw.try_into() {
+            Ok(evt) => evt,
+            Err(()) => {
+                return None;
+            }
+        };
+        match parsed {
+            MidiMonitorEvent::MoveUp => {
+                let last = self.events.len().checked_sub(1)?;
+                let cur = self.selected_idx.selected().unwrap_or(last);
+                self.selected_idx.select(Some(cur.saturating_sub(1)));
+                Some(UiAction::Redraw)
+            }
+            MidiMonitorEvent::MoveDown => {
+                let last = self.events.len().checked_sub(1)?;
+                let nxt = match self.selected_idx.selected() {
+                    Some(n) if n + 1 < last => Some(n + 1),
+                    // Scrolling past the end goes back to following new events.
+                    _ => None,
+                };
+                self.selected_idx.select(nxt);
+                Some(UiAction::Redraw)
+            }
+            MidiMonitorEvent::Clear => {
+                self.events.clear();
+                self.start_us = None;
+                self.selected_idx.select(None);
+                Some(UiAction::Redraw)
+            }
+            MidiMonitorEvent::Close => Some(UiAction::Close),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MidiMonitorWidget {}
+
+impl MidiMonitorWidget {
+    pub fn new() -> Self {
+        Self {}
+    }
+    pub fn dims(&self, state: &MidiMonitorState) -> (u16, u16) {
+        let title_len = make_title(state).len() as u16;
+        let w = LINE_WIDTH.max(title_len) + 4; // Left border + left padding + right border + right padding
+        let h = (MAX_EVENTS as u16) + 2; // As tall as will fit; the list scrolls anyway
+        (w, h)
+    }
+}
+
+impl StatefulWidget for MidiMonitorWidget {
+    type State = MidiMonitorState;
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let start_us = state.start_us.unwrap_or(0);
+        let list_items: Vec<_> = if state.events.is_empty() {
+            vec![ListItem::new(Span::styled(
+                "Waiting for MIDI...",
+                Style::default().add_modifier(Modifier::DIM),
+            ))]
+        } else {
+            state
+                .events
+                .iter()
+                .map(|evt| ListItem::new(format_event(evt, start_us)))
+                .collect()
+        };
+        // Without a selection, follow the newest event.
+        let mut list_state = ListState::default();
+        let following = state.selected_idx.selected().is_none();
+        list_state.select(
+            state
+                .selected_idx
+                .selected()
+                .or_else(|| state.events.len().checked_sub(1)),
+        );
+        let highlight_style = if following {
+            Style::default()
+        } else {
+            Style::default().add_modifier(Modifier::REVERSED)
+        };
+        let list = List::new(list_items)
+            .block(make_block(make_title(state)))
+            .highlight_style(highlight_style);
+
+        Widget::render(Clear {}, area, buf);
+        StatefulWidget::render(list, area, buf, &mut list_state);
+    }
+}
+
+fn format_event(evt: &MidiEvent, start_us: u64) -> String {
+    let secs = evt.time_us.saturating_sub(start_us) as f64 / 1_000_000.0;
+    let channel = match evt.message.channel() {
+        Some(ch) => format!("ch{:<2}", ch),
+        None => "    ".to_owned(),
+    };
+    format!("{:>10.3}s  {}  {}", secs, channel, evt.message)
+}
+
+fn make_title(state: &MidiMonitorState) -> String {
+    format!("MIDI from {}", state.port.as_ref())
+}
+
+fn make_block<'a>(title: String) -> Block<'a> {
+    let title_style = Style::default()
+        .add_modifier(Modifier::BOLD)
+        .add_modifier(Modifier::UNDERLINED);
+    let title = Span::styled(title, title_style);
+    Block::default()
+        .borders(Borders::all())
+        .border_type(BorderType::Double)
+        .border_style(Style::default().add_modifier(Modifier::BOLD))
+        .title(title)
+}
+
+enum MidiMonitorEvent {
+    MoveUp,
+    MoveDown,
+    Clear,
+    Close,
+}
+
+impl TryFrom<event::KeyEvent> for MidiMonitorEvent {
+    type Error = ();
+    fn try_from(value: event::KeyEvent) -> Result<Self, Self::Error> {
+        const UP_CODES: &[KeyCode] = &[KeyCode::Up, KeyCode::Char('w'), KeyCode::Char('k')];
+        const DOWN_CODES: &[KeyCode] = &[KeyCode::Down, KeyCode::Char('s'), KeyCode::Char('j')];
+
+        let code = value.code;
+
+        if UP_CODES.contains(&code) {
+            Ok(MidiMonitorEvent::MoveUp)
+        } else if DOWN_CODES.contains(&code) {
+            Ok(MidiMonitorEvent::MoveDown)
+        } else if code == KeyCode::Char('c') {
+            Ok(MidiMonitorEvent::Clear)
+        } else if code == KeyCode::Esc || code == KeyCode::Backspace || code == KeyCode::Enter {
+            Ok(MidiMonitorEvent::Close)
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl TryFrom<event::Event> for MidiMonitorEvent {
+    type Error = ();
+    fn try_from(value: event::Event) -> Result<Self, Self::Error> {
+        match value {
+            event::Event::Key(keyevent) => keyevent.try_into(),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::graph::{TransportInfo, TransportState};
+
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, BorderType, Borders, Paragraph, Widget};
+
+/// The tempo used as timebase master until the user picks another.
+pub const DEFAULT_BPM: f64 = 120.0;
+
+/// The slowest and fastest tempos the user can pick.
+pub const BPM_RANGE: (f64, f64) = (20.0, 300.0);
+
+/// The height of the transport panel, including its borders.
+pub const TRANSPORT_HEIGHT: u16 = 3;
+
+/// Shows the state and position of the transport on a single line.
+pub struct TransportWidget<'a> {
+    info: &'a TransportInfo,
+    master_bpm: Option<f64>,
+}
+
+impl<'a> TransportWidget<'a> {
+    pub fn new(info: &'a TransportInfo) -> Self {
+        Self {
+            info,
+            master_bpm: None,
+        }
+    }
+
+    /// Marks trej as the timebase master, counting at `bpm` beats per minute.
+    pub fn with_master_bpm(self, bpm: Option<f64>) -> Self {
+        Self {
+            master_bpm: bpm,
+            ..self
+        }
+    }
+}
+
+impl<'a> Widget for TransportWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title("Transport")
+            .borders(Borders::all())
+            .border_type(BorderType::Rounded);
+        let info = self.info;
+        let state = match info.state {
+            TransportState::Stopped => "■ Stopped ",
+            TransportState::Starting => "▷ Starting",
+            TransportState::Rolling => "▶ Rolling ",
+        };
+        let label_style = Style::default().add_modifier(Modifier::UNDERLINED);
+        let mut spans = vec![
+            Span::styled(state, Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw("  "),
+            Span::styled("Frame", label_style),
+            Span::raw(format!(": {}  ", info.frame)),
+            Span::styled("Time", label_style),
+            Span::raw(format!(": {}  ", format_time(info.seconds()))),
+        ];
+        match info.bbt {
+            Some(bbt) => {
+                spans.push(Span::styled("BBT", label_style));
+                spans.push(Span::raw(format!(
+                    ": {}|{}|{:04}  ",
+                    bbt.bar, bbt.beat, bbt.tick
+                )));
+                spans.push(Span::styled("Tempo", label_style));
+                spans.push(Span::raw(format!(
+                    ": {:.2} BPM {}/{}",
+                    bbt.bpm, bbt.beats_per_bar, bbt.beat_type
+                )));
+            }
+            None => {
+                spans.push(Span::styled(
+                    "No timebase master",
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+            }
+        }
+        if let Some(bpm) = self.master_bpm {
+            spans.push(Span::styled(
+                format!("  [master @ {:.0} BPM]", bpm),
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+        }
+        Paragraph::new(Spans::from(spans))
+            .block(block)
+            .render(area, buf);
+    }
+}
+
+/// Formats a time as minutes, seconds and milliseconds.
+fn format_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}.{:03}",
+        millis / 60_000,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
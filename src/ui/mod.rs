@@ -8,4 +8,6 @@ pub use screenwrapper::*;
 pub enum UiAction {
     Redraw,
     Close,
+    /// The config has changed, so it needs to be enforced again before redrawing.
+    ApplyConfig,
 }